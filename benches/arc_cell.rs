@@ -0,0 +1,62 @@
+//! Compares the swapping cost of the `FutureArcCell` and the `FutureOnceCell` for large values.
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use future_local_storage::{FutureArcCell, FutureOnceCell};
+use futures_util::task::noop_waker;
+
+type Large = [u64; 512];
+
+static ONCE_CELL: FutureOnceCell<Large> = FutureOnceCell::new();
+static ARC_CELL: FutureArcCell<Large> = FutureArcCell::new();
+
+const POLLS: usize = 10;
+
+/// Returns `Poll::Pending` a few times, so the value is swapped in and out on each poll.
+async fn yield_many() {
+    for _ in 0..POLLS {
+        let mut yielded = false;
+        std::future::poll_fn(|_| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+}
+
+/// Polls the future until it is completed, without any executor overhead.
+fn poll_to_end<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+fn bench_scope(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scope_large");
+    group.bench_function("FutureOnceCell", |b| {
+        b.iter(|| poll_to_end(ONCE_CELL.scope([1; 512], yield_many())));
+    });
+    let value = Arc::new([1; 512]);
+    group.bench_function("FutureArcCell", |b| {
+        b.iter(|| poll_to_end(ARC_CELL.scope(value.clone(), yield_many())));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scope);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+//! Compares the cost of collecting items with and without the pre-allocated capacity.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use future_local_storage::FutureCollector;
+use futures_util::FutureExt;
+
+static ITEMS: FutureCollector<usize> = FutureCollector::new();
+
+const PUSHES: usize = 1000;
+
+fn push_items() {
+    for i in 0..PUSHES {
+        ITEMS.push(i);
+    }
+}
+
+fn bench_collect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collect");
+    group.bench_function("scope", |b| {
+        b.iter(|| ITEMS.scope(async { push_items() }).now_or_never());
+    });
+    group.bench_function("scope_with_capacity", |b| {
+        b.iter(|| {
+            ITEMS
+                .scope_with_capacity(PUSHES, async { push_items() })
+                .now_or_never()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_collect);
+criterion_main!(benches);
@@ -0,0 +1,190 @@
+//! Compares the `FutureOnceCell` with the `tokio::task_local!` and the `tracing` span extensions
+//! for the equivalent read-heavy and suspend-heavy workloads.
+
+use std::{
+    cell::OnceCell,
+    future::Future,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use future_local_storage::{FutureOnceCell, LocalState, ThreadLocalProvider};
+use futures_util::task::noop_waker_ref;
+use tracing::{Instrument, Span};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+static ONCE_CELL: FutureOnceCell<u64> = FutureOnceCell::new();
+static STD_CELL: FutureOnceCell<u64> = FutureOnceCell::with_provider::<StdProvider>();
+
+/// Keeps the state in the standard library thread local storage.
+struct StdProvider;
+
+impl ThreadLocalProvider<LocalState<u64>> for StdProvider {
+    fn get_or_init(init: fn() -> LocalState<u64>) -> &'static LocalState<u64> {
+        thread_local! {
+            static STATE: OnceCell<&'static LocalState<u64>> = const { OnceCell::new() };
+        }
+        STATE.with(|state| *state.get_or_init(|| Box::leak(Box::new(init()))))
+    }
+}
+
+tokio::task_local! {
+    static TASK_LOCAL: u64;
+}
+
+/// The value stored in the span extensions.
+struct RequestId(u64);
+
+const READS: u64 = 100;
+const SUSPENDS: usize = 100;
+
+/// A future which is suspended the given number of times, and reads the context on each poll.
+struct Suspend<R> {
+    remaining: usize,
+    read: R,
+}
+
+impl<R: Fn() -> u64 + Unpin> Future for Suspend<R> {
+    type Output = u64;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        let value = black_box((self.read)());
+        if self.remaining == 0 {
+            return Poll::Ready(value);
+        }
+        self.remaining -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Polls the future on the current thread until it completes.
+fn run<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(noop_waker_ref());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+fn read_span() -> u64 {
+    tracing::dispatcher::get_default(|dispatch| {
+        let registry = dispatch.downcast_ref::<Registry>().unwrap();
+        let id = Span::current().id().unwrap();
+        let span = registry.span(&id).unwrap();
+        let extensions = span.extensions();
+        extensions.get::<RequestId>().unwrap().0
+    })
+}
+
+fn new_span() -> Span {
+    let span = tracing::info_span!("request");
+    tracing::dispatcher::get_default(|dispatch| {
+        let registry = dispatch.downcast_ref::<Registry>().unwrap();
+        let id = span.id().unwrap();
+        registry
+            .span(&id)
+            .unwrap()
+            .extensions_mut()
+            .insert(RequestId(1));
+    });
+    span
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    group.bench_function("FutureOnceCell", |b| {
+        b.iter(|| {
+            run(ONCE_CELL.scope(1, async {
+                (0..READS).map(|_| black_box(ONCE_CELL.get())).sum::<u64>()
+            }))
+        });
+    });
+    group.bench_function("FutureOnceCell with std provider", |b| {
+        b.iter(|| {
+            run(STD_CELL.scope(1, async {
+                (0..READS).map(|_| black_box(STD_CELL.get())).sum::<u64>()
+            }))
+        });
+    });
+    group.bench_function("tokio::task_local", |b| {
+        b.iter(|| {
+            run(TASK_LOCAL.scope(1, async {
+                (0..READS).map(|_| black_box(TASK_LOCAL.get())).sum::<u64>()
+            }))
+        });
+    });
+    group.bench_function("tracing span", |b| {
+        b.iter(|| {
+            run(
+                async { (0..READS).map(|_| black_box(read_span())).sum::<u64>() }
+                    .instrument(new_span()),
+            )
+        });
+    });
+    group.finish();
+}
+
+fn bench_suspend(c: &mut Criterion) {
+    let mut group = c.benchmark_group("suspend");
+    group.bench_function("FutureOnceCell", |b| {
+        b.iter(|| {
+            run(ONCE_CELL.scope(
+                1,
+                Suspend {
+                    remaining: SUSPENDS,
+                    read: || ONCE_CELL.get(),
+                },
+            ))
+        });
+    });
+    group.bench_function("FutureOnceCell with std provider", |b| {
+        b.iter(|| {
+            run(STD_CELL.scope(
+                1,
+                Suspend {
+                    remaining: SUSPENDS,
+                    read: || STD_CELL.get(),
+                },
+            ))
+        });
+    });
+    group.bench_function("tokio::task_local", |b| {
+        b.iter(|| {
+            run(TASK_LOCAL.scope(
+                1,
+                Suspend {
+                    remaining: SUSPENDS,
+                    read: || TASK_LOCAL.get(),
+                },
+            ))
+        });
+    });
+    group.bench_function("tracing span", |b| {
+        b.iter(|| {
+            run(Suspend {
+                remaining: SUSPENDS,
+                read: read_span,
+            }
+            .instrument(new_span()))
+        });
+    });
+    group.finish();
+}
+
+fn setup() {
+    tracing::subscriber::set_global_default(Registry::default())
+        .expect("the global subscriber must be set once");
+}
+
+fn benches(c: &mut Criterion) {
+    setup();
+    bench_read(c);
+    bench_suspend(c);
+}
+
+criterion_group!(context_comparison, benches);
+criterion_main!(context_comparison);
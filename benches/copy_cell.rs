@@ -0,0 +1,47 @@
+//! Compares the access cost of the `FutureCopyCell` and the `FutureOnceCell` for small values.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use future_local_storage::{FutureCopyCell, FutureOnceCell};
+use futures_util::FutureExt;
+
+static ONCE_CELL: FutureOnceCell<u64> = FutureOnceCell::new();
+static COPY_CELL: FutureCopyCell<u64> = FutureCopyCell::new();
+
+const READS: u64 = 100;
+
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+    group.bench_function("FutureOnceCell", |b| {
+        b.iter(|| {
+            ONCE_CELL
+                .scope(1, async {
+                    (0..READS).map(|_| ONCE_CELL.get()).sum::<u64>()
+                })
+                .now_or_never()
+        });
+    });
+    group.bench_function("FutureCopyCell", |b| {
+        b.iter(|| {
+            COPY_CELL
+                .scope(1, async {
+                    (0..READS).map(|_| COPY_CELL.get()).sum::<u64>()
+                })
+                .now_or_never()
+        });
+    });
+    group.finish();
+}
+
+fn bench_scope(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scope");
+    group.bench_function("FutureOnceCell", |b| {
+        b.iter(|| ONCE_CELL.scope(1, async {}).now_or_never());
+    });
+    group.bench_function("FutureCopyCell", |b| {
+        b.iter(|| COPY_CELL.scope(1, async {}).now_or_never());
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_get, bench_scope);
+criterion_main!(benches);
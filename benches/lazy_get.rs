@@ -0,0 +1,39 @@
+//! Measures the repeated reads of a `Copy` value of the `FutureScopedLazy`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use future_local_storage::{FutureCopyCell, FutureScopedLazy};
+use futures_util::FutureExt;
+
+static LAZY: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 1);
+static COPY_CELL: FutureCopyCell<u64> = FutureCopyCell::new();
+
+const READS: u64 = 100;
+
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lazy_get");
+    group.bench_function("FutureScopedLazy::with", |b| {
+        b.iter(|| {
+            LAZY.scope(async { (0..READS).map(|_| LAZY.with(|x| *x)).sum::<u64>() })
+                .now_or_never()
+        });
+    });
+    group.bench_function("FutureScopedLazy::get", |b| {
+        b.iter(|| {
+            LAZY.scope(async { (0..READS).map(|_| LAZY.get()).sum::<u64>() })
+                .now_or_never()
+        });
+    });
+    group.bench_function("FutureCopyCell::get", |b| {
+        b.iter(|| {
+            COPY_CELL
+                .scope(1, async {
+                    (0..READS).map(|_| COPY_CELL.get()).sum::<u64>()
+                })
+                .now_or_never()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_get);
+criterion_main!(benches);
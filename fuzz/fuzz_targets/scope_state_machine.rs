@@ -0,0 +1,110 @@
+//! Drives scoped futures through random poll, drop and complete sequences across several
+//! threads and checks the future local storage invariants:
+//!
+//! - the future local value is never set between polls;
+//! - each scoped future sees only its own value;
+//! - the value is returned exactly once, with all changes made by the inner future.
+
+#![no_main]
+
+use std::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use future_local_storage::FutureOnceCell;
+use libfuzzer_sys::fuzz_target;
+
+/// Maximum number of the simultaneously running futures.
+const FUTURES: usize = 4;
+
+static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Yields control back to the scheduler once.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+type ScopedTask = Pin<Box<dyn Future<Output = (Cell<u64>, u64)> + Send>>;
+
+/// Creates a scoped future which checks its own value and counts the number of polls.
+fn scoped_task(id: u64, steps: u8) -> ScopedTask {
+    VALUE.scope_boxed(Cell::new(id << 32), async move {
+        for _ in 0..steps {
+            VALUE.with(|value| {
+                assert_eq!(value.get() >> 32, id, "cross-future contamination");
+                value.set(value.get() + 1);
+            });
+            YieldNow(false).await;
+        }
+        id
+    })
+}
+
+fn is_value_set() -> bool {
+    VALUE.raw_local_key().borrow().is_some()
+}
+
+/// Polls the task and checks that the value has been swapped out after the poll.
+fn poll_task(task: &mut ScopedTask) -> Poll<(Cell<u64>, u64)> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let poll = task.as_mut().poll(&mut Context::from_waker(&waker));
+    assert!(!is_value_set(), "value leaked into the thread local storage");
+    poll
+}
+
+fuzz_target!(|ops: &[u8]| {
+    let mut tasks: Vec<Option<(u64, u8, ScopedTask)>> = (0..FUTURES).map(|_| None).collect();
+    let mut next_id = 0;
+
+    for op in ops {
+        let slot = &mut tasks[usize::from(op >> 4) % FUTURES];
+        let Some((id, steps, task)) = slot else {
+            // Spawn a new task in the empty slot.
+            let steps = op & 0x7;
+            *slot = Some((next_id, steps, scoped_task(next_id, steps)));
+            next_id += 1;
+            continue;
+        };
+
+        let poll = match op & 0x3 {
+            // Poll the task on the current thread.
+            0 | 1 => poll_task(task),
+            // Poll the task on another thread to simulate work stealing.
+            2 => std::thread::scope(|s| s.spawn(|| poll_task(task)).join().unwrap()),
+            // Cancel the task.
+            _ => {
+                *slot = None;
+                continue;
+            }
+        };
+
+        if let Poll::Ready((value, output)) = poll {
+            assert_eq!(output, *id);
+            assert_eq!(value.get(), (*id << 32) + u64::from(*steps));
+            *slot = None;
+        }
+    }
+    assert!(!is_value_set());
+});
@@ -0,0 +1,136 @@
+//! Implementation of the `FutureLocal` derive macro.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Field, Fields, Type};
+
+/// Names of the associated functions generated for the struct itself.
+const RESERVED_NAMES: &[&str] = &["future_local", "scope", "with"];
+
+/// Primitive types which accessors return a copy of the field value.
+const COPY_TYPES: &[&str] = &[
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+    "u64", "u128", "usize",
+];
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "`FutureLocal` can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "`FutureLocal` can only be derived for structs with named fields",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "`FutureLocal` cannot be derived for generic structs",
+        ));
+    }
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let accessors = fields
+        .named
+        .iter()
+        .map(field_accessor)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #name {
+            /// Returns the future local cell which stores the value of this type.
+            #[must_use]
+            #vis fn future_local() -> &'static ::future_local_storage::FutureOnceCell<#name> {
+                static CELL: ::future_local_storage::FutureOnceCell<#name> =
+                    ::future_local_storage::FutureOnceCell::new();
+                &CELL
+            }
+
+            /// Sets this value as the future local value for the given future.
+            #vis fn scope<F>(self, future: F) -> ::future_local_storage::ScopedFutureWithValue<#name, F>
+            where
+                F: ::core::future::Future,
+            {
+                Self::future_local().scope(self, future)
+            }
+
+            /// Acquires a reference to the current future local value.
+            #vis fn with<R>(f: impl ::core::ops::FnOnce(&#name) -> R) -> R {
+                Self::future_local().with(f)
+            }
+
+            #(#accessors)*
+        }
+    })
+}
+
+fn field_accessor(field: &Field) -> syn::Result<TokenStream> {
+    let ident = field
+        .ident
+        .as_ref()
+        .expect("named fields always have an ident");
+    if RESERVED_NAMES.iter().any(|name| ident == name) {
+        return Err(syn::Error::new_spanned(
+            ident,
+            format!("field name `{ident}` conflicts with the generated `FutureLocal` function"),
+        ));
+    }
+
+    let vis = &field.vis;
+    let ty = &field.ty;
+    if is_copy_field(field)? {
+        let doc =
+            format!("Returns a copy of the `{ident}` field of the current future local value.");
+        Ok(quote! {
+            #[doc = #doc]
+            #[must_use]
+            #vis fn #ident() -> #ty {
+                Self::with(|this| this.#ident)
+            }
+        })
+    } else {
+        let doc = format!(
+            "Acquires a reference to the `{ident}` field of the current future local value."
+        );
+        Ok(quote! {
+            #[doc = #doc]
+            #vis fn #ident<R>(f: impl ::core::ops::FnOnce(&#ty) -> R) -> R {
+                Self::with(|this| f(&this.#ident))
+            }
+        })
+    }
+}
+
+/// Returns `true` if the field is a primitive type, or it is marked by the
+/// `#[future_local(copy)]` attribute.
+fn is_copy_field(field: &Field) -> syn::Result<bool> {
+    let mut is_copy = match &field.ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .get_ident()
+            .is_some_and(|ident| COPY_TYPES.iter().any(|ty| ident == ty)),
+        _ => false,
+    };
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("future_local"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("copy") {
+                is_copy = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported `future_local` attribute, expected `copy`"))
+            }
+        })?;
+    }
+    Ok(is_copy)
+}
@@ -0,0 +1,96 @@
+//! Procedural macros for the [`future-local-storage`](https://docs.rs/future-local-storage)
+//! crate.
+//!
+//! Don't use this crate directly, use the macros reexported from the `future-local-storage`
+//! crate instead.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn};
+
+mod derive;
+mod scope;
+
+/// Derives a future local storage for a struct which aggregates several context fields.
+///
+/// The derive macro generates a single [`FutureOnceCell`] which stores the whole struct, and
+/// the following associated functions:
+///
+/// - `future_local()` returns a reference to the generated cell;
+/// - `scope(self, future)` sets this value as the future local value for the future;
+/// - `with(f)` acquires a reference to the current value;
+/// - a typed accessor for each field with the same name as the field. It returns a copy of the
+///   field value for the primitive types and for the fields marked with the
+///   `#[future_local(copy)]` attribute, and calls the given closure with a reference to the
+///   field value otherwise.
+///
+/// All accessors panic if they are called outside of the scope.
+///
+/// ```rust
+/// use future_local_storage::FutureLocal;
+///
+/// #[derive(Debug, FutureLocal)]
+/// struct RequestContext {
+///     id: u64,
+///     tenant: String,
+/// }
+///
+/// async fn handle_request() -> String {
+///     RequestContext::tenant(|tenant| format!("{tenant}:{}", RequestContext::id()))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let context = RequestContext {
+///         id: 42,
+///         tenant: "acme".to_owned(),
+///     };
+///     let (_, output) = context.scope(handle_request()).await;
+///     assert_eq!(output, "acme:42");
+/// }
+/// ```
+///
+/// [`FutureOnceCell`]: https://docs.rs/future-local-storage/latest/future_local_storage/struct.FutureOnceCell.html
+#[proc_macro_derive(FutureLocal, attributes(future_local))]
+pub fn derive_future_local(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Sets the future local values for the body of an `async fn`.
+///
+/// The attribute takes a comma separated list of the `CELL = value` pairs and wraps the function
+/// body into the scoped future of each cell, so the function returns the output of its body only.
+/// The values are evaluated before the body starts, and they may refer to the function arguments,
+/// including `self`.
+///
+/// ```rust
+/// use future_local_storage::{future_local, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[future_local(REQUEST_ID = request_id)]
+/// async fn handle_request(request_id: u64) -> String {
+///     format!("handling request {}", REQUEST_ID.get())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     assert_eq!(handle_request(42).await, "handling request 42");
+/// }
+/// ```
+///
+/// The attribute has the same name as the field attribute of the [`FutureLocal`] derive macro,
+/// so in the modules which derive it, use the attribute by its full path, like
+/// `#[future_local_storage::future_local(CELL = value)]`.
+///
+/// [`FutureLocal`]: https://docs.rs/future-local-storage/latest/future_local_storage/derive.FutureLocal.html
+#[proc_macro_attribute]
+pub fn future_local(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as scope::ScopeArgs);
+    let item = parse_macro_input!(item as ItemFn);
+    scope::expand(&args, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
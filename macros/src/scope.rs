@@ -0,0 +1,73 @@
+//! Implementation of the `future_local` attribute macro.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Expr, ItemFn, Path, Token,
+};
+
+/// A single `CELL = value` argument of the attribute.
+struct ScopeArg {
+    cell: Path,
+    value: Expr,
+}
+
+impl Parse for ScopeArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let cell = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+        Ok(Self { cell, value })
+    }
+}
+
+/// The list of the `CELL = value` arguments of the attribute.
+pub struct ScopeArgs(Punctuated<ScopeArg, Token![,]>);
+
+impl Parse for ScopeArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Punctuated::parse_terminated(input).map(Self)
+    }
+}
+
+pub fn expand(args: &ScopeArgs, mut item: ItemFn) -> syn::Result<TokenStream> {
+    if item.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            item.sig.fn_token,
+            "the `future_local` attribute can only be applied to `async fn`",
+        ));
+    }
+    if args.0.is_empty() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "the `future_local` attribute expects at least one `CELL = value` argument",
+        ));
+    }
+
+    // The values are evaluated before the body captures the function arguments, so they may
+    // refer to the arguments.
+    let bindings = args.0.iter().enumerate().map(|(i, arg)| {
+        let binding = format_ident!("__future_local_value_{i}");
+        let value = &arg.value;
+        quote! { let #binding = #value; }
+    });
+    let block = &item.block;
+    let future = args.0.iter().enumerate().rev().fold(
+        quote! { async move #block },
+        |future, (i, arg)| {
+            let binding = format_ident!("__future_local_value_{i}");
+            let cell = &arg.cell;
+            quote! {
+                ::future_local_storage::FutureLocalStorage::with_scope_out(#future, &#cell, #binding)
+            }
+        },
+    );
+
+    item.block = syn::parse_quote! {{
+        #(#bindings)*
+        #future.await
+    }};
+    Ok(quote! { #item })
+}
@@ -0,0 +1,191 @@
+//! Future local collector of the heterogeneous items, which are recovered by their types.
+
+use std::{any::Any, fmt::Debug, future::Future};
+
+use crate::{future::ScopedFutureWithValue, imp::FutureLocalKey, FutureOnceCell};
+
+/// A list of the type-erased items collected by the [`FutureAnyCollector`] scope.
+///
+/// The items keep the order of the pushes, and are recovered by their types using the
+/// [`AnyCollection::drain_as`] method.
+#[derive(Default)]
+pub struct AnyCollection {
+    items: Vec<Box<dyn Any + Send>>,
+}
+
+impl AnyCollection {
+    /// Creates an empty collection.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes an item of any type to the collection.
+    pub fn push<T: Send + 'static>(&mut self, item: T) {
+        self.items.push(Box::new(item));
+    }
+
+    /// Removes all items of the given type from the collection, and returns them in the order of
+    /// the pushes.
+    ///
+    /// The items of the other types are kept in the collection.
+    pub fn drain_as<T: Send + 'static>(&mut self) -> impl Iterator<Item = T> {
+        let (matching, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| item.is::<T>());
+        self.items = rest;
+        matching
+            .into_iter()
+            .filter_map(|item| item.downcast().ok())
+            .map(|item| *item)
+    }
+
+    /// Returns the number of items in the collection.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the collection contains no items.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl Debug for AnyCollection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnyCollection")
+            .field("len", &self.items.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A future local collector of the items of different types.
+///
+/// It's useful for the extensible middleware stacks, where each plugin reports its own context
+/// type, but all of them are collected uniformly in a single scope. The scoped future returns
+/// the [`AnyCollection`] along with the future output, and the items are recovered by their
+/// types.
+///
+/// ```rust
+/// use future_local_storage::FutureAnyCollector;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct AuthEvent(&'static str);
+///
+/// #[derive(Debug, PartialEq)]
+/// struct CacheHit(u64);
+///
+/// static PLUGINS: FutureAnyCollector = FutureAnyCollector::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (mut collection, ()) = PLUGINS
+///         .scope(async {
+///             PLUGINS.push(AuthEvent("alice"));
+///             PLUGINS.push(CacheHit(1));
+///             PLUGINS.push(CacheHit(2));
+///         })
+///         .await;
+///
+///     let hits = collection.drain_as::<CacheHit>().collect::<Vec<_>>();
+///     assert_eq!(hits, vec![CacheHit(1), CacheHit(2)]);
+///     let events = collection.drain_as::<AuthEvent>().collect::<Vec<_>>();
+///     assert_eq!(events, vec![AuthEvent("alice")]);
+///     assert!(collection.is_empty());
+/// }
+/// ```
+pub struct FutureAnyCollector(FutureOnceCell<AnyCollection>);
+
+impl FutureAnyCollector {
+    /// Creates an empty future collector.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(FutureOnceCell::new())
+    }
+
+    /// Pushes the item of any type to the collection of the current scope.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the collector scope.
+    #[inline]
+    #[track_caller]
+    pub fn push<T: Send + 'static>(&'static self, item: T) {
+        self.0
+            .raw_local_key()
+            .borrow_mut()
+            .as_mut()
+            .expect("cannot push an item outside of the collector scope")
+            .push(item);
+    }
+
+    /// Creates a new collector scope for the future `F`.
+    ///
+    /// On completion the scoped future returns the collected items along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, future: F) -> ScopedFutureWithValue<AnyCollection, F>
+    where
+        F: Future,
+    {
+        self.0.scope(AnyCollection::new(), future)
+    }
+}
+
+impl Default for FutureAnyCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for FutureAnyCollector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureAnyCollector").finish()
+    }
+}
+
+impl AsRef<FutureLocalKey<AnyCollection>> for FutureAnyCollector {
+    fn as_ref(&self) -> &FutureLocalKey<AnyCollection> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_any_collector_drain_by_type() {
+        static ITEMS: FutureAnyCollector = FutureAnyCollector::new();
+
+        let (mut collection, ()) = ITEMS
+            .scope(async {
+                ITEMS.push(1_u64);
+                tokio::task::yield_now().await;
+                ITEMS.push("two");
+                ITEMS.push(3_u64);
+            })
+            .await;
+
+        assert_eq!(collection.len(), 3);
+        assert_eq!(collection.drain_as::<u64>().collect::<Vec<_>>(), vec![1, 3]);
+        // The items of a type which has not been pushed are not found.
+        assert_eq!(collection.drain_as::<u32>().count(), 0);
+        assert_eq!(
+            collection.drain_as::<&str>().collect::<Vec<_>>(),
+            vec!["two"]
+        );
+        assert!(collection.is_empty());
+    }
+
+    #[tokio::test]
+    #[should_panic = "cannot push an item outside of the collector scope"]
+    async fn test_future_any_collector_push_outside() {
+        static ITEMS: FutureAnyCollector = FutureAnyCollector::new();
+
+        ITEMS.push(());
+    }
+}
@@ -0,0 +1,159 @@
+//! Future local storage for large immutable values shared by reference counting.
+
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use crate::{
+    future::ScopedFutureWithValue, imp::FutureLocalKey, FutureLocalStorage, FutureOnceCell,
+    OnConflict,
+};
+
+/// A future local cell for large immutable values, which are shared between scopes by the
+/// [`Arc`].
+///
+/// Unlike the [`FutureOnceCell`], which moves the value in and out of the thread local storage
+/// on each poll of the scoped future, this cell moves the pointer only, so the swapping cost does
+/// not depend on the value size. For a 4 KiB value the scoped future polled ten times is about
+/// three times cheaper, see the `arc_cell` benchmark. The nested scopes created by the
+/// [`FutureArcCell::fork`] share the same value by cloning the [`Arc`], so re-scoping the same
+/// context in each layer of a deep call tree is cheap.
+///
+/// The value is immutable, use the [`FutureMutexLock`](crate::FutureMutexLock) if it should be
+/// shared and mutated at the same time.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use future_local_storage::FutureArcCell;
+///
+/// static TRACE_ID: FutureArcCell<String> = FutureArcCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let trace_id = Arc::new("trace-1".to_owned());
+///     let (_, len) = TRACE_ID
+///         .scope(trace_id.clone(), async {
+///             let (_, len) = TRACE_ID.fork(async { TRACE_ID.with(String::len) }).await;
+///             len
+///         })
+///         .await;
+///
+///     assert_eq!(len, 7);
+///     assert_eq!(Arc::strong_count(&trace_id), 1);
+/// }
+/// ```
+pub struct FutureArcCell<T>(FutureOnceCell<Arc<T>>);
+
+impl<T> FutureArcCell<T> {
+    /// Creates an empty future arc cell.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        Self(FutureOnceCell::new())
+    }
+}
+
+impl<T: Send + Sync + 'static> Default for FutureArcCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + Sync + 'static> FutureArcCell<T> {
+    /// Acquires a reference to the value in this future local storage.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.0.with(|value| f(value))
+    }
+
+    /// Returns a clone of the [`Arc`] with the current future local value.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn get(&'static self) -> Arc<T> {
+        self.0.with(Arc::clone)
+    }
+
+    /// Sets a shared value `T` as the future-local value for the future `F`.
+    ///
+    /// On completion the scoped future returns the [`Arc`] along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, value: Arc<T>, future: F) -> ScopedFutureWithValue<Arc<T>, F>
+    where
+        F: Future,
+    {
+        self.0.scope(value, future)
+    }
+
+    /// Shares the current future-local value with the future `F`.
+    ///
+    /// Unlike the [`FutureOnceCell::fork`], the scoped future may be polled either inside the
+    /// current scope or as a separate task, since the same value is shared anyway.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn fork<F>(&'static self, future: F) -> ScopedFutureWithValue<Arc<T>, F>
+    where
+        F: Future,
+    {
+        future.with_scope_with_policy(&self.0, self.get(), OnConflict::Nest)
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> Debug for FutureArcCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureArcCell").field(&self.0).finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<Arc<T>>> for FutureArcCell<T> {
+    fn as_ref(&self) -> &FutureLocalKey<Arc<T>> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_arc_cell_nested_forks_share_value() {
+        static CONTEXT: FutureArcCell<Vec<u64>> = FutureArcCell::new();
+
+        let context = Arc::new(vec![1, 2, 3]);
+        let (_, (inner, spawned)) = CONTEXT
+            .scope(context.clone(), async {
+                let (inner, ()) = CONTEXT
+                    .fork(async {
+                        // Each nested scope shares the same allocation.
+                        assert!(Arc::ptr_eq(&CONTEXT.get(), &context));
+                    })
+                    .await;
+                let spawned = tokio::spawn(CONTEXT.fork(async { CONTEXT.with(Vec::len) }));
+                (inner, spawned.await.unwrap().1)
+            })
+            .await;
+
+        assert!(Arc::ptr_eq(&inner, &context));
+        assert_eq!(spawned, 3);
+        assert_eq!(Arc::strong_count(&context), 2);
+    }
+}
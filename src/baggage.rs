@@ -0,0 +1,244 @@
+//! Propagation of the future local values across service boundaries in the
+//! [W3C baggage](https://www.w3.org/TR/baggage/) header.
+
+use std::{collections::HashMap, fmt::Write as _, hash::BuildHasher};
+
+use crate::error::BaggageError;
+
+/// The name of the W3C baggage header.
+pub const BAGGAGE_HEADER: &str = "baggage";
+
+/// Headers of a request which carry the baggage.
+///
+/// It abstracts over the header maps of the different HTTP libraries, implement it for the
+/// header map type of your library to use the
+/// [`FutureOnceCell::inject_into`](crate::FutureOnceCell::inject_into) and
+/// [`FutureOnceCell::extract_scope`](crate::FutureOnceCell::extract_scope) methods.
+pub trait BaggageCarrier {
+    /// Returns the value of the header with the given name.
+    fn header(&self, name: &str) -> Option<&str>;
+
+    /// Sets the value of the header with the given name, replacing the previous one.
+    fn set_header(&mut self, name: &str, value: String);
+}
+
+impl<S: BuildHasher> BaggageCarrier for HashMap<String, String, S> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.get(name).map(String::as_str)
+    }
+
+    fn set_header(&mut self, name: &str, value: String) {
+        self.insert(name.to_owned(), value);
+    }
+}
+
+/// Returns `true` if the given string is a valid baggage key, i.e. a non-empty HTTP token.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+/// Percent-encodes all the bytes of the value except the unreserved ones.
+fn encode_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+    encoded
+}
+
+fn decode_value(key: &str, value: &str) -> Result<String, BaggageError> {
+    let malformed = || BaggageError::Malformed(key.to_owned());
+
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let high = input
+                .next()
+                .and_then(|digit| char::from(digit).to_digit(16));
+            let low = input
+                .next()
+                .and_then(|digit| char::from(digit).to_digit(16));
+            let (Some(high), Some(low)) = (high, low) else {
+                return Err(malformed());
+            };
+            bytes.push(u8::try_from(high << 4 | low).map_err(|_| malformed())?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| malformed())
+}
+
+/// Splits the baggage header into the list members, along with their keys.
+fn members(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|member| !member.is_empty())
+        .map(|member| {
+            let key = member.split(['=', ';']).next().unwrap_or_default().trim();
+            (key, member)
+        })
+}
+
+/// Sets the encoded value under the given key in the baggage header of the given headers,
+/// keeping the other list members.
+pub(crate) fn inject<H>(headers: &mut H, key: &str, value: &str) -> Result<(), BaggageError>
+where
+    H: BaggageCarrier + ?Sized,
+{
+    if !is_valid_key(key) {
+        return Err(BaggageError::InvalidKey(key.to_owned()));
+    }
+
+    let mut header = headers
+        .header(BAGGAGE_HEADER)
+        .map(|header| {
+            members(header)
+                .filter(|(member_key, _)| *member_key != key)
+                .map(|(_, member)| member)
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .unwrap_or_default();
+    if !header.is_empty() {
+        header.push(',');
+    }
+    write!(header, "{key}={}", encode_value(value)).unwrap();
+    headers.set_header(BAGGAGE_HEADER, header);
+    Ok(())
+}
+
+/// Returns the decoded value under the given key in the baggage header of the given headers.
+pub(crate) fn extract<H>(headers: &H, key: &str) -> Result<String, BaggageError>
+where
+    H: BaggageCarrier + ?Sized,
+{
+    let header = headers.header(BAGGAGE_HEADER).unwrap_or_default();
+    let (_, member) = members(header)
+        .find(|(member_key, _)| *member_key == key)
+        .ok_or_else(|| BaggageError::Missing(key.to_owned()))?;
+    // The properties of the list member are ignored.
+    let value = member
+        .split(';')
+        .next()
+        .and_then(|member| member.split_once('='))
+        .map(|(_, value)| value.trim())
+        .ok_or_else(|| BaggageError::Malformed(key.to_owned()))?;
+    decode_value(key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::FutureOnceCell;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Tenant {
+        name: String,
+        tier: u8,
+    }
+
+    #[test]
+    fn test_value_encoding_round_trip() {
+        let value = r#"{"name":"acme, inc; 100%","emoji":"é"}"#;
+        let encoded = encode_value(value);
+        assert!(!encoded.contains([',', ';', '"', ' ']));
+        assert_eq!(decode_value("key", &encoded).unwrap(), value);
+    }
+
+    #[test]
+    fn test_decode_malformed_value() {
+        assert!(matches!(
+            decode_value("key", "%4"),
+            Err(BaggageError::Malformed(key)) if key == "key"
+        ));
+        assert!(decode_value("key", "%FF").is_err());
+    }
+
+    #[test]
+    fn test_inject_keeps_other_members() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            BAGGAGE_HEADER.to_owned(),
+            "user=alice;prop=1, tenant=old".to_owned(),
+        );
+
+        inject(&mut headers, "tenant", "new").unwrap();
+        assert_eq!(headers[BAGGAGE_HEADER], "user=alice;prop=1,tenant=new");
+        assert_eq!(extract(&headers, "user").unwrap(), "alice");
+        assert_eq!(extract(&headers, "tenant").unwrap(), "new");
+    }
+
+    #[test]
+    fn test_inject_invalid_key() {
+        let mut headers = HashMap::<String, String>::new();
+        assert!(matches!(
+            inject(&mut headers, "bad key", "value"),
+            Err(BaggageError::InvalidKey(_))
+        ));
+        assert!(headers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_baggage_round_trip() {
+        static TENANT: FutureOnceCell<Tenant> = FutureOnceCell::new();
+
+        let tenant = Tenant {
+            name: "acme, inc".to_owned(),
+            tier: 2,
+        };
+        // The sending side.
+        let (_, headers) = TENANT
+            .scope(tenant.clone(), async {
+                let mut headers = HashMap::new();
+                TENANT.inject_into("tenant", &mut headers).unwrap();
+                headers
+            })
+            .await;
+
+        // The receiving side.
+        let (received, name) = TENANT
+            .extract_scope("tenant", &headers, async {
+                TENANT.with(|tenant| tenant.name.clone())
+            })
+            .unwrap()
+            .await;
+        assert_eq!(received, tenant);
+        assert_eq!(name, "acme, inc");
+    }
+
+    #[test]
+    fn test_baggage_errors() {
+        static TENANT: FutureOnceCell<Tenant> = FutureOnceCell::new();
+
+        let mut headers = HashMap::new();
+        assert!(matches!(
+            TENANT.inject_into("tenant", &mut headers),
+            Err(BaggageError::Access(_))
+        ));
+        assert!(matches!(
+            TENANT.extract_scope("tenant", &headers, async {}),
+            Err(BaggageError::Missing(key)) if key == "tenant"
+        ));
+
+        headers.insert(BAGGAGE_HEADER.to_owned(), "tenant=42".to_owned());
+        assert!(matches!(
+            TENANT.extract_scope("tenant", &headers, async {}),
+            Err(BaggageError::Serde(_))
+        ));
+    }
+}
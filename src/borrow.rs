@@ -0,0 +1,82 @@
+//! Guard-based access to the future local values.
+
+use std::{
+    cell::Ref,
+    fmt::{self, Debug, Display},
+    ops::Deref,
+};
+
+/// A wrapper type for an immutably borrowed value from a
+/// [`FutureOnceCell`](crate::FutureOnceCell), returned by the
+/// [`FutureOnceCell::borrow`](crate::FutureOnceCell::borrow) method.
+///
+/// The future local value can't be swapped out while it is borrowed, so the guard must be
+/// dropped before the enclosing future yields. To catch the most common mistake, the guard is
+/// not [`Send`], so a future that holds it across an `.await` point is not [`Send`] either and
+/// can't be spawned on a multi-threaded executor:
+///
+/// ```rust,compile_fail
+/// use future_local_storage::FutureOnceCell;
+///
+/// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     tokio::spawn(VALUE.scope(42, async {
+///         let value = VALUE.borrow();
+///         tokio::task::yield_now().await;
+///         *value
+///     }));
+/// }
+/// ```
+///
+/// Dropping the guard before the `.await` point fixes the error:
+///
+/// ```rust
+/// use future_local_storage::FutureOnceCell;
+///
+/// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let handle = tokio::spawn(VALUE.scope(42, async {
+///         let value = *VALUE.borrow();
+///         tokio::task::yield_now().await;
+///         value
+///     }));
+///     assert_eq!(handle.await.unwrap(), (42, 42));
+/// }
+/// ```
+///
+/// This is not a complete protection, since the futures that are not required to be [`Send`]
+/// can still hold the guard across an `.await` point, and the scoped future polling will panic
+/// in this case. The same limitation applies to the closure passed to the
+/// [`FutureOnceCell::with`](crate::FutureOnceCell::with) method, although the closure can't
+/// contain `.await` points itself.
+pub struct FutureRef<T: 'static>(Ref<'static, T>);
+
+impl<T: 'static> FutureRef<T> {
+    pub(crate) fn new(value: Ref<'static, T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: 'static> Deref for FutureRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Debug + 'static> Debug for FutureRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&*self.0, f)
+    }
+}
+
+impl<T: Display + 'static> Display for FutureRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&*self.0, f)
+    }
+}
@@ -0,0 +1,240 @@
+//! Fluent configuration of the scoped futures.
+
+use std::{any::Any, future::Future, time::Duration};
+
+use crate::{future::BuiltScopedFuture, FutureOnceCell};
+
+/// A builder of a scoped future with the combined options, which is created by the
+/// [`FutureOnceCell::build_scope`] method.
+///
+/// Each option corresponds to one of the `scope_*` methods of the [`FutureOnceCell`] or to a
+/// combinator of the [`ScopedFutureWithValue`](crate::ScopedFutureWithValue), and the builder
+/// allows to combine them in a single scoped future, which returns the [`ScopeReport`].
+///
+/// The builder can also be prepared in advance, and then used to run the future later, like
+/// `let report = CELL.build_scope(value).run(future).await`. As all the scoped futures of this
+/// crate, the resulting future implements the [`IntoFuture`](std::future::IntoFuture) trait by
+/// the blanket implementation, so it can be passed to the APIs which expect it.
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicBool, Ordering};
+///
+/// use future_local_storage::FutureOnceCell;
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static CANCELLED: AtomicBool = AtomicBool::new(false);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let report = REQUEST_ID
+///         .build_scope(42)
+///         .named("handle_request")
+///         .on_drop(|_| CANCELLED.store(true, Ordering::Relaxed))
+///         .catch_unwind()
+///         .timed()
+///         .run(async { panic!("request {} failed", REQUEST_ID.get()) })
+///         .await;
+///
+///     assert_eq!(report.name, Some("handle_request"));
+///     assert_eq!(report.value, 42);
+///     assert!(report.output.is_err());
+///     assert!(report.elapsed.is_some());
+///     assert!(!CANCELLED.load(Ordering::Relaxed));
+/// }
+/// ```
+pub struct ScopeBuilder<T: Send + 'static, D = fn(T)> {
+    cell: &'static FutureOnceCell<T>,
+    value: T,
+    name: Option<&'static str>,
+    on_drop: Option<D>,
+    catch_unwind: bool,
+    timed: bool,
+}
+
+impl<T: Send + 'static> ScopeBuilder<T> {
+    pub(crate) fn new(cell: &'static FutureOnceCell<T>, value: T) -> Self {
+        Self {
+            cell,
+            value,
+            name: None,
+            on_drop: None,
+            catch_unwind: false,
+            timed: false,
+        }
+    }
+}
+
+impl<T: Send + 'static, D: FnOnce(T)> ScopeBuilder<T, D> {
+    /// Sets the name of the scope, which is returned in the [`ScopeReport::name`] and shown in
+    /// the [`Debug`] representation of the scoped future.
+    #[must_use]
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Sets the closure which is called with the future local value if the scoped future is
+    /// dropped before completion.
+    #[must_use]
+    pub fn on_drop<D2: FnOnce(T)>(self, on_drop: D2) -> ScopeBuilder<T, D2> {
+        ScopeBuilder {
+            cell: self.cell,
+            value: self.value,
+            name: self.name,
+            on_drop: Some(on_drop),
+            catch_unwind: self.catch_unwind,
+            timed: self.timed,
+        }
+    }
+
+    /// Catches a panic of the inner future and returns it in the [`ScopeReport::output`] along
+    /// with the future local value, see the [`FutureOnceCell::scope_catch`].
+    #[must_use]
+    pub fn catch_unwind(mut self) -> Self {
+        self.catch_unwind = true;
+        self
+    }
+
+    /// Measures the wall-clock time from the first poll to the completion of the inner future,
+    /// see the [`ScopedFutureWithValue::timed`](crate::ScopedFutureWithValue::timed).
+    #[must_use]
+    pub fn timed(mut self) -> Self {
+        self.timed = true;
+        self
+    }
+
+    /// Creates the configured scoped future for the future `F`.
+    pub fn run<F: Future>(self, future: F) -> BuiltScopedFuture<T, F, D> {
+        BuiltScopedFuture::new(
+            self.cell.scope(self.value, future),
+            self.name,
+            self.on_drop,
+            self.catch_unwind,
+            self.timed,
+        )
+    }
+}
+
+impl<T: Send + 'static, D> std::fmt::Debug for ScopeBuilder<T, D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopeBuilder")
+            .field("name", &self.name)
+            .field("on_drop", &self.on_drop.is_some())
+            .field("catch_unwind", &self.catch_unwind)
+            .field("timed", &self.timed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The output of the scoped future created by the [`ScopeBuilder`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ScopeReport<T, O> {
+    /// The future local value.
+    pub value: T,
+    /// The output of the inner future, or the panic payload if the inner future panicked and
+    /// the [`ScopeBuilder::catch_unwind`] option is set. Without this option it's always `Ok`.
+    pub output: Result<O, Box<dyn Any + Send>>,
+    /// The elapsed time, if the [`ScopeBuilder::timed`] option is set.
+    pub elapsed: Option<Duration>,
+    /// The name of the scope, if it was set by the [`ScopeBuilder::named`].
+    pub name: Option<&'static str>,
+}
+
+impl<T, O> ScopeReport<T, O> {
+    /// Returns the future local value and the inner future output, resuming the caught panic,
+    /// if any.
+    pub fn unwrap_output(self) -> (T, O) {
+        match self.output {
+            Ok(output) => (self.value, output),
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_builder_defaults() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let report = VALUE.build_scope(1).run(async { VALUE.get() + 1 }).await;
+        assert_eq!(report.name, None);
+        assert_eq!(report.elapsed, None);
+        assert_eq!(report.unwrap_output(), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_scope_builder_all_options() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let builder = VALUE.build_scope(Cell::new(1)).named("timed").timed();
+        assert!(format!("{builder:?}").contains("\"timed\""));
+
+        let future = builder.run(async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            VALUE.with(|x| x.set(2));
+        });
+        let report = future.await;
+        assert_eq!(report.name, Some("timed"));
+        assert_eq!(report.value.get(), 2);
+        assert!(report.output.is_ok());
+        assert!(report.elapsed.unwrap() >= Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn test_scope_builder_on_drop() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let dropped = Cell::new(None);
+        let mut future = Box::pin(
+            VALUE
+                .build_scope(1)
+                .on_drop(|value| dropped.set(Some(value)))
+                .catch_unwind()
+                .run(std::future::pending::<()>()),
+        );
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+        assert_eq!(dropped.get(), Some(1));
+
+        // The callback is not called on completion.
+        let report = VALUE
+            .build_scope(2)
+            .on_drop(|value| dropped.set(Some(value)))
+            .run(async {})
+            .await;
+        assert_eq!(report.value, 2);
+        assert_eq!(dropped.get(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_scope_builder_into_future() {
+        use std::future::IntoFuture;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        async fn await_into<I: IntoFuture>(future: I) -> I::Output {
+            future.into_future().await
+        }
+
+        let prepared = VALUE.build_scope(1);
+        let report = await_into(prepared.run(async { VALUE.get() })).await;
+        assert_eq!(report.unwrap_output(), (1, 1));
+        // The other scoped futures are accepted in the same way.
+        assert_eq!(
+            await_into(VALUE.scope(2, async { VALUE.get() })).await,
+            (2, 2)
+        );
+        assert_eq!(
+            await_into(VALUE.scope_out(3, async { VALUE.get() })).await,
+            3
+        );
+    }
+}
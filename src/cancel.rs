@@ -0,0 +1,182 @@
+//! Future local storage with cooperative cancellation of the scopes.
+
+use std::{fmt::Debug, future::Future};
+
+use crate::{
+    future::ScopedFutureWithValue, imp::FutureLocalKey, CancelToken, FutureLocalStorage,
+    FutureOnceCell, ScopedFuture,
+};
+
+/// A future local cell, whose scopes can be cancelled cooperatively by a [`CancelToken`].
+///
+/// Along with the value, the cell keeps the cancellation token of the current scope in a
+/// companion future local, so only the cells created as cancellable pay for it. The cancellation
+/// request doesn't stop the scoped future by itself, instead the code running inside the scope
+/// checks it by the [`FutureCancellableCell::is_cancelled`] method, so there is no need to pass
+/// the token through every function call.
+///
+/// ```rust
+/// use future_local_storage::FutureCancellableCell;
+///
+/// static JOB_ID: FutureCancellableCell<u64> = FutureCancellableCell::new();
+///
+/// async fn process_batch() -> usize {
+///     let mut processed = 0;
+///     while !JOB_ID.is_cancelled() {
+///         processed += 1;
+///         tokio::task::yield_now().await;
+///     }
+///     processed
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (token, future) = JOB_ID.scope_cancellable(42, process_batch());
+///     let job = tokio::spawn(future);
+///
+///     tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+///     token.cancel();
+///     let (job_id, _processed) = job.await.unwrap();
+///     assert_eq!(job_id, 42);
+/// }
+/// ```
+pub struct FutureCancellableCell<T>(FutureOnceCell<T>, FutureOnceCell<CancelToken>);
+
+impl<T> FutureCancellableCell<T> {
+    /// Creates an empty future cancellable cell.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: Send + 'static,
+    {
+        Self(FutureOnceCell::new(), FutureOnceCell::new())
+    }
+}
+
+impl<T: Send + 'static> Default for FutureCancellableCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> FutureCancellableCell<T> {
+    /// Acquires a reference to the value in this future local storage.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.0.with(f)
+    }
+
+    /// Returns a copy of the value in this future local storage.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn get(&'static self) -> T
+    where
+        T: Copy,
+    {
+        self.0.get()
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, the scope is never
+    /// cancelled.
+    #[inline]
+    pub fn scope<F>(&'static self, value: T, future: F) -> ScopedFutureWithValue<T, F>
+    where
+        F: Future,
+    {
+        self.0.scope(value, future)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and returns a token which
+    /// requests cooperative cancellation of the scoped future.
+    #[inline]
+    pub fn scope_cancellable<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> (
+        CancelToken,
+        ScopedFuture<CancelToken, ScopedFutureWithValue<T, F>>,
+    )
+    where
+        F: Future,
+    {
+        let token = CancelToken::new();
+        let future = self
+            .0
+            .scope(value, future)
+            .with_scope_out(&self.1, token.clone());
+        (token, future)
+    }
+
+    /// Returns `true` if the cancellation of the current scope has been requested by the token
+    /// returned by the [`Self::scope_cancellable`].
+    ///
+    /// Returns `false` outside of the cancellable scopes of this future local.
+    #[inline]
+    #[must_use]
+    pub fn is_cancelled(&'static self) -> bool {
+        self.1.current().is_ok_and(|token| token.is_cancelled())
+    }
+}
+
+impl<T: Debug + Send + 'static> Debug for FutureCancellableCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureCancellableCell")
+            .field(&self.0)
+            .field(&self.1)
+            .finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<T>> for FutureCancellableCell<T> {
+    fn as_ref(&self) -> &FutureLocalKey<T> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_cancellable() {
+        static VALUE: FutureCancellableCell<u64> = FutureCancellableCell::new();
+
+        assert!(!VALUE.is_cancelled());
+        let (token, future) = VALUE.scope_cancellable(1, async {
+            let mut polls = 0;
+            while !VALUE.is_cancelled() {
+                polls += 1;
+                tokio::task::yield_now().await;
+            }
+            polls
+        });
+        let mut future = Box::pin(future);
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+
+        token.cancel();
+        // The token is visible inside the scope only.
+        assert!(!VALUE.is_cancelled());
+        assert_eq!(future.await, (1, 2));
+
+        // The regular scopes are never cancelled.
+        let (_, cancelled) = VALUE.scope(2, async { VALUE.is_cancelled() }).await;
+        assert!(!cancelled);
+    }
+}
@@ -0,0 +1,194 @@
+//! Future local storage of the items collected by the scope and its child futures.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{future::CollectedScopedFuture, imp::FutureLocalKey, FutureOnceCell, ScopedFuture};
+
+/// The shared list of the items collected by a single scope.
+pub(crate) type CollectedItems<T> = Arc<Mutex<Vec<T>>>;
+
+/// A future local collector of the items pushed by the scope and its child futures.
+///
+/// Unlike the `RefCell<Vec<T>>` stored in the [`FutureOnceCell`], the collected items are shared
+/// by the [`Arc<Mutex<Vec<T>>>`], so the child futures created by the
+/// [`FutureCollector::fork`] can push the items even if they are spawned as separate tasks. The
+/// scoped future returns the collected items in the order of the pushes along with the future
+/// output.
+///
+/// ```rust
+/// use future_local_storage::FutureCollector;
+///
+/// static EVENTS: FutureCollector<&str> = FutureCollector::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (events, ()) = EVENTS
+///         .scope(async {
+///             EVENTS.push("parent");
+///             let child = EVENTS.fork(async { EVENTS.push("child") });
+///             tokio::spawn(child).await.unwrap();
+///         })
+///         .await;
+///
+///     assert_eq!(events, vec!["parent", "child"]);
+/// }
+/// ```
+pub struct FutureCollector<T>(FutureOnceCell<CollectedItems<T>>);
+
+impl<T> FutureCollector<T> {
+    /// Creates an empty future collector.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: Send + 'static,
+    {
+        Self(FutureOnceCell::new())
+    }
+}
+
+impl<T: Send + 'static> Default for FutureCollector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> FutureCollector<T> {
+    /// Pushes the item to the collector of the current scope.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the scope of this future local.
+    #[inline]
+    #[track_caller]
+    pub fn push(&'static self, item: T) {
+        let items = self.0.with_expect(
+            "cannot push an item outside of the collector scope",
+            Arc::clone,
+        );
+        items
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(item);
+    }
+
+    /// Creates a new collector scope for the future `F`.
+    ///
+    /// On completion the scoped future returns the collected items along with the future output.
+    /// The items pushed by the forked futures after that are discarded.
+    #[inline]
+    pub fn scope<F>(&'static self, future: F) -> CollectedScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        CollectedScopedFuture::new(self.0.scope(CollectedItems::default(), future))
+    }
+
+    /// Creates a new collector scope for the future `F` with the pre-allocated space for at
+    /// least `capacity` items.
+    ///
+    /// It avoids the repeated reallocations if the number of the collected items is known in
+    /// advance. Note that the cost of a single push is dominated by the thread local lookup and
+    /// the mutex locking, so for small items the difference is within the noise, see the
+    /// `collector` benchmark.
+    #[inline]
+    pub fn scope_with_capacity<F>(
+        &'static self,
+        capacity: usize,
+        future: F,
+    ) -> CollectedScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        let items = Arc::new(Mutex::new(Vec::with_capacity(capacity)));
+        CollectedScopedFuture::new(self.0.scope(items, future))
+    }
+
+    /// Shares the collector of the current scope with the future `F`.
+    ///
+    /// The items pushed by the forked future are returned by the parent scope, so the forked
+    /// future returns its output only.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn fork<F>(&'static self, future: F) -> ScopedFuture<CollectedItems<T>, F>
+    where
+        F: Future,
+    {
+        self.0.fork(future).discard_value()
+    }
+}
+
+impl<T> Debug for FutureCollector<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureCollector").finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<CollectedItems<T>>> for FutureCollector<T> {
+    fn as_ref(&self) -> &FutureLocalKey<CollectedItems<T>> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_future_collector_spawned_children() {
+        static ITEMS: FutureCollector<u64> = FutureCollector::new();
+
+        let (mut items, ()) = ITEMS
+            .scope(async {
+                let children = (0..4)
+                    .map(|i| {
+                        tokio::spawn(ITEMS.fork(async move {
+                            for j in 0..10 {
+                                ITEMS.push(i * 10 + j);
+                                tokio::task::yield_now().await;
+                            }
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+                for child in children {
+                    child.await.unwrap();
+                }
+            })
+            .await;
+
+        items.sort_unstable();
+        assert_eq!(items, (0..40).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_future_collector_scope_with_capacity() {
+        static ITEMS: FutureCollector<u64> = FutureCollector::new();
+
+        let (items, ()) = ITEMS
+            .scope_with_capacity(16, async {
+                ITEMS.push(1);
+                ITEMS.push(2);
+            })
+            .await;
+
+        assert_eq!(items, vec![1, 2]);
+        assert!(items.capacity() >= 16);
+    }
+
+    #[tokio::test]
+    #[should_panic = "cannot push an item outside of the collector scope"]
+    async fn test_future_collector_push_outside() {
+        static ITEMS: FutureCollector<()> = FutureCollector::new();
+
+        ITEMS.push(());
+    }
+}
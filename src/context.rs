@@ -0,0 +1,183 @@
+//! Errors annotated by the future local context.
+
+use std::{
+    fmt::{self, Debug, Display},
+    sync::{PoisonError, RwLock},
+};
+
+use crate::FutureOnceCell;
+
+type ContextSource = (&'static str, Box<dyn Fn() -> Option<String> + Send + Sync>);
+
+static CONTEXT_SOURCES: RwLock<Vec<ContextSource>> = RwLock::new(Vec::new());
+
+/// Registers the future local cell, which value is attached to the errors created by the
+/// [`with_context`] function under the given name.
+///
+/// The value is attached in its [`Debug`] representation. The cells which have no value set at
+/// the moment of the error creation are skipped.
+///
+/// # Panics
+///
+/// Registering a cell inside the [`Debug`] implementation of a registered cell value leads to a
+/// deadlock or panic.
+pub fn register_error_context<T>(name: &'static str, cell: &'static FutureOnceCell<T>)
+where
+    T: Debug + Send + 'static,
+{
+    let source = move || {
+        // The value may be mutably borrowed if the error is created inside a `with` closure.
+        let value = cell.as_ref().local_key().try_borrow().ok()?;
+        value.as_ref().map(|value| format!("{value:?}"))
+    };
+    CONTEXT_SOURCES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((name, Box::new(source)));
+}
+
+/// Wraps the error into the [`ContextualError`] with the current values of the cells registered
+/// by the [`register_error_context`] function.
+///
+/// It's intended to be used with the `?` operator, like `.map_err(with_context)?`, so the errors
+/// keep the context of the future, which created them, for example, the request id, even after
+/// they are propagated out of the scope.
+///
+/// ```rust
+/// use future_local_storage::{register_error_context, with_context, ContextualError, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// async fn parse(input: &str) -> Result<u64, ContextualError<std::num::ParseIntError>> {
+///     input.parse().map_err(with_context)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     register_error_context("request_id", &REQUEST_ID);
+///
+///     let (_, result) = REQUEST_ID.scope(42, parse("forty-two")).await;
+///     let error = result.unwrap_err();
+///     assert_eq!(error.context_value("request_id"), Some("42"));
+///     assert_eq!(
+///         error.to_string(),
+///         "invalid digit found in string (request_id: 42)"
+///     );
+/// }
+/// ```
+pub fn with_context<E>(error: E) -> ContextualError<E> {
+    let sources = CONTEXT_SOURCES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    let context = sources
+        .iter()
+        .filter_map(|(name, source)| source().map(|value| (*name, value)))
+        .collect();
+    ContextualError { error, context }
+}
+
+/// An error annotated by the future local context captured by the [`with_context`] function.
+pub struct ContextualError<E> {
+    error: E,
+    context: Vec<(&'static str, String)>,
+}
+
+impl<E> ContextualError<E> {
+    /// Returns a reference to the wrapped error.
+    #[must_use]
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Consumes the contextual error, returning the wrapped error.
+    #[must_use]
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// Returns the captured context as the list of the names and the [`Debug`] representations
+    /// of the values in the order of registration.
+    #[must_use]
+    pub fn context(&self) -> &[(&'static str, String)] {
+        &self.context
+    }
+
+    /// Returns the [`Debug`] representation of the captured context value with the given name.
+    #[must_use]
+    pub fn context_value(&self, name: &str) -> Option<&str> {
+        self.context
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl<E: Debug> Debug for ContextualError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextualError")
+            .field("error", &self.error)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+impl<E: Display> Display for ContextualError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)?;
+        for (i, (name, value)) in self.context.iter().enumerate() {
+            f.write_str(if i == 0 { " (" } else { ", " })?;
+            write!(f, "{name}: {value}")?;
+        }
+        if !self.context.is_empty() {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for ContextualError<E> {
+    // The wrapped error is a part of the display message, so its source is reported instead.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct StorageError;
+
+    async fn read_row() -> Result<u64, ContextualError<StorageError>> {
+        tokio::task::yield_now().await;
+        Err(with_context(StorageError))
+    }
+
+    async fn load_user() -> Result<u64, ContextualError<StorageError>> {
+        let row = read_row().await?;
+        Ok(row + 1)
+    }
+
+    async fn handle_request() -> Result<u64, ContextualError<StorageError>> {
+        load_user().await
+    }
+
+    #[tokio::test]
+    async fn test_with_context_captures_scope_values() {
+        static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+        static TENANT: FutureOnceCell<&str> = FutureOnceCell::new();
+
+        register_error_context("test_request_id", &REQUEST_ID);
+        register_error_context("test_tenant", &TENANT);
+
+        let (_, result) = REQUEST_ID.scope(42, handle_request()).await;
+        let error = result.unwrap_err();
+        assert_eq!(error.error(), &StorageError);
+        assert_eq!(error.context_value("test_request_id"), Some("42"));
+        // The tenant is not set in this scope.
+        assert_eq!(error.context_value("test_tenant"), None);
+    }
+}
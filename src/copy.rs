@@ -0,0 +1,152 @@
+//! Future local storage for small [`Copy`] values.
+
+use std::{cell::Cell, fmt::Debug, future::Future};
+
+use state::LocalInitCell;
+
+use crate::{future::CopyScopedFuture, FutureLocalValue};
+
+/// A lightweight future local cell for small [`Copy`] values, like integer identifiers.
+///
+/// Unlike the [`FutureOnceCell`](crate::FutureOnceCell), which keeps the value in the
+/// [`RefCell`](std::cell::RefCell) and thus has to track borrows on each access, this cell keeps
+/// the value in the plain [`Cell`] and copies it on each access. As a result, swapping the value
+/// in and out on each poll of the scoped future is several times cheaper, see the `copy_cell`
+/// benchmark. The cost of a single read is dominated by the thread local lookup, so it is about
+/// the same for both cells.
+///
+/// ```rust
+/// use future_local_storage::FutureCopyCell;
+///
+/// static REQUEST_ID: FutureCopyCell<u64> = FutureCopyCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (request_id, output) = REQUEST_ID
+///         .scope(1, async {
+///             REQUEST_ID.set(REQUEST_ID.get() + 1);
+///             REQUEST_ID.get() * 10
+///         })
+///         .await;
+///
+///     assert_eq!(request_id, 2);
+///     assert_eq!(output, 20);
+/// }
+/// ```
+pub struct FutureCopyCell<T>(LocalInitCell<Cell<Option<T>>>);
+
+impl<T> FutureCopyCell<T> {
+    /// Creates an empty future copy cell.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: FutureLocalValue,
+    {
+        Self(LocalInitCell::new())
+    }
+}
+
+impl<T: FutureLocalValue> Default for FutureCopyCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy + Send + 'static> FutureCopyCell<T> {
+    /// Returns the underlying thread local cell, and if it has not been initialized,
+    /// initializes it with the `None` value.
+    #[inline]
+    pub(crate) fn local_cell(&'static self) -> &'static Cell<Option<T>> {
+        self.0.set(|| Cell::new(None));
+        self.0.get()
+    }
+
+    /// Returns a copy of the contained value.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn get(&'static self) -> T {
+        self.local_cell()
+            .get()
+            .expect("cannot access a future local value without setting it first")
+    }
+
+    /// Returns a copy of the contained value, or the [`Default`] value if the future local
+    /// doesn't have a value set.
+    #[inline]
+    #[must_use]
+    pub fn get_or_default(&'static self) -> T
+    where
+        T: Default,
+    {
+        self.local_cell().get().unwrap_or_default()
+    }
+
+    /// Sets the value in the current scope.
+    ///
+    /// The scoped future returns the last set value on completion.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the scope of this future local.
+    #[inline]
+    #[track_caller]
+    pub fn set(&'static self, value: T) {
+        let cell = self.local_cell();
+        assert!(
+            cell.get().is_some(),
+            "cannot set a future local value outside of its scope"
+        );
+        cell.set(Some(value));
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`.
+    ///
+    /// On completion of `scope`, the future-local value will be returned by the scoped future
+    /// along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, value: T, future: F) -> CopyScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        CopyScopedFuture::new(self, value, future)
+    }
+}
+
+impl<T: Copy + Debug + Send + 'static> Debug for FutureCopyCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureCopyCell").field(&self.0).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_copy_cell_nested_scopes() {
+        static VALUE: FutureCopyCell<u64> = FutureCopyCell::new();
+
+        let (outer, (inner, ())) = VALUE
+            .scope(1, async {
+                let inner = VALUE
+                    .scope(10, async {
+                        tokio::task::yield_now().await;
+                        VALUE.set(VALUE.get() + 1);
+                    })
+                    .await;
+                // The outer value is restored after the inner scope.
+                VALUE.set(VALUE.get() + 1);
+                inner
+            })
+            .await;
+
+        assert_eq!((outer, inner), (2, 11));
+        assert_eq!(VALUE.local_cell().get(), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! Future local storage of the deadline, which is propagated to the nested futures.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::{future::CopyScopedFuture, FutureCopyCell};
+
+/// A future local deadline, which is readable by the code running anywhere inside the scope.
+///
+/// The deadline allows the deeply nested code to make timeout decisions, like choosing a timeout
+/// of an outgoing request, without passing the deadline through each function call. It's stored
+/// in the [`FutureCopyCell`], so reading it is cheap.
+///
+/// A nested scope can only shorten the deadline of the outer scope, the later deadline is
+/// ignored, so the inner code can't outlive the time budget of the outer one.
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+///
+/// use future_local_storage::FutureDeadline;
+///
+/// static DEADLINE: FutureDeadline = FutureDeadline::new();
+///
+/// async fn call_backend() -> Duration {
+///     // Use the rest of the time budget as the request timeout.
+///     DEADLINE.remaining()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let deadline = Instant::now() + Duration::from_secs(10);
+///     let (_, timeout) = DEADLINE.scope_until(deadline, call_backend()).await;
+///
+///     assert!(timeout <= Duration::from_secs(10));
+/// }
+/// ```
+pub struct FutureDeadline(FutureCopyCell<Instant>);
+
+impl FutureDeadline {
+    /// Creates an empty future deadline.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(FutureCopyCell::new())
+    }
+
+    /// Returns the deadline of the current scope.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the deadline scope.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn deadline(&'static self) -> Instant {
+        self.0
+            .local_cell()
+            .get()
+            .expect("cannot access a future deadline outside of the deadline scope")
+    }
+
+    /// Returns the time remaining until the deadline of the current scope, or zero if the
+    /// deadline has already passed.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the deadline scope.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn remaining(&'static self) -> Duration {
+        self.deadline().saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` if the deadline of the current scope has already passed.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the deadline scope.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn is_expired(&'static self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Sets the deadline for the future `F`.
+    ///
+    /// If it's called inside another deadline scope, the earliest of the two deadlines is used.
+    /// On completion the scoped future returns the effective deadline along with the future
+    /// output.
+    ///
+    /// The deadline is not enforced, it's up to the code inside the scope to check it. Combine it
+    /// with a timer, like the `tokio::time::timeout_at`, to cancel the future on the deadline.
+    #[inline]
+    pub fn scope_until<F>(
+        &'static self,
+        deadline: Instant,
+        future: F,
+    ) -> CopyScopedFuture<Instant, F>
+    where
+        F: Future,
+    {
+        let deadline = match self.0.local_cell().get() {
+            Some(outer) => outer.min(deadline),
+            None => deadline,
+        };
+        self.0.scope(deadline, future)
+    }
+}
+
+impl Default for FutureDeadline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for FutureDeadline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureDeadline").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_deadline_remaining_decreases() {
+        static DEADLINE: FutureDeadline = FutureDeadline::new();
+
+        async fn nested() -> Instant {
+            tokio::task::yield_now().await;
+            DEADLINE.deadline()
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let (_, (first, second, inner)) = DEADLINE
+            .scope_until(deadline, async {
+                let first = DEADLINE.remaining();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let second = DEADLINE.remaining();
+                (first, second, nested().await)
+            })
+            .await;
+
+        assert!(second < first);
+        assert!(first <= Duration::from_secs(60));
+        assert_eq!(inner, deadline);
+    }
+
+    #[tokio::test]
+    async fn test_future_deadline_nested_scope_shortens_only() {
+        static DEADLINE: FutureDeadline = FutureDeadline::new();
+
+        let now = Instant::now();
+        let outer = now + Duration::from_secs(10);
+        let (_, (later, earlier)) = DEADLINE
+            .scope_until(outer, async {
+                let (later, ()) = DEADLINE
+                    .scope_until(now + Duration::from_secs(20), async {})
+                    .await;
+                let (earlier, expired) = DEADLINE
+                    .scope_until(now, async { DEADLINE.is_expired() })
+                    .await;
+                assert!(expired);
+                (later, earlier)
+            })
+            .await;
+
+        assert_eq!(later, outer);
+        assert_eq!(earlier, now);
+    }
+
+    #[test]
+    #[should_panic = "cannot access a future deadline outside of the deadline scope"]
+    fn test_future_deadline_outside() {
+        static DEADLINE: FutureDeadline = FutureDeadline::new();
+
+        let _ = DEADLINE.remaining();
+    }
+}
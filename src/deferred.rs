@@ -0,0 +1,140 @@
+//! Future local storage of the deferred work, which runs on the scope exit.
+
+use std::{cell::RefCell, fmt::Debug, future::Future, pin::Pin};
+
+use crate::{future::DeferredScopedFuture, imp::FutureLocalKey, FutureOnceCell};
+
+/// A boxed deferred future registered by the [`DeferredScope::defer`].
+pub(crate) type DeferredFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The list of the deferred futures of a single scope.
+pub(crate) type DeferredList<T> = RefCell<Vec<DeferredFuture<T>>>;
+
+/// A future local list of the futures, which are executed when the scoped future completes.
+///
+/// The code running inside the scope registers the futures by the [`DeferredScope::defer`]
+/// method, and the scoped future awaits them one by one after the inner future completes, like
+/// the `defer` statement in Go. The deferred futures are executed in the reverse order of their
+/// registration, i.e. the last registered future runs first, and their outputs are returned in
+/// the execution order along with the inner future output.
+///
+/// The deferred futures run outside of the scope, so they can't register other deferred futures.
+/// They are dropped without execution if the scoped future is dropped before completion.
+///
+/// ```rust
+/// use future_local_storage::DeferredScope;
+///
+/// static CLEANUP: DeferredScope<&str> = DeferredScope::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (deferred, output) = CLEANUP
+///         .scope(async {
+///             CLEANUP.defer(async { "close connection" });
+///             CLEANUP.defer(async { "flush traces" });
+///             42
+///         })
+///         .await;
+///
+///     assert_eq!(output, 42);
+///     assert_eq!(deferred, vec!["flush traces", "close connection"]);
+/// }
+/// ```
+pub struct DeferredScope<T>(FutureOnceCell<DeferredList<T>>);
+
+impl<T> DeferredScope<T> {
+    /// Creates an empty deferred scope.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: Send + 'static,
+    {
+        Self(FutureOnceCell::new())
+    }
+}
+
+impl<T: Send + 'static> Default for DeferredScope<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> DeferredScope<T> {
+    /// Registers the future to be executed when the current scope completes.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the scope of this future local.
+    #[inline]
+    #[track_caller]
+    pub fn defer<F>(&'static self, future: F)
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        self.0.with_expect(
+            "cannot defer a future outside of the deferred scope",
+            |deferred| deferred.borrow_mut().push(Box::pin(future)),
+        );
+    }
+
+    /// Creates a new deferred scope for the future `F`.
+    ///
+    /// On completion of the future `F`, the scoped future executes the deferred futures, and then
+    /// returns their outputs along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, future: F) -> DeferredScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        DeferredScopedFuture::new(self.0.scope(RefCell::default(), future))
+    }
+}
+
+impl<T> Debug for DeferredScope<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DeferredScope").finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<DeferredList<T>>> for DeferredScope<T> {
+    fn as_ref(&self) -> &FutureLocalKey<DeferredList<T>> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deferred_scope_runs_in_reverse_order() {
+        static DEFERRED: DeferredScope<usize> = DeferredScope::new();
+
+        let (deferred, output) = DEFERRED
+            .scope(async {
+                for i in 0..3 {
+                    DEFERRED.defer(async move {
+                        // Each deferred future completes before the next one starts.
+                        tokio::task::yield_now().await;
+                        i
+                    });
+                    tokio::task::yield_now().await;
+                }
+                "done"
+            })
+            .await;
+
+        assert_eq!(output, "done");
+        assert_eq!(deferred, vec![2, 1, 0]);
+    }
+
+    #[tokio::test]
+    #[should_panic = "cannot defer a future outside of the deferred scope"]
+    async fn test_deferred_scope_defer_outside() {
+        static DEFERRED: DeferredScope<()> = DeferredScope::new();
+
+        DEFERRED.defer(async {});
+    }
+}
@@ -0,0 +1,235 @@
+//! Errors of the future local accesses.
+
+use std::{
+    any::Any,
+    fmt::{self, Debug, Display},
+};
+
+/// An error returned by the [`FutureOnceCell::current`](crate::FutureOnceCell::current) and
+/// similar methods if the future local doesn't have a value in the current scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AccessError;
+
+impl Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cannot access a future local value without setting it first")
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+/// An error returned by the
+/// [`FutureOnceCell::scope_no_panic`](crate::FutureOnceCell::scope_no_panic) scoped future if the
+/// inner future panics.
+///
+/// The error keeps the future local value, so the context of the crashed future can still be
+/// recovered, for example, for logging.
+pub struct ScopeError<T> {
+    value: T,
+    payload: Box<dyn Any + Send>,
+}
+
+impl<T> ScopeError<T> {
+    pub(crate) fn new(value: T, payload: Box<dyn Any + Send>) -> Self {
+        Self { value, payload }
+    }
+
+    /// Returns `true` if the panic has been caused by an access to a future local value without
+    /// setting it first, i.e. it's the panicking counterpart of the [`AccessError`].
+    #[must_use]
+    pub fn is_access_error(&self) -> bool {
+        self.message() == Some(&AccessError.to_string())
+    }
+
+    /// Returns the panic message, if the panic payload is a string.
+    #[must_use]
+    pub fn message(&self) -> Option<&str> {
+        self.payload
+            .downcast_ref::<&'static str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+
+    /// Returns a reference to the future local value.
+    #[must_use]
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Consumes the error, returning the future local value.
+    #[must_use]
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    /// Consumes the error, returning the future local value and the panic payload, which can be
+    /// used to resume the panic by the [`std::panic::resume_unwind`].
+    #[must_use]
+    pub fn into_parts(self) -> (T, Box<dyn Any + Send>) {
+        (self.value, self.payload)
+    }
+}
+
+impl<T: Debug> Debug for ScopeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopeError")
+            .field("value", &self.value)
+            .field("message", &self.message())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Display for ScopeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "scoped future panicked: {message}"),
+            None => f.write_str("scoped future panicked"),
+        }
+    }
+}
+
+impl<T: Debug> std::error::Error for ScopeError<T> {}
+
+/// An error returned by the [`try_join_scopes`](crate::try_join_scopes) future if one of the
+/// joined futures fails.
+///
+/// The other scoped future is cancelled, but the future local values of both scoped futures are
+/// kept, so the context of both of them can still be recovered. The outputs of the successfully
+/// completed future are dropped.
+pub struct TryJoinError<T1, T2, E> {
+    error: E,
+    values: (T1, T2),
+}
+
+impl<T1, T2, E> TryJoinError<T1, T2, E> {
+    pub(crate) fn new(error: E, first: T1, second: T2) -> Self {
+        Self {
+            error,
+            values: (first, second),
+        }
+    }
+
+    /// Returns a reference to the error of the failed future.
+    #[must_use]
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Returns references to the future local values of both scoped futures.
+    #[must_use]
+    pub fn values(&self) -> (&T1, &T2) {
+        (&self.values.0, &self.values.1)
+    }
+
+    /// Consumes the error, returning the error of the failed future.
+    #[must_use]
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// Consumes the error, returning the error of the failed future and the future local values
+    /// of both scoped futures.
+    #[must_use]
+    pub fn into_parts(self) -> (E, (T1, T2)) {
+        (self.error, self.values)
+    }
+}
+
+impl<T1: Debug, T2: Debug, E: Debug> Debug for TryJoinError<T1, T2, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryJoinError")
+            .field("error", &self.error)
+            .field("values", &self.values)
+            .finish()
+    }
+}
+
+impl<T1, T2, E: Display> Display for TryJoinError<T1, T2, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "joined scoped future failed: {}", self.error)
+    }
+}
+
+impl<T1: Debug, T2: Debug, E: std::error::Error + 'static> std::error::Error
+    for TryJoinError<T1, T2, E>
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// An error returned by the [`scope_from_map`](crate::scope_from_map) function if the values
+/// can't be set to the registered future locals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScopeMapError {
+    /// There is no future local registered under this name.
+    UnknownName(String),
+    /// The value type doesn't match the type of the registered future local.
+    TypeMismatch {
+        /// The name of the future local.
+        name: &'static str,
+        /// The name of the future local value type.
+        expected: &'static str,
+    },
+}
+
+impl Display for ScopeMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownName(name) => write!(f, "future local `{name}` is not registered"),
+            Self::TypeMismatch { name, expected } => {
+                write!(
+                    f,
+                    "future local `{name}` expects a value of type `{expected}`"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScopeMapError {}
+
+/// An error returned by the [`FutureOnceCell::inject_into`](crate::FutureOnceCell::inject_into)
+/// and [`FutureOnceCell::extract_scope`](crate::FutureOnceCell::extract_scope) methods if the
+/// value can't be propagated in the baggage header.
+#[cfg(feature = "baggage")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BaggageError {
+    /// The future local doesn't have a value to inject.
+    Access(AccessError),
+    /// The key is not a valid baggage key.
+    InvalidKey(String),
+    /// There is no baggage entry with this key.
+    Missing(String),
+    /// The baggage entry with this key is not properly encoded.
+    Malformed(String),
+    /// The value can't be serialized or deserialized.
+    Serde(serde_json::Error),
+}
+
+#[cfg(feature = "baggage")]
+impl Display for BaggageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Access(err) => Display::fmt(err, f),
+            Self::InvalidKey(key) => write!(f, "`{key}` is not a valid baggage key"),
+            Self::Missing(key) => write!(f, "there is no baggage entry `{key}`"),
+            Self::Malformed(key) => write!(f, "baggage entry `{key}` is malformed"),
+            Self::Serde(err) => write!(f, "cannot encode a baggage value: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "baggage")]
+impl std::error::Error for BaggageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Access(err) => Some(err),
+            Self::Serde(err) => Some(err),
+            Self::InvalidKey(_) | Self::Missing(_) | Self::Malformed(_) => None,
+        }
+    }
+}
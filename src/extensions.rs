@@ -0,0 +1,207 @@
+//! A future local bag of arbitrary typed values.
+//!
+//! Unlike the [`FutureOnceCell`], which requires a dedicated static for each value, the
+//! [`FutureExtensions`] bag allows independent crates, for example, middleware layers, to attach
+//! their context to the current future by the value type, without coordinating on a shared
+//! static.
+//!
+//! ```rust
+//! use future_local_storage::extensions;
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct RequestId(u64);
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct User(String);
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let (extensions, user) = extensions::scope(async {
+//!         // Some middleware attaches the request id.
+//!         extensions::insert(RequestId(42));
+//!         // And another one the user.
+//!         extensions::insert(User("alice".to_owned()));
+//!
+//!         extensions::get::<User>()
+//!     })
+//!     .await;
+//!
+//!     assert_eq!(user, Some(User("alice".to_owned())));
+//!     assert_eq!(extensions.get::<RequestId>(), Some(&RequestId(42)));
+//! }
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+};
+
+use crate::{future::ScopedFutureWithValue, FutureOnceCell};
+
+static EXTENSIONS: FutureOnceCell<FutureExtensions> = FutureOnceCell::new();
+
+/// A type map of the future local values, which is set by the [`scope`] function.
+#[derive(Default)]
+pub struct FutureExtensions {
+    map: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl FutureExtensions {
+    /// Creates an empty extensions bag.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value into the bag, returning the previous value of the same type.
+    pub fn insert<T: Send + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    /// Returns a reference to the value of the given type, if any.
+    #[must_use]
+    pub fn get<T: Send + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns a mutable reference to the value of the given type, if any.
+    pub fn get_mut<T: Send + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Removes the value of the given type from the bag, returning it.
+    pub fn remove<T: Send + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Returns the number of values in the bag.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the bag contains no values.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl Debug for FutureExtensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FutureExtensions")
+            .field("len", &self.map.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sets an empty [`FutureExtensions`] bag for the future `F`.
+///
+/// On completion the scoped future returns the bag along with the future output.
+pub fn scope<F>(future: F) -> ScopedFutureWithValue<FutureExtensions, F>
+where
+    F: Future,
+{
+    EXTENSIONS.scope(FutureExtensions::new(), future)
+}
+
+/// Inserts a value into the current bag, returning the previous value of the same type.
+///
+/// # Panics
+///
+/// - This function will panic if it's called outside of the [`scope`].
+///
+/// - This function will panic if it's called inside the [`with`] closure.
+#[track_caller]
+pub fn insert<T: Send + 'static>(value: T) -> Option<T> {
+    with_bag_mut(|extensions| extensions.insert(value))
+}
+
+/// Removes the value of the given type from the current bag, returning it.
+///
+/// # Panics
+///
+/// - This function will panic if it's called outside of the [`scope`].
+///
+/// - This function will panic if it's called inside the [`with`] closure.
+#[track_caller]
+pub fn remove<T: Send + 'static>() -> Option<T> {
+    with_bag_mut(FutureExtensions::remove)
+}
+
+/// Returns a clone of the value of the given type from the current bag, if any.
+///
+/// # Panics
+///
+/// This function will panic if it's called outside of the [`scope`].
+#[track_caller]
+#[must_use]
+pub fn get<T: Clone + Send + 'static>() -> Option<T> {
+    with(T::clone)
+}
+
+/// Calls the given closure with a reference to the value of the given type from the current
+/// bag, if any.
+///
+/// # Panics
+///
+/// This function will panic if it's called outside of the [`scope`].
+#[track_caller]
+pub fn with<T, R>(f: impl FnOnce(&T) -> R) -> Option<R>
+where
+    T: Send + 'static,
+{
+    EXTENSIONS.with_expect(
+        "cannot access future extensions outside of their scope",
+        |extensions| extensions.get().map(f),
+    )
+}
+
+#[track_caller]
+fn with_bag_mut<R>(f: impl FnOnce(&mut FutureExtensions) -> R) -> R {
+    let mut extensions = EXTENSIONS.raw_local_key().borrow_mut();
+    f(extensions
+        .as_mut()
+        .expect("cannot access future extensions outside of their scope"))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_extensions_isolated_between_futures() {
+        let future = |i: u64| {
+            scope(async move {
+                assert_eq!(insert(i), None);
+                tokio::task::yield_now().await;
+                assert_eq!(insert(i.to_string()), None);
+                assert_eq!(with(|x: &u64| *x + 1), Some(i + 1));
+                assert_eq!(get::<bool>(), None);
+                remove::<String>()
+            })
+        };
+
+        let ((first, first_output), (second, second_output)) =
+            futures_util::future::join(future(1), future(2)).await;
+        assert_eq!(first.get::<u64>(), Some(&1));
+        assert_eq!(second.get::<u64>(), Some(&2));
+        assert_eq!((first.len(), second.len()), (1, 1));
+        assert_eq!(first_output.as_deref(), Some("1"));
+        assert_eq!(second_output.as_deref(), Some("2"));
+    }
+}
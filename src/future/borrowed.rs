@@ -0,0 +1,241 @@
+//! Scoped futures lending the value from a place outside of the scope.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::{pin_project, pinned_drop};
+
+use super::ScopedFutureWithValue;
+use crate::{imp::FutureLocalKey, FutureCopyCell};
+
+/// A [`Future`] returned by the [`FutureCopyCell::scope`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct CopyScopedFuture<T, F>
+where
+    T: Copy + Send + 'static,
+{
+    #[pin]
+    inner: F,
+    scope: &'static FutureCopyCell<T>,
+    value: Option<T>,
+}
+
+impl<T, F> CopyScopedFuture<T, F>
+where
+    T: Copy + Send + 'static,
+{
+    pub(crate) fn new(scope: &'static FutureCopyCell<T>, value: T, inner: F) -> Self {
+        Self {
+            inner,
+            scope,
+            value: Some(value),
+        }
+    }
+}
+
+impl<T, F> Future for CopyScopedFuture<T, F>
+where
+    T: Copy + Send + 'static,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Swaps the value back even if the inner future panics.
+        struct Guard<'a, T: Copy + 'static> {
+            cell: &'static std::cell::Cell<Option<T>>,
+            value: &'a mut Option<T>,
+        }
+
+        impl<T: Copy + 'static> Drop for Guard<'_, T> {
+            fn drop(&mut self) {
+                *self.value = self.cell.replace(*self.value);
+            }
+        }
+
+        let this = self.project();
+        let result = {
+            let cell = this.scope.local_cell();
+            *this.value = cell.replace(*this.value);
+            let _guard = Guard {
+                cell,
+                value: this.value,
+            };
+            this.inner.poll(cx)
+        };
+
+        let result = std::task::ready!(result);
+        let value = this
+            .value
+            .take()
+            .expect("`CopyScopedFuture` must not be polled after it returned `Poll::Ready`");
+        Poll::Ready((value, result))
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_borrowed`](crate::FutureOnceCell::scope_borrowed) method.
+///
+/// The borrowed value is moved into the scope and written back to the borrowed place when the
+/// inner future completes or this future is dropped.
+//
+// A scope storing a reference or a raw pointer to the borrowed value instead of moving it would
+// require the `unsafe` code, which is forbidden in this crate, since the thread local storage can
+// contain the `'static` types only. Moreover, it's not sound in general: the value set in the
+// thread local storage is reachable by any code running on the thread during the poll, including
+// the code that doesn't know about the `'a` lifetime, and a leaked scoped future (e.g. by the
+// `mem::forget`) would not clear it. Lending the value by `&mut` is the safe subset: the borrow
+// checker guarantees that this future doesn't outlive the borrow, and the borrowed place gets the
+// value back even if the future is cancelled.
+#[pin_project(PinnedDrop)]
+pub struct BorrowedScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    place: &'a mut T,
+}
+
+impl<'a, T, F> BorrowedScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, place: &'a mut T) -> Self {
+        Self { future, place }
+    }
+}
+
+impl<T, F> Debug for BorrowedScopedFuture<'_, T, F>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BorrowedScopedFuture")
+            .field("future", &self.future)
+            .finish_non_exhaustive()
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for BorrowedScopedFuture<'_, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if let Some(value) = this.future.take_cancelled_value() {
+            **this.place = value;
+        }
+    }
+}
+
+impl<T, F> Future for BorrowedScopedFuture<'_, T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        **this.place = value;
+        Poll::Ready(output)
+    }
+}
+
+/// A [`Future`] returned by the [`FutureOnceCell::scope_into`](crate::FutureOnceCell::scope_into)
+/// method.
+///
+/// The value is taken from the borrowed slot on the first poll, and written back to the slot
+/// when the inner future completes or this future is dropped.
+#[pin_project(PinnedDrop)]
+pub struct SlotScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    slot: &'a mut Option<T>,
+    installed: bool,
+}
+
+impl<'a, T, F> SlotScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(
+        scope: &'static FutureLocalKey<T>,
+        slot: &'a mut Option<T>,
+        future: F,
+    ) -> Self {
+        Self {
+            future: ScopedFutureWithValue::uninit(scope, future),
+            slot,
+            installed: false,
+        }
+    }
+}
+
+impl<T, F> Debug for SlotScopedFuture<'_, T, F>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlotScopedFuture")
+            .field("future", &self.future)
+            .field("installed", &self.installed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for SlotScopedFuture<'_, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if let Some(value) = this.future.take_cancelled_value() {
+            **this.slot = Some(value);
+        }
+    }
+}
+
+impl<T, F> Future for SlotScopedFuture<'_, T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if !*this.installed {
+            let value = this
+                .slot
+                .take()
+                .expect("`SlotScopedFuture` requires the slot to contain a value");
+            this.future.as_mut().restore_value(|| value);
+            *this.installed = true;
+        }
+
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        **this.slot = Some(value);
+        Poll::Ready(output)
+    }
+}
@@ -0,0 +1,183 @@
+//! Scoped futures collecting the values of the scope.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::PoisonError,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use super::ScopedFutureWithValue;
+use crate::{
+    collector::CollectedItems,
+    deferred::{DeferredFuture, DeferredList},
+    registry::ErasedSlot,
+};
+
+/// A [`Future`] returned by the [`DeferredScope::scope`](crate::DeferredScope::scope) method.
+///
+/// On completion of the inner future it executes the deferred futures in the reverse order of
+/// their registration, and then returns their outputs along with the inner future output.
+#[pin_project]
+pub struct DeferredScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<DeferredList<T>, F>,
+    /// The deferred futures remaining to be executed, it's `None` while the inner future runs.
+    deferred: Option<Vec<DeferredFuture<T>>>,
+    outputs: Vec<T>,
+    output: Option<F::Output>,
+}
+
+impl<T, F> DeferredScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<DeferredList<T>, F>) -> Self {
+        Self {
+            future,
+            deferred: None,
+            outputs: Vec::new(),
+            output: None,
+        }
+    }
+}
+
+impl<T, F> Debug for DeferredScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeferredScopedFuture")
+            .field("inner", &self.future.inner)
+            .field("deferred", &self.deferred.as_ref().map(Vec::len))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Future for DeferredScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (Vec<T>, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if this.deferred.is_none() {
+            let (deferred, output) = std::task::ready!(this.future.as_mut().poll(cx));
+            *this.deferred = Some(deferred.into_inner());
+            *this.output = Some(output);
+        }
+
+        let deferred = this.deferred.as_mut().unwrap();
+        while let Some(future) = deferred.last_mut() {
+            let output = std::task::ready!(future.as_mut().poll(cx));
+            deferred.pop();
+            this.outputs.push(output);
+        }
+        let output = this
+            .output
+            .take()
+            .expect("`DeferredScopedFuture` must not be polled after it returned `Poll::Ready`");
+        Poll::Ready((std::mem::take(this.outputs), output))
+    }
+}
+
+/// A [`Future`] returned by the [`scope_from_map`](crate::scope_from_map) function.
+///
+/// It sets the values of the named future locals on each poll of the inner future, and returns
+/// the map of the values along with the inner future output on completion.
+#[pin_project]
+pub struct MapScopedFuture<F> {
+    #[pin]
+    inner: F,
+    slots: Vec<(&'static str, Box<dyn ErasedSlot>)>,
+}
+
+impl<F> MapScopedFuture<F> {
+    pub(crate) fn new(slots: Vec<(&'static str, Box<dyn ErasedSlot>)>, inner: F) -> Self {
+        Self { inner, slots }
+    }
+}
+
+impl<F: Debug> Debug for MapScopedFuture<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self.slots.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        f.debug_struct("MapScopedFuture")
+            .field("inner", &self.inner)
+            .field("names", &names)
+            .finish()
+    }
+}
+
+/// Calls the closure with the values of all the slots set, the first slot is the outermost one.
+fn within_slots(slots: &mut [(&'static str, Box<dyn ErasedSlot>)], f: &mut dyn FnMut()) {
+    match slots.split_first_mut() {
+        Some(((_, slot), rest)) => slot.within(&mut || within_slots(rest, f)),
+        None => f(),
+    }
+}
+
+impl<F: Future> Future for MapScopedFuture<F> {
+    type Output = (HashMap<&'static str, Box<dyn Any + Send>>, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut inner = this.inner;
+        let mut poll = Poll::Pending;
+        within_slots(this.slots, &mut || poll = inner.as_mut().poll(cx));
+        let output = std::task::ready!(poll);
+        let values = std::mem::take(this.slots)
+            .into_iter()
+            .map(|(name, slot)| (name, slot.into_value()))
+            .collect();
+        Poll::Ready((values, output))
+    }
+}
+
+/// A [`Future`] returned by the [`FutureCollector::scope`](crate::FutureCollector::scope) method.
+///
+/// On completion it returns the collected items along with the inner future output.
+#[pin_project]
+#[derive(Debug)]
+pub struct CollectedScopedFuture<T, F>(#[pin] ScopedFutureWithValue<CollectedItems<T>, F>)
+where
+    T: Send + 'static,
+    F: Future;
+
+impl<T, F> CollectedScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<CollectedItems<T>, F>) -> Self {
+        Self(future)
+    }
+}
+
+impl<T, F> Future for CollectedScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (Vec<T>, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().0.poll(cx).map(|(items, output)| {
+            // The forked futures may still hold the shared list, so the items are taken out of it.
+            let items = std::mem::take(&mut *items.lock().unwrap_or_else(PoisonError::into_inner));
+            (items, output)
+        })
+    }
+}
@@ -0,0 +1,356 @@
+//! Combinators of the scoped futures.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use super::{ScopedFuture, ScopedFutureWithValue};
+use crate::{imp::FutureLocalKey, FutureLocalStorage};
+
+impl<T, F> ScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    /// Maps the output of this future to a different value using the given closure.
+    ///
+    /// The closure `g` is called exactly once, within the same `poll` call in which the inner
+    /// future returns [`Poll::Ready`], right after the inner future completes and before the
+    /// future local value is swapped out. Thus the future local value is still accessible inside
+    /// the closure.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let answer = VALUE
+    ///         .scope(2, async { 40 })
+    ///         .discard_value()
+    ///         .map_output(|output| output + VALUE.get())
+    ///         .await;
+    ///     assert_eq!(answer, 42);
+    /// }
+    /// ```
+    pub fn map_output<U, G>(self, g: G) -> MapOutput<T, F, G>
+    where
+        G: FnOnce(F::Output) -> U,
+    {
+        MapOutput {
+            future: self.0,
+            g: Some(g),
+        }
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFuture::map_output`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct MapOutput<T, F, G>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    g: Option<G>,
+}
+
+impl<T, F, G, U> Future for MapOutput<T, F, G>
+where
+    T: Send,
+    F: Future,
+    G: FnOnce(F::Output) -> U,
+{
+    type Output = U;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let g = this.g;
+        this.future
+            .poll_scoped(cx, |output| {
+                let g = g
+                    .take()
+                    .expect("`MapOutput` must not be polled after it returned `Poll::Ready`");
+                g(output)
+            })
+            .map(|(_value, output)| output)
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::inspect_complete`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct InspectComplete<T, F, G>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    g: Option<G>,
+}
+
+impl<T, F, G> Future for InspectComplete<T, F, G>
+where
+    T: Send,
+    F: Future,
+    G: FnOnce(&T, &F::Output),
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let scope = this.future.scope;
+        let g = this.g;
+        this.future.poll_scoped(cx, |output| {
+            let g = g
+                .take()
+                .expect("`InspectComplete` must not be polled after it returned `Poll::Ready`");
+            let value = scope.local_key().borrow();
+            g(
+                value
+                    .as_ref()
+                    .expect("future local value must be set during the poll"),
+                &output,
+            );
+            output
+        })
+    }
+}
+
+impl<T, F> ScopedFutureWithValue<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    /// Calls the given closure with the references to the future local value and the output of
+    /// this future on completion.
+    ///
+    /// The closure `g` is called exactly once, within the same `poll` call in which the inner
+    /// future returns [`Poll::Ready`], before the future local value is swapped out, so the value
+    /// is also accessible through the future local inside the closure. The closure is not called
+    /// if the future is cancelled, since there is no output.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACE: FutureOnceCell<Vec<&str>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (trace, output) = TRACE
+    ///         .scope(vec!["start"], async { 42 })
+    ///         .inspect_complete(|trace, output| println!("{output}: {trace:?}"))
+    ///         .await;
+    ///     assert_eq!((trace, output), (vec!["start"], 42));
+    /// }
+    /// ```
+    pub fn inspect_complete<G>(self, g: G) -> InspectComplete<T, F, G>
+    where
+        G: FnOnce(&T, &F::Output),
+    {
+        InspectComplete {
+            future: self,
+            g: Some(g),
+        }
+    }
+    /// Chains this scoped future with the next stage of a pipeline, which is scoped by another
+    /// future local.
+    ///
+    /// Once this future completes, its output is passed to the `make_value` closure to create
+    /// the next future local value, and then to the `make_future` closure to create the next
+    /// future, which is executed within the `next_scope` scope with the created value.
+    ///
+    /// The output of the resulting future contains both future local values along with the
+    /// output of the next future.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    /// static USER: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (request_id, user, greeting) = REQUEST_ID
+    ///         .scope(7, async { format!("user-{}", REQUEST_ID.get()) })
+    ///         .and_then_scope(
+    ///             &USER,
+    ///             |name| name.clone(),
+    ///             |name| async move { USER.with(|user| format!("hello, {user} ({name})")) },
+    ///         )
+    ///         .await;
+    ///
+    ///     assert_eq!(request_id, 7);
+    ///     assert_eq!(user, "user-7");
+    ///     assert_eq!(greeting, "hello, user-7 (user-7)");
+    /// }
+    /// ```
+    pub fn and_then_scope<U, S, V, G, F2>(
+        self,
+        next_scope: &'static S,
+        make_value: V,
+        make_future: G,
+    ) -> AndThenScope<T, F, U, F2, V, G>
+    where
+        U: Send,
+        S: AsRef<FutureLocalKey<U>>,
+        V: FnOnce(&F::Output) -> U,
+        G: FnOnce(F::Output) -> F2,
+        F2: Future,
+    {
+        AndThenScope::First {
+            future: self,
+            next_scope: next_scope.as_ref(),
+            make_next: Some((make_value, make_future)),
+        }
+    }
+    /// Re-initializes the future local value by a clone of the `template` each time this future
+    /// is restarted.
+    ///
+    /// The scoped future is restarted if it is polled again after it has returned
+    /// [`Poll::Ready`], which is possible if the inner future can be polled after completion, for
+    /// example, in the custom combinators which reuse a completed future. Each run of the inner
+    /// future starts with a fresh copy of the template and returns the resulting value on
+    /// completion.
+    ///
+    /// ```rust
+    /// use std::{cell::Cell, future::Future, pin::pin, task::Poll};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static ATTEMPTS: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     // The inner future can be polled to completion many times.
+    ///     let inner = std::future::poll_fn(|_| {
+    ///         ATTEMPTS.with(|x| x.set(x.get() + 1));
+    ///         Poll::Ready(())
+    ///     });
+    ///     let mut future = pin!(ATTEMPTS.scope(Cell::new(0), inner).reset_with(Cell::new(0)));
+    ///
+    ///     for _ in 0..3 {
+    ///         let (attempts, ()) = std::future::poll_fn(|cx| future.as_mut().poll(cx)).await;
+    ///         assert_eq!(attempts.get(), 1);
+    ///     }
+    /// }
+    /// ```
+    pub fn reset_with(self, template: T) -> ResetWith<T, F>
+    where
+        T: Clone,
+    {
+        ResetWith {
+            future: self,
+            template,
+        }
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::and_then_scope`] method.
+#[pin_project(project = AndThenScopeProj)]
+#[derive(Debug)]
+pub enum AndThenScope<T, F, U, F2, V, G>
+where
+    T: Send + 'static,
+    F: Future,
+    U: Send + 'static,
+    F2: Future,
+{
+    /// The first stage is being executed.
+    First {
+        /// The scoped future of the first stage.
+        #[pin]
+        future: ScopedFutureWithValue<T, F>,
+        /// The future local key of the next stage.
+        next_scope: &'static FutureLocalKey<U>,
+        /// The closures which create the future local value and the future of the next stage.
+        make_next: Option<(V, G)>,
+    },
+    /// The next stage is being executed.
+    Next {
+        /// The future local value returned by the first stage.
+        value: Option<T>,
+        /// The scoped future of the next stage.
+        #[pin]
+        future: ScopedFutureWithValue<U, F2>,
+    },
+}
+
+impl<T, F, U, F2, V, G> Future for AndThenScope<T, F, U, F2, V, G>
+where
+    T: Send,
+    F: Future,
+    U: Send,
+    F2: Future,
+    V: FnOnce(&F::Output) -> U,
+    G: FnOnce(F::Output) -> F2,
+{
+    type Output = (T, U, F2::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match self.as_mut().project() {
+                AndThenScopeProj::First {
+                    future,
+                    next_scope,
+                    make_next,
+                } => {
+                    let (value, output) = std::task::ready!(future.poll(cx));
+                    let next_scope: &'static FutureLocalKey<U> = next_scope;
+                    let (make_value, make_future) = make_next.take().expect(
+                        "`AndThenScope` must not be polled after it returned `Poll::Ready`",
+                    );
+                    let next_value = make_value(&output);
+                    self.set(Self::Next {
+                        value: Some(value),
+                        future: make_future(output).with_scope(next_scope, next_value),
+                    });
+                }
+                AndThenScopeProj::Next { value, future } => {
+                    let (next_value, output) = std::task::ready!(future.poll(cx));
+                    let value = value.take().expect(
+                        "`AndThenScope` must not be polled after it returned `Poll::Ready`",
+                    );
+                    return Poll::Ready((value, next_value, output));
+                }
+            }
+        }
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::reset_with`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct ResetWith<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    template: T,
+}
+
+impl<T, F> Future for ResetWith<T, F>
+where
+    T: Clone + Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let template = this.template;
+        this.future.as_mut().restore_value(|| template.clone());
+        this.future.poll(cx)
+    }
+}
@@ -0,0 +1,424 @@
+//! Instrumentation of the scoped futures.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use pin_project::pin_project;
+#[cfg(feature = "tracing")]
+use pin_project::pinned_drop;
+
+use super::ScopedFutureWithValue;
+
+impl<T, F> ScopedFutureWithValue<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    /// Measures the wall-clock time from the first poll of this future to its completion.
+    ///
+    /// The elapsed time includes the time the future spent suspended, but not the time before
+    /// the first poll. It's returned along with the future local value and the future output.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (elapsed, request_id, ()) = REQUEST_ID
+    ///         .scope(42, tokio::time::sleep(Duration::from_millis(10)))
+    ///         .timed()
+    ///         .await;
+    ///
+    ///     assert_eq!(request_id, 42);
+    ///     assert!(elapsed >= Duration::from_millis(10));
+    /// }
+    /// ```
+    pub fn timed(self) -> Timed<T, F> {
+        Timed {
+            future: self,
+            started: None,
+        }
+    }
+
+    /// Counts how many times this future has been polled.
+    ///
+    /// The number of polls is returned along with the future local value and the future output.
+    /// It helps to find the futures which are polled excessively, for example, because of the
+    /// spurious wake ups.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (polls, _, ()) = REQUEST_ID
+    ///         .scope(42, tokio::task::yield_now())
+    ///         .count_polls()
+    ///         .await;
+    ///     assert_eq!(polls, 2);
+    /// }
+    /// ```
+    pub fn count_polls(self) -> CountPolls<T, F> {
+        CountPolls {
+            future: self,
+            polls: 0,
+        }
+    }
+
+    /// Counts how many distinct threads this future has been polled on.
+    ///
+    /// The number of threads is returned along with the future local value and the future output.
+    /// On a work-stealing runtime a task may be moved between the worker threads at any suspension
+    /// point, which is why the future local value is swapped out between the polls and why the
+    /// `!Send` values can't be stored in a future local.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let (threads, _, ()) = REQUEST_ID
+    ///         .scope(42, tokio::task::yield_now())
+    ///         .count_migrations()
+    ///         .await;
+    ///     assert_eq!(threads, 1);
+    /// }
+    /// ```
+    pub fn count_migrations(self) -> CountMigrations<T, F> {
+        CountMigrations {
+            future: self,
+            threads: Vec::new(),
+        }
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::timed`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct Timed<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    started: Option<Instant>,
+}
+
+impl<T, F> Future for Timed<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (Duration, T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let started = *this.started.get_or_insert_with(Instant::now);
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        Poll::Ready((started.elapsed(), value, output))
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::count_polls`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct CountPolls<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    polls: u64,
+}
+
+impl<T, F> Future for CountPolls<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (u64, T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        *this.polls += 1;
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        Poll::Ready((*this.polls, value, output))
+    }
+}
+
+/// A [`Future`] returned by the [`ScopedFutureWithValue::count_migrations`] method.
+#[pin_project]
+#[derive(Debug)]
+pub struct CountMigrations<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    // There are only a few worker threads, so a vector is cheaper than a hash set.
+    threads: Vec<ThreadId>,
+}
+
+impl<T, F> Future for CountMigrations<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (usize, T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let current = thread::current().id();
+        if !this.threads.contains(&current) {
+            this.threads.push(current);
+        }
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        Poll::Ready((this.threads.len(), value, output))
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_traced`](crate::FutureOnceCell::scope_traced) method.
+///
+/// On completion it emits a [`tracing`] event with the [`Debug`] representation of the final
+/// future local value.
+#[cfg(feature = "tracing")]
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct TracedScopedFuture<T, F>
+where
+    T: Debug + Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    level: tracing::Level,
+    trace_cancel: bool,
+}
+
+#[cfg(feature = "tracing")]
+impl<T, F> TracedScopedFuture<T, F>
+where
+    T: Debug + Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, level: tracing::Level) -> Self {
+        Self {
+            future,
+            level,
+            trace_cancel: false,
+        }
+    }
+
+    /// Emits the event also if this future is dropped before completion, the event has the
+    /// `cancelled` field set to `true` in this case.
+    #[must_use]
+    pub fn trace_cancel(mut self) -> Self {
+        self.trace_cancel = true;
+        self
+    }
+}
+
+/// Emits the scope completion event with the dynamic level, since the [`tracing::event!`] macro
+/// requires a constant one.
+#[cfg(feature = "tracing")]
+fn emit_traced_event(level: tracing::Level, value: &dyn Debug, cancelled: bool) {
+    use tracing::Level;
+
+    macro_rules! event {
+        ($level:expr) => {
+            // Keep the target of the public `future` module, so the subscriber filters don't
+            // depend on the private module layout.
+            tracing::event!(
+                target: "future_local_storage::future",
+                $level,
+                value = ?value,
+                cancelled,
+                "future local scope finished"
+            )
+        };
+    }
+
+    match level {
+        Level::ERROR => event!(Level::ERROR),
+        Level::WARN => event!(Level::WARN),
+        Level::INFO => event!(Level::INFO),
+        Level::DEBUG => event!(Level::DEBUG),
+        _ => event!(Level::TRACE),
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[pinned_drop]
+impl<T, F> PinnedDrop for TracedScopedFuture<T, F>
+where
+    T: Debug + Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if !*this.trace_cancel {
+            return;
+        }
+        if let Some(value) = this.future.take_cancelled_value() {
+            emit_traced_event(*this.level, &value, true);
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T, F> Future for TracedScopedFuture<T, F>
+where
+    T: Debug + Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        emit_traced_event(*this.level, &value, false);
+        Poll::Ready((value, output))
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_observed`](crate::FutureOnceCell::scope_observed) method.
+///
+/// It sends a clone of the future local value to the [`ScopeSnapshots`](crate::ScopeSnapshots)
+/// each time the inner future is suspended, and the value itself on completion, and returns the
+/// output of the inner future only.
+#[cfg(feature = "tokio")]
+#[pin_project]
+#[derive(Debug)]
+pub struct ObservedScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    /// The sender is dropped if the snapshots stream is dropped, so the value is not cloned in vain.
+    sender: Option<tokio::sync::mpsc::UnboundedSender<T>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> ObservedScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        sender: tokio::sync::mpsc::UnboundedSender<T>,
+    ) -> Self {
+        Self {
+            future,
+            sender: Some(sender),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> Future for ObservedScopedFuture<T, F>
+where
+    T: Clone + Send,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let Poll::Ready((value, output)) = this.future.as_mut().poll(cx) else {
+            if let (Some(sender), Some(value)) = (
+                this.sender.as_ref(),
+                this.future.as_ref().get_ref().value.as_ref(),
+            ) {
+                if sender.send(value.clone()).is_err() {
+                    *this.sender = None;
+                }
+            }
+            return Poll::Pending;
+        };
+        if let Some(sender) = this.sender.take() {
+            let _ = sender.send(value);
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_on_pending`](crate::FutureOnceCell::scope_on_pending) method.
+///
+/// It calls the callback with the future local value each time the inner future is suspended.
+#[pin_project]
+pub struct OnPendingScopedFuture<T, F, P>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    on_pending: P,
+}
+
+impl<T, F, P> OnPendingScopedFuture<T, F, P>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, on_pending: P) -> Self {
+        Self { future, on_pending }
+    }
+}
+
+impl<T, F, P> Debug for OnPendingScopedFuture<T, F, P>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnPendingScopedFuture")
+            .field("future", &self.future)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F, P> Future for OnPendingScopedFuture<T, F, P>
+where
+    T: Send,
+    F: Future,
+    P: FnMut(&T),
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let poll = this.future.as_mut().poll(cx);
+        if poll.is_pending() {
+            // The value has been swapped back to the scoped future with all the modifications
+            // made during the poll.
+            if let Some(value) = this.future.as_ref().get_ref().value.as_ref() {
+                (this.on_pending)(value);
+            }
+        }
+        poll
+    }
+}
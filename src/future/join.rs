@@ -0,0 +1,259 @@
+//! Joined scoped futures.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use super::ScopedFutureWithValue;
+use crate::{task_scope::ScopeTask, TryJoinError};
+
+/// A [`Future`] returned by the [`future_scope`](crate::future_scope) function.
+///
+/// It polls all the scoped futures registered in the scope concurrently, and returns the result
+/// of the scope closure when all of them complete.
+pub struct FutureScopeJoin<'env, R> {
+    tasks: Vec<Option<ScopeTask<'env>>>,
+    output: Option<R>,
+}
+
+impl<'env, R> FutureScopeJoin<'env, R> {
+    pub(crate) fn new(tasks: Vec<ScopeTask<'env>>, output: R) -> Self {
+        Self {
+            tasks: tasks.into_iter().map(Some).collect(),
+            output: Some(output),
+        }
+    }
+}
+
+impl<R> Debug for FutureScopeJoin<'_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FutureScopeJoin")
+            .field("running", &self.tasks.iter().flatten().count())
+            .finish_non_exhaustive()
+    }
+}
+
+// The tasks are boxed and the output is never pinned, so this future is `Unpin`.
+impl<R> Unpin for FutureScopeJoin<'_, R> {}
+
+impl<R> Future for FutureScopeJoin<'_, R> {
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut running = false;
+        for slot in &mut this.tasks {
+            if let Some(task) = slot {
+                if task.as_mut().poll(cx).is_ready() {
+                    *slot = None;
+                } else {
+                    running = true;
+                }
+            }
+        }
+        if running {
+            return Poll::Pending;
+        }
+        Poll::Ready(
+            this.output
+                .take()
+                .expect("`FutureScopeJoin` must not be polled after it returned `Poll::Ready`"),
+        )
+    }
+}
+
+/// A [`Future`] returned by the [`join_scopes`](crate::join_scopes) function.
+///
+/// It polls both scoped futures concurrently and returns their values and outputs when both of
+/// them complete.
+#[pin_project]
+pub struct JoinScopes<T1, F1, T2, F2>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future,
+    F2: Future,
+{
+    #[pin]
+    first: ScopedFutureWithValue<T1, F1>,
+    #[pin]
+    second: ScopedFutureWithValue<T2, F2>,
+    first_output: Option<(T1, F1::Output)>,
+    second_output: Option<(T2, F2::Output)>,
+}
+
+impl<T1, F1, T2, F2> JoinScopes<T1, F1, T2, F2>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future,
+    F2: Future,
+{
+    pub(crate) fn new(
+        first: ScopedFutureWithValue<T1, F1>,
+        second: ScopedFutureWithValue<T2, F2>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            first_output: None,
+            second_output: None,
+        }
+    }
+}
+
+impl<T1, F1, T2, F2> Debug for JoinScopes<T1, F1, T2, F2>
+where
+    T1: Send + Debug + 'static,
+    T2: Send + Debug + 'static,
+    F1: Future + Debug,
+    F2: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinScopes")
+            .field("first", &self.first)
+            .field("second", &self.second)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T1, F1, T2, F2> Future for JoinScopes<T1, F1, T2, F2>
+where
+    T1: Send,
+    T2: Send,
+    F1: Future,
+    F2: Future,
+{
+    type Output = ((T1, F1::Output), (T2, F2::Output));
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.first_output.is_none() {
+            if let Poll::Ready(output) = this.first.poll(cx) {
+                *this.first_output = Some(output);
+            }
+        }
+        if this.second_output.is_none() {
+            if let Poll::Ready(output) = this.second.poll(cx) {
+                *this.second_output = Some(output);
+            }
+        }
+        if this.first_output.is_none() || this.second_output.is_none() {
+            return Poll::Pending;
+        }
+        Poll::Ready((
+            this.first_output.take().unwrap(),
+            this.second_output.take().unwrap(),
+        ))
+    }
+}
+
+/// A [`Future`] returned by the [`try_join_scopes`](crate::try_join_scopes) function.
+///
+/// It polls both scoped futures concurrently and returns their values and outputs when both of
+/// them complete successfully. If one of them fails, the other one is cancelled, and the error is
+/// returned along with the values of both scoped futures.
+#[pin_project]
+pub struct TryJoinScopes<T1, F1, T2, F2, R1, R2, E>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future<Output = Result<R1, E>>,
+    F2: Future<Output = Result<R2, E>>,
+{
+    #[pin]
+    first: ScopedFutureWithValue<T1, F1>,
+    #[pin]
+    second: ScopedFutureWithValue<T2, F2>,
+    first_output: Option<(T1, R1)>,
+    second_output: Option<(T2, R2)>,
+}
+
+impl<T1, F1, T2, F2, R1, R2, E> TryJoinScopes<T1, F1, T2, F2, R1, R2, E>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future<Output = Result<R1, E>>,
+    F2: Future<Output = Result<R2, E>>,
+{
+    pub(crate) fn new(
+        first: ScopedFutureWithValue<T1, F1>,
+        second: ScopedFutureWithValue<T2, F2>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            first_output: None,
+            second_output: None,
+        }
+    }
+}
+
+impl<T1, F1, T2, F2, R1, R2, E> Debug for TryJoinScopes<T1, F1, T2, F2, R1, R2, E>
+where
+    T1: Send + Debug + 'static,
+    T2: Send + Debug + 'static,
+    F1: Future<Output = Result<R1, E>> + Debug,
+    F2: Future<Output = Result<R2, E>> + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TryJoinScopes")
+            .field("first", &self.first)
+            .field("second", &self.second)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T1, F1, T2, F2, R1, R2, E> Future for TryJoinScopes<T1, F1, T2, F2, R1, R2, E>
+where
+    T1: Send,
+    T2: Send,
+    F1: Future<Output = Result<R1, E>>,
+    F2: Future<Output = Result<R2, E>>,
+{
+    type Output = Result<((T1, R1), (T2, R2)), TryJoinError<T1, T2, E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if this.first_output.is_none() {
+            if let Poll::Ready((first, output)) = this.first.as_mut().poll(cx) {
+                match output {
+                    Ok(output) => *this.first_output = Some((first, output)),
+                    Err(error) => {
+                        let second = match this.second_output.take() {
+                            Some((second, _)) => second,
+                            None => this.second.take_cancelled_value().unwrap(),
+                        };
+                        return Poll::Ready(Err(TryJoinError::new(error, first, second)));
+                    }
+                }
+            }
+        }
+        if this.second_output.is_none() {
+            if let Poll::Ready((second, output)) = this.second.as_mut().poll(cx) {
+                match output {
+                    Ok(output) => *this.second_output = Some((second, output)),
+                    Err(error) => {
+                        let first = match this.first_output.take() {
+                            Some((first, _)) => first,
+                            None => this.first.take_cancelled_value().unwrap(),
+                        };
+                        return Poll::Ready(Err(TryJoinError::new(error, first, second)));
+                    }
+                }
+            }
+        }
+        if this.first_output.is_none() || this.second_output.is_none() {
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok((
+            this.first_output.take().unwrap(),
+            this.second_output.take().unwrap(),
+        )))
+    }
+}
@@ -0,0 +1,627 @@
+//! Scoped futures hooking into the start and the end of the scope.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use pin_project::{pin_project, pinned_drop};
+
+use super::ScopedFutureWithValue;
+use crate::{builder::ScopeReport, FutureOnceCell, ScopeHandle};
+
+/// A [`Future`] returned by the
+/// [`FutureLocalStorage::with_scope_boxed_init`](crate::FutureLocalStorage::with_scope_boxed_init)
+/// method.
+///
+/// The future local value is created by the boxed closure on the first poll.
+#[pin_project]
+pub struct BoxedInitScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    init: Option<Box<dyn FnOnce() -> T + Send>>,
+}
+
+impl<T, F> BoxedInitScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(super) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        init: Box<dyn FnOnce() -> T + Send>,
+    ) -> Self {
+        Self {
+            future,
+            init: Some(init),
+        }
+    }
+}
+
+impl<T, F> Debug for BoxedInitScopedFuture<T, F>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoxedInitScopedFuture")
+            .field("future", &self.future)
+            .field("initialized", &self.init.is_none())
+            .finish()
+    }
+}
+
+impl<T, F> Future for BoxedInitScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some(init) = this.init.take() {
+            this.future.as_mut().restore_value(init);
+        }
+        this.future.poll(cx)
+    }
+}
+
+/// A [`Future`] returned by the [`lazy_body`] function.
+#[pin_project]
+#[derive(Debug)]
+pub struct LazyBody<G, F> {
+    make_future: Option<G>,
+    #[pin]
+    future: Option<F>,
+}
+
+/// Creates a future which calls the `make_future` closure on its first poll and then polls the
+/// returned future.
+///
+/// Unlike a future passed to the [`FutureOnceCell::scope`] directly, the lazy body is created
+/// when the scope is already entered. So the synchronous setup code, which runs before a
+/// function returns its future, also sees the future local value.
+///
+/// ```rust
+/// use std::future::Future;
+///
+/// use future_local_storage::{future::lazy_body, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// fn handle() -> impl Future<Output = u64> {
+///     // This code runs before the returned future is polled.
+///     let request_id = REQUEST_ID.get();
+///     async move { request_id * 2 }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (_, output) = REQUEST_ID.scope(21, lazy_body(handle)).await;
+///     assert_eq!(output, 42);
+/// }
+/// ```
+pub fn lazy_body<G, F>(make_future: G) -> LazyBody<G, F>
+where
+    G: FnOnce() -> F,
+    F: Future,
+{
+    LazyBody {
+        make_future: Some(make_future),
+        future: None,
+    }
+}
+
+impl<G, F> Future for LazyBody<G, F>
+where
+    G: FnOnce() -> F,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some(make_future) = this.make_future.take() {
+            this.future.set(Some(make_future()));
+        }
+        this.future
+            .as_pin_mut()
+            .expect("`LazyBody` must not be polled after it panicked")
+            .poll(cx)
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_with_handle`](crate::FutureOnceCell::scope_with_handle) method.
+///
+/// It updates the state of the associated [`ScopeHandle`] on completion and on drop.
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct WithScopeHandle<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    handle: ScopeHandle,
+}
+
+impl<T, F> WithScopeHandle<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, handle: ScopeHandle) -> Self {
+        Self { future, handle }
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for WithScopeHandle<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        self.handle.cancel();
+    }
+}
+
+impl<T, F> Future for WithScopeHandle<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = std::task::ready!(this.future.poll(cx));
+        this.handle.complete();
+        Poll::Ready(output)
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_channel`](crate::FutureOnceCell::scope_channel) method.
+///
+/// It sends the future local value to the receiver on completion and returns the output of the
+/// inner future only.
+#[cfg(feature = "tokio")]
+#[pin_project]
+#[derive(Debug)]
+pub struct ChannelScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    sender: Option<tokio::sync::oneshot::Sender<T>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> ChannelScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        sender: tokio::sync::oneshot::Sender<T>,
+    ) -> Self {
+        Self {
+            future,
+            sender: Some(sender),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> Future for ChannelScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        let sender = this
+            .sender
+            .take()
+            .expect("`ChannelScopedFuture` must not be polled after it returned `Poll::Ready`");
+        // The receiver may be dropped if nobody is interested in the value.
+        let _ = sender.send(value);
+        Poll::Ready(output)
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_with_async_finalizer`](crate::FutureOnceCell::scope_with_async_finalizer)
+/// method.
+///
+/// On completion of the inner future it awaits the finalizer future created from the future
+/// local value, and then returns the inner future output.
+#[pin_project(PinnedDrop)]
+pub struct AsyncFinalizer<T, F, G, Fut, C>
+where
+    T: Send + 'static,
+    F: Future,
+    C: FnOnce(T),
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    finalizer: Option<G>,
+    #[pin]
+    finalizing: Option<Fut>,
+    output: Option<F::Output>,
+    on_cancel: Option<C>,
+}
+
+impl<T, F, G, Fut, C> AsyncFinalizer<T, F, G, Fut, C>
+where
+    T: Send + 'static,
+    F: Future,
+    C: FnOnce(T),
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, finalizer: G, on_cancel: C) -> Self {
+        Self {
+            future,
+            finalizer: Some(finalizer),
+            finalizing: None,
+            output: None,
+            on_cancel: Some(on_cancel),
+        }
+    }
+}
+
+impl<T, F, G, Fut, C> Debug for AsyncFinalizer<T, F, G, Fut, C>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+    C: FnOnce(T),
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncFinalizer")
+            .field("future", &self.future)
+            .field("finalizing", &self.finalizing.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[pinned_drop]
+impl<T, F, G, Fut, C> PinnedDrop for AsyncFinalizer<T, F, G, Fut, C>
+where
+    T: Send + 'static,
+    F: Future,
+    C: FnOnce(T),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        // The finalizer can't be awaited in the drop, so the synchronous fallback is called
+        // instead, if the inner future has not been completed yet.
+        if let Some(value) = this.future.take_cancelled_value() {
+            if let Some(on_cancel) = this.on_cancel.take() {
+                on_cancel(value);
+            }
+        }
+    }
+}
+
+impl<T, F, G, Fut, C> Future for AsyncFinalizer<T, F, G, Fut, C>
+where
+    T: Send,
+    F: Future,
+    G: FnOnce(T) -> Fut,
+    Fut: Future<Output = ()>,
+    C: FnOnce(T),
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            if let Some(finalizing) = this.finalizing.as_mut().as_pin_mut() {
+                std::task::ready!(finalizing.poll(cx));
+                this.finalizing.set(None);
+                let output = this
+                    .output
+                    .take()
+                    .expect("`AsyncFinalizer` must not be polled after it returned `Poll::Ready`");
+                return Poll::Ready(output);
+            }
+
+            let (value, output) = std::task::ready!(this.future.as_mut().poll(cx));
+            let finalizer = this
+                .finalizer
+                .take()
+                .expect("`AsyncFinalizer` must not be polled after it returned `Poll::Ready`");
+            *this.output = Some(output);
+            this.finalizing.set(Some(finalizer(value)));
+        }
+    }
+}
+
+/// The boxed future acquiring a semaphore permit for the [`LimitedScopedFuture`].
+#[cfg(feature = "tokio")]
+type AcquirePermit<'a> = Pin<
+    Box<
+        dyn Future<Output = Result<tokio::sync::SemaphorePermit<'a>, tokio::sync::AcquireError>>
+            + Send
+            + 'a,
+    >,
+>;
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_limited`](crate::FutureOnceCell::scope_limited) method.
+///
+/// It acquires a semaphore permit before the first poll of the inner future, and holds it until
+/// the inner future completes or this future is dropped.
+#[cfg(feature = "tokio")]
+#[pin_project]
+pub struct LimitedScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    acquire: Option<AcquirePermit<'a>>,
+    permit: Option<tokio::sync::SemaphorePermit<'a>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<'a, T, F> LimitedScopedFuture<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        semaphore: &'a tokio::sync::Semaphore,
+    ) -> Self {
+        Self {
+            future,
+            acquire: Some(Box::pin(semaphore.acquire())),
+            permit: None,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> Debug for LimitedScopedFuture<'_, T, F>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LimitedScopedFuture")
+            .field("future", &self.future)
+            .field("permit", &self.permit)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T, F> Future for LimitedScopedFuture<'_, T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, Result<F::Output, tokio::sync::AcquireError>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Some(acquire) = this.acquire.as_mut() {
+            let result = std::task::ready!(acquire.as_mut().poll(cx));
+            *this.acquire = None;
+            match result {
+                Ok(permit) => *this.permit = Some(permit),
+                // The semaphore has been closed, so the inner future is never polled.
+                Err(err) => return Poll::Ready((this.future.take_value(), Err(err))),
+            }
+        }
+
+        let (value, output) = std::task::ready!(this.future.poll(cx));
+        // Release the permit as soon as the scope is finished.
+        *this.permit = None;
+        Poll::Ready((value, Ok(output)))
+    }
+}
+
+/// A [`Future`] returned by the [`ScopeBuilder::run`](crate::ScopeBuilder::run) method.
+#[pin_project(PinnedDrop)]
+pub struct BuiltScopedFuture<T, F, D>
+where
+    T: Send + 'static,
+    F: Future,
+    D: FnOnce(T),
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    name: Option<&'static str>,
+    on_drop: Option<D>,
+    catch_unwind: bool,
+    timed: bool,
+    started: Option<Instant>,
+}
+
+impl<T, F, D> BuiltScopedFuture<T, F, D>
+where
+    T: Send + 'static,
+    F: Future,
+    D: FnOnce(T),
+{
+    pub(crate) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        name: Option<&'static str>,
+        on_drop: Option<D>,
+        catch_unwind: bool,
+        timed: bool,
+    ) -> Self {
+        Self {
+            future,
+            name,
+            on_drop,
+            catch_unwind,
+            timed,
+            started: None,
+        }
+    }
+}
+
+impl<T, F, D> Debug for BuiltScopedFuture<T, F, D>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+    D: FnOnce(T),
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuiltScopedFuture")
+            .field("name", &self.name)
+            .field("future", &self.future)
+            .field("catch_unwind", &self.catch_unwind)
+            .field("timed", &self.timed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[pinned_drop]
+impl<T, F, D> PinnedDrop for BuiltScopedFuture<T, F, D>
+where
+    T: Send + 'static,
+    F: Future,
+    D: FnOnce(T),
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        let Some(on_drop) = this.on_drop.take() else {
+            return;
+        };
+        if let Some(value) = this.future.take_cancelled_value() {
+            on_drop(value);
+        }
+    }
+}
+
+impl<T, F, D> Future for BuiltScopedFuture<T, F, D>
+where
+    T: Send,
+    F: Future,
+    D: FnOnce(T),
+{
+    type Output = ScopeReport<T, F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut future = this.future;
+        if *this.timed && this.started.is_none() {
+            *this.started = Some(Instant::now());
+        }
+
+        let (value, output) = if *this.catch_unwind {
+            match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll_scoped(cx, Ok))) {
+                Ok(poll) => std::task::ready!(poll),
+                // The future local value has been already swapped back by the scope guard.
+                Err(payload) => (future.take_value(), Err(payload)),
+            }
+        } else {
+            std::task::ready!(future.poll_scoped(cx, Ok))
+        };
+        Poll::Ready(ScopeReport {
+            value,
+            output,
+            elapsed: this.started.map(|started| started.elapsed()),
+            name: *this.name,
+        })
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_retry`](crate::FutureOnceCell::scope_retry) method.
+///
+/// It retries the failed attempts, each of them is scoped by a fresh future local value.
+#[pin_project]
+pub struct RetryScopedFuture<T, V, M, Fut>
+where
+    T: Send + 'static,
+    Fut: Future,
+{
+    #[pin]
+    attempt: ScopedFutureWithValue<T, Fut>,
+    cell: &'static FutureOnceCell<T>,
+    make_value: V,
+    make_future: M,
+    retries: usize,
+}
+
+impl<T, V, M, Fut> RetryScopedFuture<T, V, M, Fut>
+where
+    T: Send + 'static,
+    V: FnMut() -> T,
+    M: FnMut() -> Fut,
+    Fut: Future,
+{
+    pub(crate) fn new(
+        cell: &'static FutureOnceCell<T>,
+        mut make_value: V,
+        mut make_future: M,
+        retries: usize,
+    ) -> Self {
+        Self {
+            attempt: cell.scope(make_value(), make_future()),
+            cell,
+            make_value,
+            make_future,
+            retries,
+        }
+    }
+}
+
+impl<T, V, M, Fut> Debug for RetryScopedFuture<T, V, M, Fut>
+where
+    T: Send + Debug + 'static,
+    Fut: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryScopedFuture")
+            .field("attempt", &self.attempt)
+            .field("retries", &self.retries)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, V, M, Fut, O, E> Future for RetryScopedFuture<T, V, M, Fut>
+where
+    T: Send,
+    V: FnMut() -> T,
+    M: FnMut() -> Fut,
+    Fut: Future<Output = Result<O, E>>,
+{
+    type Output = (T, Result<O, E>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let (value, output) = std::task::ready!(this.attempt.as_mut().poll(cx));
+            if output.is_ok() || *this.retries == 0 {
+                return Poll::Ready((value, output));
+            }
+            *this.retries -= 1;
+            this.attempt
+                .set(this.cell.scope((this.make_value)(), (this.make_future)()));
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Future types.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::{pin_project, pinned_drop};
+
+#[cfg(feature = "tokio")]
+pub use self::instrument::ObservedScopedFuture;
+#[cfg(feature = "tracing")]
+pub use self::instrument::TracedScopedFuture;
+#[cfg(feature = "tokio")]
+pub use self::lifecycle::{ChannelScopedFuture, LimitedScopedFuture};
+#[cfg(feature = "stream")]
+pub use self::stream::{ScopedStream, ScopedStreamOnce, TryScopedStream};
+pub use self::{
+    borrowed::{BorrowedScopedFuture, CopyScopedFuture, SlotScopedFuture},
+    collect::{CollectedScopedFuture, DeferredScopedFuture, MapScopedFuture},
+    combinators::{AndThenScope, InspectComplete, MapOutput, ResetWith},
+    instrument::{CountMigrations, CountPolls, OnPendingScopedFuture, Timed},
+    join::{FutureScopeJoin, JoinScopes, TryJoinScopes},
+    lifecycle::{
+        lazy_body, AsyncFinalizer, BoxedInitScopedFuture, BuiltScopedFuture, LazyBody,
+        RetryScopedFuture, WithScopeHandle,
+    },
+    outer::{LinkParent, MergedScopedFuture, ReplaceScope},
+    thread::{StickyScopedFuture, ThreadAffineScopedFuture, ThreadLocalBridge},
+    unwind::{CatchUnwind, NoPanicScopedFuture},
+};
+#[cfg(feature = "internals")]
+pub use crate::imp::ScopeGuard;
+#[cfg(not(feature = "internals"))]
+use crate::imp::ScopeGuard;
+use crate::{
+    imp::FutureLocalKey,
+    subscriber::{self, ScopeEventKind},
+    FutureLocalStorage,
+};
+
+mod borrowed;
+mod collect;
+mod combinators;
+mod instrument;
+mod join;
+mod lifecycle;
+mod outer;
+#[cfg(feature = "stream")]
+mod stream;
+mod thread;
+mod unwind;
+
+impl<F: Future> FutureLocalStorage for F {
+    fn with_scope<T, S>(self, scope: &'static S, value: T) -> ScopedFutureWithValue<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>,
+    {
+        self.with_scope_with_policy(scope, value, OnConflict::default())
+    }
+
+    fn with_scope_with_policy<T, S>(
+        self,
+        scope: &'static S,
+        value: T,
+        policy: OnConflict,
+    ) -> ScopedFutureWithValue<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>,
+    {
+        let scope = scope.as_ref();
+        ScopedFutureWithValue {
+            inner: self,
+            scope,
+            value: Some(value),
+            policy,
+            #[cfg(feature = "debug-warn-unused")]
+            read: false,
+            drop_scope: DropScope::new(scope),
+        }
+    }
+
+    fn with_scope_boxed_init<T, S>(
+        self,
+        scope: &'static S,
+        init: Box<dyn FnOnce() -> T + Send>,
+    ) -> BoxedInitScopedFuture<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>,
+    {
+        BoxedInitScopedFuture::new(ScopedFutureWithValue::uninit(scope.as_ref(), self), init)
+    }
+}
+
+/// Defines the behavior of a scoped future when the future local already has a value at the
+/// moment the scoped future is polled, i.e. when it is polled inside an outer scope of the same
+/// future local.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Silently installs the scoped value over the outer one.
+    ///
+    /// The outer value is swapped into the scoped future for the duration of the poll, and swapped
+    /// back when the poll finishes.
+    #[default]
+    Overwrite,
+    /// Panics if the future local already has a value.
+    ///
+    /// The reentrant scopes, i.e. the scoped futures polled inside a scoped future of the same
+    /// future local, are reported with a distinct panic message.
+    Panic,
+    /// Saves the outer value before polling and restores it afterwards, so the scoped value
+    /// shadows the outer one.
+    ///
+    /// Unlike the [`OnConflict::Overwrite`] the outer value is never stored in the scoped future
+    /// itself.
+    Nest,
+}
+
+/// A [`Future`] that sets a value `T` of a future local for the future `F` during its execution.
+/// Unlike the [`ScopedFutureWithValue`] this future discards the future local value.
+///
+/// The output of this future is the output of the inner future `F`. It has the same [`Send`] and
+/// [`Unpin`] properties as the [`ScopedFutureWithValue`] it wraps.
+///
+/// ```rust
+/// use std::cell::Cell;
+///
+/// use future_local_storage::FutureOnceCell;
+///
+/// static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let answer: u64 = VALUE
+///         .scope(Cell::from(41), async { VALUE.with(Cell::get) + 1 })
+///         .discard_value()
+///         .await;
+///     assert_eq!(answer, 42);
+/// }
+/// ```
+#[pin_project]
+#[derive(Debug)]
+pub struct ScopedFuture<T, F>(#[pin] ScopedFutureWithValue<T, F>)
+where
+    T: Send + 'static,
+    F: Future;
+
+impl<T, F> Future for ScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.project().0;
+        let wipe = future.drop_scope.wipe;
+        future.poll(cx).map(|(mut value, result)| {
+            if let Some(wipe) = wipe {
+                wipe(&mut value);
+            }
+            result
+        })
+    }
+}
+
+impl<T, F> ScopedFutureWithValue<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    /// Discards the future local value from the future output.
+    pub fn discard_value(self) -> ScopedFuture<T, F> {
+        ScopedFuture(self)
+    }
+
+    /// Polls the inner future with the future local value set, and if it is ready, maps its
+    /// output by the given closure before the value is swapped out.
+    pub(crate) fn poll_scoped<R>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(F::Output) -> R,
+    ) -> Poll<(T, R)> {
+        let this = self.project();
+        let result = {
+            // Swap in future local key, it will be swapped back by the guard even if the
+            // underlying future panics.
+            let _guard = ScopeGuard::enter(
+                this.scope,
+                this.value,
+                *this.policy,
+                #[cfg(feature = "debug-warn-unused")]
+                this.read,
+            );
+            // Poll the underlying future.
+            this.inner.poll(cx).map(f)
+        };
+
+        let result = std::task::ready!(result);
+        #[cfg(feature = "debug-warn-unused")]
+        if !*this.read {
+            log::warn!(
+                "a scoped future of `{}` future local completed without reading its value",
+                std::any::type_name::<T>()
+            );
+        }
+        subscriber::emit(this.scope, ScopeEventKind::Complete);
+        // Take the scoped value to return it back to the future caller.
+        let value = this.value.take().unwrap();
+        Poll::Ready((value, result))
+    }
+
+    /// Returns a pinned mutable reference to the inner future.
+    ///
+    /// This method allows custom combinators to poll the inner future by themselves.
+    ///
+    /// # Important
+    ///
+    /// The future local value is *not* set by this method, so the code which polls the inner
+    /// future directly must set it by itself, for example using the
+    /// [`ScopedFutureWithValue::enter_scope`] method. Otherwise, the inner future will not see
+    /// the value during the poll, or will see the value of an outer scope.
+    #[cfg(feature = "internals")]
+    #[must_use]
+    pub fn project_inner(self: Pin<&mut Self>) -> Pin<&mut F> {
+        self.project().inner
+    }
+
+    /// Sets the future local value and returns a pinned mutable reference to the inner future
+    /// along with the guard which keeps the value set while it is alive.
+    ///
+    /// This is what the `poll` method of this future does before polling the inner future, so
+    /// custom combinators can use it to poll the inner future by themselves. Note that in this
+    /// case the future local value remains inside the scoped future after the inner future
+    /// completes, and it is dropped along with the scoped future.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the [`OnConflict::Panic`] policy is set for this future and the
+    /// future local already has a value.
+    ///
+    /// ```rust
+    /// use std::{future::Future, pin::pin, task::Poll};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut future = pin!(VALUE.scope(42, async { VALUE.get() }));
+    ///     let output = std::future::poll_fn(|cx| {
+    ///         let (inner, _guard) = future.as_mut().enter_scope();
+    ///         inner.poll(cx)
+    ///     })
+    ///     .await;
+    ///     assert_eq!(output, 42);
+    /// }
+    /// ```
+    #[cfg(feature = "internals")]
+    #[must_use]
+    pub fn enter_scope(self: Pin<&mut Self>) -> (Pin<&mut F>, ScopeGuard<'_, T>) {
+        let this = self.project();
+        let guard = ScopeGuard::enter(
+            this.scope,
+            this.value,
+            *this.policy,
+            #[cfg(feature = "debug-warn-unused")]
+            this.read,
+        );
+        (this.inner, guard)
+    }
+
+    /// Returns a mutable reference to the future local value, if it is not taken yet and is not
+    /// set in the future local right now.
+    fn value_mut(self: Pin<&mut Self>) -> Option<&mut T> {
+        self.project().value.as_mut()
+    }
+
+    /// Sets the future local value of this future, if it has been taken.
+    fn restore_value(self: Pin<&mut Self>, value: impl FnOnce() -> T) {
+        self.project().value.get_or_insert_with(value);
+    }
+
+    /// Creates a scoped future without the value, it must be set by the
+    /// [`Self::restore_value`] before the first poll.
+    pub(crate) fn uninit(scope: &'static FutureLocalKey<T>, future: F) -> Self {
+        Self {
+            inner: future,
+            scope,
+            value: None,
+            policy: OnConflict::default(),
+            #[cfg(feature = "debug-warn-unused")]
+            read: false,
+            drop_scope: DropScope::new(scope),
+        }
+    }
+
+    /// Sets the function which wipes the future local value before this future drops it, i.e. on
+    /// cancellation, or on completion if the value is discarded by the [`ScopedFuture`].
+    #[cfg(feature = "zeroize")]
+    pub(crate) fn wipe_on_drop(mut self, wipe: fn(&mut T)) -> Self {
+        self.drop_scope.wipe = Some(wipe);
+        self
+    }
+
+    /// Takes the future local value out of this future.
+    fn take_value(self: Pin<&mut Self>) -> T {
+        self.project()
+            .value
+            .take()
+            .expect("future local value has already been taken")
+    }
+
+    /// Takes the future local value out of this suspended future, if any, without treating it
+    /// as cancelled.
+    fn take_suspended_value(self: Pin<&mut Self>) -> Option<T> {
+        self.project().value.take()
+    }
+
+    /// Takes the future local value out of this future, if it has not been taken yet, treating
+    /// this future as cancelled.
+    fn take_cancelled_value(self: Pin<&mut Self>) -> Option<T> {
+        let this = self.project();
+        let value = this.value.take();
+        if value.is_some() {
+            subscriber::emit(this.scope, ScopeEventKind::Cancel);
+        }
+        value
+    }
+}
+
+/// A [`Future`] that sets a value `T` of a future local for the future `F` during its execution.
+///
+/// This future also returns a future local value after execution, so its output is the
+/// `(T, F::Output)` tuple. Use [`ScopedFutureWithValue::discard_value`] to get the output of
+/// the inner future only.
+///
+/// With the `debug-warn-unused` feature enabled, the scoped future logs a warning on completion
+/// if the inner future has never read the future local value, which often means that the scope
+/// is set up for nothing.
+///
+/// The scoped future is [`Send`] if both `T` and `F` are [`Send`], so it can be spawned on a
+/// multi-threaded executor. The value is never pinned, thus the scoped future is [`Unpin`] if the
+/// inner future `F` is [`Unpin`].
+///
+/// For the [`Unpin`] inner futures, the mutable reference to the scoped future is also a
+/// [`Future`], so the scoped future can be polled by reference without consuming it, for
+/// example, in the `tokio::select!` loops.
+///
+/// ```rust
+/// use future_local_storage::FutureOnceCell;
+///
+/// static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (value, len): (String, usize) = VALUE
+///         .scope("hello".to_owned(), async { VALUE.with(String::len) })
+///         .await;
+///     assert_eq!(value, "hello");
+///     assert_eq!(len, 5);
+/// }
+/// ```
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct ScopedFutureWithValue<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    inner: F,
+    scope: &'static FutureLocalKey<T>,
+    value: Option<T>,
+    policy: OnConflict,
+    /// Whether the value has been read by the inner future.
+    #[cfg(feature = "debug-warn-unused")]
+    read: bool,
+    /// Must be the last field: the fields are dropped in the declaration order, so the inner
+    /// future is dropped before the scope installed for it is exited.
+    drop_scope: DropScope<T>,
+}
+
+/// Removes the value installed by a cancelled [`ScopedFutureWithValue`] once its inner future
+/// has been dropped, restoring the outer one.
+#[derive(Debug)]
+struct DropScope<T: Send + 'static> {
+    scope: &'static FutureLocalKey<T>,
+    entered: bool,
+    outer: Option<T>,
+    /// Wipes the value before it's dropped, see the [`ScopedFutureWithValue::wipe_on_drop`].
+    wipe: Option<fn(&mut T)>,
+}
+
+impl<T: Send + 'static> DropScope<T> {
+    fn new(scope: &'static FutureLocalKey<T>) -> Self {
+        Self {
+            scope,
+            entered: false,
+            outer: None,
+            wipe: None,
+        }
+    }
+
+    /// Installs the given value until this guard is dropped.
+    fn enter(&mut self, value: T) {
+        self.outer = FutureLocalKey::replace(self.scope, Some(value));
+        self.entered = true;
+    }
+}
+
+impl<T: Send + 'static> Drop for DropScope<T> {
+    fn drop(&mut self) {
+        if self.entered {
+            // Drop our value only after the outer one has been restored.
+            let value = FutureLocalKey::replace(self.scope, self.outer.take());
+            if let (Some(mut value), Some(wipe)) = (value, self.wipe) {
+                wipe(&mut value);
+            }
+        }
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for ScopedFutureWithValue<T, F>
+where
+    F: Future,
+    T: Send + 'static,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        // The value is taken on completion, so the remaining value means that the future has
+        // been cancelled.
+        if let Some(value) = this.value.take() {
+            subscriber::emit(this.scope, ScopeEventKind::Cancel);
+            // Keep the value installed while the inner future is being dropped, so it's
+            // accessible from the `Drop` implementations of the inner future state.
+            this.drop_scope.enter(value);
+        }
+    }
+}
+
+impl<T, F> Future for ScopedFutureWithValue<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_scoped(cx, std::convert::identity)
+    }
+}
+
+impl<T, F> From<ScopedFutureWithValue<T, F>> for ScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    fn from(value: ScopedFutureWithValue<T, F>) -> Self {
+        Self(value)
+    }
+}
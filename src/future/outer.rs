@@ -0,0 +1,311 @@
+//! Scoped futures interacting with an outer scope of the same future local.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::{pin_project, pinned_drop};
+
+use super::{OnConflict, ScopeGuard, ScopedFutureWithValue};
+use crate::{imp::FutureLocalKey, FutureOnceCell};
+
+/// A [`Future`] returned by the [`FutureOnceCell::replace_scope`](crate::FutureOnceCell::replace_scope)
+/// method.
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct ReplaceScope<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    inner: F,
+    scope: &'static FutureLocalKey<T>,
+    value: Option<T>,
+    outer: Option<T>,
+    state: ReplaceState,
+    #[cfg(feature = "debug-warn-unused")]
+    read: bool,
+}
+
+/// Whether a [`ReplaceScope`] has replaced the outer value on the first poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplaceState {
+    /// The future has not been polled yet.
+    Init,
+    /// The outer value has been replaced, and the new one is kept by the enclosing scope.
+    Replaced,
+    /// There is no outer value, so the new one is scoped to the inner future only.
+    Scoped,
+}
+
+impl<T, F> ReplaceScope<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(scope: &'static FutureLocalKey<T>, value: T, future: F) -> Self {
+        Self {
+            inner: future,
+            scope,
+            value: Some(value),
+            outer: None,
+            state: ReplaceState::Init,
+            #[cfg(feature = "debug-warn-unused")]
+            read: false,
+        }
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for ReplaceScope<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        // The future has been cancelled after the replacement, so put the outer value back into
+        // the enclosing scope instead of the new one.
+        if let Some(outer) = this.outer.take() {
+            if FutureLocalKey::is_set(this.scope) {
+                FutureLocalKey::replace(this.scope, Some(outer));
+            }
+        }
+    }
+}
+
+impl<T, F> Future for ReplaceScope<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (Option<T>, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if *this.state == ReplaceState::Init {
+            if FutureLocalKey::is_set(this.scope) {
+                *this.outer = FutureLocalKey::replace(this.scope, this.value.take());
+                *this.state = ReplaceState::Replaced;
+            } else {
+                *this.state = ReplaceState::Scoped;
+            }
+        }
+
+        if *this.state == ReplaceState::Replaced {
+            let result = std::task::ready!(this.inner.poll(cx));
+            return Poll::Ready((this.outer.take(), result));
+        }
+
+        // Without an outer scope nothing would swap the new value out, so it's set for the polls
+        // of the inner future only, as the regular scope does.
+        let result = {
+            let _guard = ScopeGuard::enter(
+                this.scope,
+                this.value,
+                OnConflict::Overwrite,
+                #[cfg(feature = "debug-warn-unused")]
+                this.read,
+            );
+            std::task::ready!(this.inner.poll(cx))
+        };
+        *this.value = None;
+        Poll::Ready((None, result))
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_merged`](crate::FutureOnceCell::scope_merged) method.
+///
+/// On the first poll it either merges the value into the value of an outer scope, or sets it as
+/// a fresh scope if there is no outer one.
+#[pin_project]
+pub struct MergedScopedFuture<T, F, M>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    merge: Option<M>,
+    merged: bool,
+}
+
+impl<T, F, M> MergedScopedFuture<T, F, M>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>, merge: M) -> Self {
+        Self {
+            future,
+            merge: Some(merge),
+            merged: false,
+        }
+    }
+}
+
+impl<T, F, M> Debug for MergedScopedFuture<T, F, M>
+where
+    T: Send + Debug + 'static,
+    F: Future + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergedScopedFuture")
+            .field("future", &self.future)
+            .field("merged", &self.merged)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F, M> Future for MergedScopedFuture<T, F, M>
+where
+    T: Send,
+    F: Future,
+    M: FnOnce(&mut T, T),
+{
+    type Output = (Option<T>, F::Output);
+
+    #[track_caller]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let scope = this.future.scope;
+        if let Some(merge) = this.merge.take() {
+            if FutureLocalKey::is_set(scope) {
+                let value = this.future.as_mut().take_value();
+                // Merge without holding a borrow of the future local, so the closure may access
+                // it, though it sees no value.
+                let mut outer = LentValue::lend(this.future.as_mut());
+                merge(outer.value_mut(), value);
+                *this.merged = true;
+            }
+        }
+
+        if !*this.merged {
+            return this
+                .future
+                .poll(cx)
+                .map(|(value, output)| (Some(value), output));
+        }
+
+        // Borrow the merged value from the outer scope for the duration of the poll, so the
+        // inner future is polled under the scope guard as usual.
+        let mut outer = LentValue::lend(this.future.as_mut());
+        match outer.future.as_mut().poll(cx) {
+            Poll::Ready((value, output)) => {
+                drop(outer);
+                FutureLocalKey::replace(scope, Some(value));
+                Poll::Ready((None, output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Moves the value of the outer scope into a [`MergedScopedFuture`] and back to the outer scope
+/// when dropped, so the outer value is not lost even if the merge or the inner future panics.
+struct LentValue<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    future: Pin<&'a mut ScopedFutureWithValue<T, F>>,
+}
+
+impl<'a, T, F> LentValue<'a, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[track_caller]
+    fn lend(mut future: Pin<&'a mut ScopedFutureWithValue<T, F>>) -> Self {
+        let Some(outer) = FutureLocalKey::replace(future.scope, None) else {
+            panic!(
+                "`scope_merged` must be polled inside the outer scope its value has been merged \
+                 into, there is no outer value"
+            );
+        };
+        future.as_mut().restore_value(|| outer);
+        Self { future }
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        self.future.as_mut().value_mut().unwrap()
+    }
+}
+
+impl<T, F> Drop for LentValue<'_, T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(&mut self) {
+        // The value is taken by the scoped future on completion.
+        if let Some(value) = self.future.as_mut().take_suspended_value() {
+            FutureLocalKey::replace(self.future.scope, Some(value));
+        }
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_with_parent`](crate::FutureOnceCell::scope_with_parent) method.
+#[pin_project]
+#[derive(Debug)]
+pub struct LinkParent<T, P, F, H>
+where
+    T: Send + 'static,
+    P: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    parent: &'static FutureOnceCell<P>,
+    link: Option<H>,
+}
+
+impl<T, P, F, H> LinkParent<T, P, F, H>
+where
+    T: Send + 'static,
+    P: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(
+        future: ScopedFutureWithValue<T, F>,
+        parent: &'static FutureOnceCell<P>,
+        link: H,
+    ) -> Self {
+        Self {
+            future,
+            parent,
+            link: Some(link),
+        }
+    }
+}
+
+impl<T, P, F, H> Future for LinkParent<T, P, F, H>
+where
+    T: Send,
+    P: Send,
+    F: Future,
+    H: FnOnce(&mut T, Option<&P>),
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        // Link the value with the parent one on the first poll.
+        if let Some(link) = this.link.take() {
+            let value = this
+                .future
+                .as_mut()
+                .value_mut()
+                .expect("future local value must be present before the first poll");
+            link(value, this.parent.raw_local_key().borrow().as_ref());
+        }
+        this.future.poll(cx)
+    }
+}
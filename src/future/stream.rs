@@ -0,0 +1,259 @@
+//! Scoped streams.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use super::{OnConflict, ScopeGuard, ScopedFutureWithValue};
+use crate::imp::FutureLocalKey;
+
+impl<St: futures_core::Stream> crate::StreamLocalStorage for St {
+    fn with_scope<T, S>(self, scope: &'static S, value: T) -> ScopedStream<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>,
+    {
+        ScopedStream {
+            inner: self,
+            scope: scope.as_ref(),
+            value: Some(value),
+            #[cfg(feature = "debug-warn-unused")]
+            read: false,
+        }
+    }
+}
+
+impl<T, F> ScopedFutureWithValue<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    /// Converts this future into a stream which yields the future output along with the future
+    /// local value as a single item and then ends.
+    ///
+    /// The future local value is set during the `poll_next` call in the same way as during the
+    /// `poll` call of this future.
+    ///
+    /// ```rust
+    /// use futures_util::StreamExt;
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let items = VALUE
+    ///         .scope(1, async { VALUE.get() + 1 })
+    ///         .into_stream()
+    ///         .collect::<Vec<_>>()
+    ///         .await;
+    ///     assert_eq!(items, vec![(1, 2)]);
+    /// }
+    /// ```
+    pub fn into_stream(self) -> ScopedStreamOnce<T, F> {
+        ScopedStreamOnce { future: Some(self) }
+    }
+}
+
+/// A [`Stream`](futures_core::Stream) returned by the [`ScopedFutureWithValue::into_stream`]
+/// method.
+#[pin_project]
+#[derive(Debug)]
+pub struct ScopedStreamOnce<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: Option<ScopedFutureWithValue<T, F>>,
+}
+
+impl<T, F> futures_core::Stream for ScopedStreamOnce<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Item = (T, F::Output);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let Some(future) = this.future.as_mut().as_pin_mut() else {
+            return Poll::Ready(None);
+        };
+
+        let item = std::task::ready!(future.poll(cx));
+        this.future.set(None);
+        Poll::Ready(Some(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = usize::from(self.future.is_some());
+        (len, Some(len))
+    }
+}
+
+/// A [`Stream`](futures_core::Stream) returned by the
+/// [`StreamLocalStorage::with_scope`](crate::StreamLocalStorage::with_scope) method.
+///
+/// The future-local value is set while the inner stream is polled. The value can be taken back
+/// by the [`ScopedStream::take_value`] method at any point between polls, for example, after the
+/// stream has ended.
+#[pin_project]
+#[derive(Debug)]
+pub struct ScopedStream<T, S>
+where
+    T: Send + 'static,
+{
+    #[pin]
+    inner: S,
+    scope: &'static FutureLocalKey<T>,
+    value: Option<T>,
+    #[cfg(feature = "debug-warn-unused")]
+    read: bool,
+}
+
+impl<T, S> ScopedStream<T, S>
+where
+    T: Send + 'static,
+{
+    /// Takes the future-local value out of the stream.
+    ///
+    /// The inner stream is not polled anymore after this call, and the stream ends. Returns
+    /// `None` if the value has already been taken.
+    #[must_use]
+    pub fn take_value(self: Pin<&mut Self>) -> Option<T> {
+        self.project().value.take()
+    }
+}
+
+impl<T, S> futures_core::Stream for ScopedStream<T, S>
+where
+    T: Send,
+    S: futures_core::Stream,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if this.value.is_none() {
+            return Poll::Ready(None);
+        }
+
+        let _guard = ScopeGuard::enter(
+            this.scope,
+            this.value,
+            OnConflict::default(),
+            #[cfg(feature = "debug-warn-unused")]
+            this.read,
+        );
+        this.inner.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.value.is_none() {
+            (0, Some(0))
+        } else {
+            self.inner.size_hint()
+        }
+    }
+}
+
+/// A [`Stream`](futures_core::Stream) returned by the
+/// [`FutureOnceCell::try_scope_stream`](crate::FutureOnceCell::try_scope_stream) method.
+///
+/// The future-local value is set while the inner stream is polled. The stream ends after the
+/// first error item, the inner stream is not polled anymore. The value can be taken back by the
+/// [`TryScopedStream::take_value`] method at any point between polls, in particular after the
+/// stream has ended either by exhaustion or by an error.
+#[pin_project]
+#[derive(Debug)]
+pub struct TryScopedStream<T, S>
+where
+    T: Send + 'static,
+{
+    #[pin]
+    inner: S,
+    scope: &'static FutureLocalKey<T>,
+    value: Option<T>,
+    terminated: bool,
+    #[cfg(feature = "debug-warn-unused")]
+    read: bool,
+}
+
+impl<T, S> TryScopedStream<T, S>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(scope: &'static FutureLocalKey<T>, value: T, stream: S) -> Self {
+        Self {
+            inner: stream,
+            scope,
+            value: Some(value),
+            terminated: false,
+            #[cfg(feature = "debug-warn-unused")]
+            read: false,
+        }
+    }
+
+    /// Returns `true` if the stream has ended, either because the inner stream is exhausted or
+    /// because it has produced an error item.
+    #[must_use]
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Takes the future-local value out of the stream.
+    ///
+    /// The stream is terminated after this call, and the subsequent polls return `None`. Returns
+    /// `None` if the value has already been taken.
+    #[must_use]
+    pub fn take_value(self: Pin<&mut Self>) -> Option<T> {
+        let this = self.project();
+        *this.terminated = true;
+        this.value.take()
+    }
+}
+
+impl<T, S, I, E> futures_core::Stream for TryScopedStream<T, S>
+where
+    T: Send,
+    S: futures_core::Stream<Item = Result<I, E>>,
+{
+    type Item = Result<I, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.terminated {
+            return Poll::Ready(None);
+        }
+
+        let item = {
+            let _guard = ScopeGuard::enter(
+                this.scope,
+                this.value,
+                OnConflict::default(),
+                #[cfg(feature = "debug-warn-unused")]
+                this.read,
+            );
+            std::task::ready!(this.inner.poll_next(cx))
+        };
+        // Short-circuit on the first error as the `try_for_each` does.
+        *this.terminated = !matches!(item, Some(Ok(_)));
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.terminated {
+            (0, Some(0))
+        } else {
+            // Any item may be an error, so the stream may end after the first one.
+            let (_, upper) = self.inner.size_hint();
+            (0, upper)
+        }
+    }
+}
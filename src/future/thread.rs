@@ -0,0 +1,247 @@
+//! Scoped futures bound to the threads and the thread locals.
+
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    thread::{self, LocalKey, ThreadId},
+};
+
+use pin_project::{pin_project, pinned_drop};
+
+use super::ScopedFutureWithValue;
+use crate::{imp::FutureLocalKey, FutureOnceCell};
+
+/// A [`Future`] returned by the [`FutureOnceCell::scope_sticky`](crate::FutureOnceCell::scope_sticky)
+/// method.
+///
+/// Unlike the [`ScopedFutureWithValue`] this future doesn't swap the future local value out when
+/// the inner future is suspended. The value is set on the first poll, on the thread that polled
+/// the future, and it is removed only when the future completes or is dropped. Thus the value is
+/// also visible in the `Drop` implementations of the objects that are dropped outside of the
+/// future `poll` calls, including the inner future itself.
+///
+/// # Sharp edges
+///
+/// - While the inner future is suspended, the value is visible to other futures polled on the same
+///   thread, so the isolation between futures is lost.
+///
+/// - The future must be polled and dropped on the same thread, so it's only suitable for the
+///   current-thread runtimes. Polling the future on another thread panics, and dropping it on
+///   another thread leaves the value set on the thread which polled it.
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct StickyScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    inner: Option<F>,
+    scope: &'static FutureLocalKey<T>,
+    value: Option<T>,
+    installed_on: Option<ThreadId>,
+}
+
+impl<T, F> StickyScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(scope: &'static FutureLocalKey<T>, value: T, future: F) -> Self {
+        Self {
+            inner: Some(future),
+            scope,
+            value: Some(value),
+            installed_on: None,
+        }
+    }
+}
+
+#[pinned_drop]
+impl<T, F> PinnedDrop for StickyScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let mut this = self.project();
+        if *this.installed_on == Some(thread::current().id()) {
+            // Drop the inner future while the value is still set, and only then remove it.
+            this.inner.set(None);
+            FutureLocalKey::swap(this.scope, this.value);
+        }
+    }
+}
+
+impl<T, F> Future for StickyScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let current_thread = thread::current().id();
+        if let Some(thread_id) = this.installed_on {
+            assert_eq!(
+                *thread_id, current_thread,
+                "sticky scoped future must not be polled on different threads"
+            );
+        } else {
+            FutureLocalKey::swap(this.scope, this.value);
+            *this.installed_on = Some(current_thread);
+        }
+
+        let result = std::task::ready!(this
+            .inner
+            .as_pin_mut()
+            .expect("`StickyScopedFuture` must not be polled after it returned `Poll::Ready`")
+            .poll(cx));
+        // Remove the value only when the inner future is completed.
+        FutureLocalKey::swap(this.scope, this.value);
+        *this.installed_on = None;
+
+        let value = this.value.take().unwrap();
+        Poll::Ready((value, result))
+    }
+}
+
+/// The inner future of the scoped future returned by the
+/// [`FutureOnceCell::bridge_thread_local`](crate::FutureOnceCell::bridge_thread_local) method.
+///
+/// It sets a clone of the future local value to the thread local before each poll of the inner
+/// future, and if the thread local value has been changed during the poll, writes it back to the
+/// future local afterwards. The previous thread local value is restored after each poll, even if
+/// the inner future panics.
+#[pin_project]
+#[derive(Debug)]
+pub struct ThreadLocalBridge<T, F>
+where
+    T: Send + 'static,
+{
+    #[pin]
+    inner: F,
+    cell: &'static FutureOnceCell<T>,
+    thread_local: &'static LocalKey<RefCell<Option<T>>>,
+}
+
+impl<T, F> ThreadLocalBridge<T, F>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(
+        cell: &'static FutureOnceCell<T>,
+        thread_local: &'static LocalKey<RefCell<Option<T>>>,
+        future: F,
+    ) -> Self {
+        Self {
+            inner: future,
+            cell,
+            thread_local,
+        }
+    }
+}
+
+impl<T, F> Future for ThreadLocalBridge<T, F>
+where
+    T: Clone + PartialEq + Send + 'static,
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let value = this.cell.with(T::clone);
+        let mut guard = ThreadLocalGuard {
+            thread_local: *this.thread_local,
+            entered: true,
+            outer: this
+                .thread_local
+                .with(|slot| slot.replace(Some(value.clone()))),
+        };
+        let result = this.inner.poll(cx);
+
+        let current = guard.exit();
+        if let Some(current) = current.filter(|current| *current != value) {
+            FutureLocalKey::replace((*this.cell).as_ref(), Some(current));
+        }
+        result
+    }
+}
+
+/// Restores the outer value of the thread local on drop.
+struct ThreadLocalGuard<T: 'static> {
+    thread_local: &'static LocalKey<RefCell<Option<T>>>,
+    entered: bool,
+    outer: Option<T>,
+}
+
+impl<T: 'static> ThreadLocalGuard<T> {
+    /// Restores the outer value of the thread local and returns the current one.
+    fn exit(&mut self) -> Option<T> {
+        self.entered = false;
+        let outer = self.outer.take();
+        self.thread_local.with(|slot| slot.replace(outer))
+    }
+}
+
+impl<T: 'static> Drop for ThreadLocalGuard<T> {
+    fn drop(&mut self) {
+        if self.entered {
+            self.exit();
+        }
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_thread_affine`](crate::FutureOnceCell::scope_thread_affine) method.
+///
+/// It records the thread of the first poll, and panics if it's polled on another thread later.
+#[pin_project]
+#[derive(Debug)]
+pub struct ThreadAffineScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    #[pin]
+    future: ScopedFutureWithValue<T, F>,
+    thread_id: Option<ThreadId>,
+}
+
+impl<T, F> ThreadAffineScopedFuture<T, F>
+where
+    T: Send + 'static,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>) -> Self {
+        Self {
+            future,
+            thread_id: None,
+        }
+    }
+}
+
+impl<T, F> Future for ThreadAffineScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, F::Output);
+
+    #[track_caller]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let current_thread = thread::current().id();
+        let thread_id = *this.thread_id.get_or_insert(current_thread);
+        assert_eq!(
+            thread_id, current_thread,
+            "thread affine scoped future must not be polled on different threads"
+        );
+        this.future.poll(cx)
+    }
+}
@@ -0,0 +1,91 @@
+//! Scoped futures catching the panics of the inner future.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use super::ScopedFutureWithValue;
+use crate::ScopeError;
+
+/// A [`Future`] returned by the [`FutureOnceCell::scope_catch`](crate::FutureOnceCell::scope_catch)
+/// method.
+///
+/// This future catches a panic of the inner future and returns it along with the future local
+/// value.
+#[pin_project]
+#[derive(Debug)]
+pub struct CatchUnwind<T, F>(#[pin] ScopedFutureWithValue<T, F>)
+where
+    T: Send + 'static,
+    F: Future;
+
+impl<T, F> CatchUnwind<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    pub(crate) fn new(future: ScopedFutureWithValue<T, F>) -> Self {
+        Self(future)
+    }
+}
+
+impl<T, F> Future for CatchUnwind<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = (T, std::thread::Result<F::Output>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut future = self.project().0;
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll_scoped(cx, Ok))) {
+            Ok(poll) => poll,
+            // At this point the future local value has been already swapped back by the scope guard.
+            Err(payload) => Poll::Ready((future.take_value(), Err(payload))),
+        }
+    }
+}
+
+/// A [`Future`] returned by the
+/// [`FutureOnceCell::scope_no_panic`](crate::FutureOnceCell::scope_no_panic) method.
+///
+/// This future converts a panic of the inner future into the [`ScopeError`] holding the future
+/// local value.
+#[pin_project]
+#[derive(Debug)]
+pub struct NoPanicScopedFuture<T, F>(#[pin] CatchUnwind<T, F>)
+where
+    T: Send + 'static,
+    F: Future;
+
+impl<T, F> NoPanicScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    pub(crate) fn new(future: CatchUnwind<T, F>) -> Self {
+        Self(future)
+    }
+}
+
+impl<T, F> Future for NoPanicScopedFuture<T, F>
+where
+    T: Send,
+    F: Future,
+{
+    type Output = Result<(T, F::Output), ScopeError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (value, result) = std::task::ready!(self.project().0.poll(cx));
+        Poll::Ready(match result {
+            Ok(output) => Ok((value, output)),
+            Err(payload) => Err(ScopeError::new(value, payload)),
+        })
+    }
+}
@@ -0,0 +1,139 @@
+//! Observable lifecycle of scoped futures.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU8, Ordering},
+    Arc,
+};
+
+const RUNNING: u8 = 0;
+const COMPLETED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// The lifecycle state of a scoped future observed by the [`ScopeHandle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeState {
+    /// The scoped future has not been completed or dropped yet.
+    Running,
+    /// The scoped future has been completed.
+    Completed,
+    /// The scoped future has been dropped before completion.
+    Cancelled,
+}
+
+/// A handle which allows to observe the state of a scoped future from outside of it.
+///
+/// The handle is returned by the
+/// [`FutureOnceCell::scope_with_handle`](crate::FutureOnceCell::scope_with_handle) method along
+/// with the scoped future. It can be cloned and sent to other threads, for example, to a
+/// supervisor which tracks whether the request scoped work has been finished.
+#[derive(Debug, Clone)]
+pub struct ScopeHandle(Arc<AtomicU8>);
+
+impl ScopeHandle {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(AtomicU8::new(RUNNING)))
+    }
+
+    /// Returns the current state of the scoped future.
+    #[must_use]
+    pub fn state(&self) -> ScopeState {
+        match self.0.load(Ordering::Acquire) {
+            RUNNING => ScopeState::Running,
+            COMPLETED => ScopeState::Completed,
+            _ => ScopeState::Cancelled,
+        }
+    }
+
+    /// Returns `true` if the scoped future has not been completed or dropped yet.
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.state() == ScopeState::Running
+    }
+
+    /// Returns `true` if the scoped future has been completed.
+    #[must_use]
+    pub fn is_completed(&self) -> bool {
+        self.state() == ScopeState::Completed
+    }
+
+    /// Returns `true` if the scoped future has been dropped before completion.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.state() == ScopeState::Cancelled
+    }
+
+    /// Marks the scoped future as completed.
+    pub(crate) fn complete(&self) {
+        self.0.store(COMPLETED, Ordering::Release);
+    }
+
+    /// Marks the scoped future as cancelled, unless it has been completed.
+    pub(crate) fn cancel(&self) {
+        // The failure means that the future has been already completed, so it is fine to ignore.
+        let _ = self
+            .0
+            .compare_exchange(RUNNING, CANCELLED, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+/// A token which requests cooperative cancellation of a scoped future.
+///
+/// The token is returned by the
+/// [`FutureCancellableCell::scope_cancellable`](crate::FutureCancellableCell::scope_cancellable)
+/// method along with the scoped future, and the code running inside the scope observes the
+/// request by the [`FutureCancellableCell::is_cancelled`](crate::FutureCancellableCell::is_cancelled)
+/// method. The token can be cloned and sent to other threads.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of the scoped future.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the cancellation has been requested.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// A stream of the future local value snapshots returned by the
+/// [`FutureOnceCell::scope_observed`](crate::FutureOnceCell::scope_observed) method.
+///
+/// It yields a clone of the value each time the scoped future is suspended, and the final value
+/// when it completes. The stream ends when the scoped future completes or is dropped. It
+/// implements the `Stream` trait if the `stream` feature is enabled.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct ScopeSnapshots<T>(tokio::sync::mpsc::UnboundedReceiver<T>);
+
+#[cfg(feature = "tokio")]
+impl<T> ScopeSnapshots<T> {
+    pub(crate) fn new(receiver: tokio::sync::mpsc::UnboundedReceiver<T>) -> Self {
+        Self(receiver)
+    }
+
+    /// Receives the next snapshot, or returns `None` if the scoped future has completed or has
+    /// been dropped, and all the snapshots have been received.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.0.recv().await
+    }
+}
+
+#[cfg(all(feature = "tokio", feature = "stream"))]
+impl<T> futures_core::Stream for ScopeSnapshots<T> {
+    type Item = T;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<T>> {
+        self.0.poll_recv(cx)
+    }
+}
@@ -1,23 +1,108 @@
 //! Low-level implementation details.
 
-use std::{cell::RefCell, fmt::Debug};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+    marker::PhantomData,
+};
 
 use state::LocalInitCell;
 
+use crate::{
+    future::OnConflict,
+    subscriber::{self, ScopeEventKind},
+};
+
 /// A type wrapper that provides interior mutability and allows for safe and efficient access to an
 /// optional value stored in a cell.
 pub type LocalKey<T> = RefCell<Option<T>>;
 
+/// The per-thread state of a future local, which is stored by a [`ThreadLocalProvider`].
+///
+/// This type is opaque, it is only exposed to allow declaring the custom providers.
+pub struct LocalState<T> {
+    value: LocalKey<T>,
+    /// Whether a scoped future of this key is being polled right now.
+    polling: Cell<bool>,
+    /// The number of the scopes of this key entered on the current thread.
+    depth: Cell<usize>,
+    /// Whether the value has been read since the last [`FutureLocalKey::replace_read`] call.
+    #[cfg(feature = "debug-warn-unused")]
+    read: Cell<bool>,
+}
+
+impl<T> LocalState<T> {
+    fn new() -> Self {
+        Self {
+            value: RefCell::new(None),
+            polling: Cell::new(false),
+            depth: Cell::new(0),
+            #[cfg(feature = "debug-warn-unused")]
+            read: Cell::new(false),
+        }
+    }
+}
+
+impl<T: Debug> Debug for LocalState<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LocalState").field(&self.value).finish()
+    }
+}
+
+/// A thread local storage backend of the future locals.
+///
+/// By default, the future locals keep their per-thread state in the thread local storage
+/// provided by the [`state`](https://docs.rs/state) crate. This trait allows to replace it by
+/// another implementation, for example, by a faster one, see the
+/// [`FutureOnceCell::with_provider`](crate::FutureOnceCell::with_provider) method.
+///
+/// The implementation must return a distinct value for each thread, otherwise the future locals
+/// will be shared between the futures polled on the different threads.
+pub trait ThreadLocalProvider<V> {
+    /// Returns a reference to the value of the current thread, and if it has not been
+    /// initialized, initializes it by the given function.
+    fn get_or_init(init: fn() -> V) -> &'static V;
+}
+
+/// A function which returns the per-thread state of a custom provider.
+///
+/// The lifetime of the returned reference is bound to the token, which is always `'static` in
+/// practice, to avoid the `'static` bound on the value type in the [`FutureLocalKey`] type.
+type GetOrInit<T> = fn(&(), fn() -> LocalState<T>) -> &LocalState<T>;
+
+fn get_or_init<T, P>(_token: &(), init: fn() -> LocalState<T>) -> &LocalState<T>
+where
+    T: 'static,
+    P: ThreadLocalProvider<LocalState<T>>,
+{
+    P::get_or_init(init)
+}
+
+enum Backend<T> {
+    Builtin(LocalInitCell<LocalState<T>>),
+    Custom(GetOrInit<T>),
+}
+
 /// A future local storage key which owns its content.
 ///
 /// It uses thread local storage to ensure that the each polled future has its own local storage key.
-pub struct FutureLocalKey<T>(LocalInitCell<LocalKey<T>>);
+pub struct FutureLocalKey<T>(Backend<T>);
 
 impl<T> FutureLocalKey<T> {
     /// Creates an empty future local key.
     #[inline]
     pub const fn new() -> Self {
-        Self(LocalInitCell::new())
+        Self(Backend::Builtin(LocalInitCell::new()))
+    }
+
+    /// Creates an empty future local key, which keeps its per-thread state in the given provider.
+    #[inline]
+    pub const fn with_provider<P>() -> Self
+    where
+        T: 'static,
+        P: ThreadLocalProvider<LocalState<T>>,
+    {
+        Self(Backend::Custom(get_or_init::<T, P>))
     }
 }
 
@@ -29,6 +114,19 @@ impl<T> Default for FutureLocalKey<T> {
 }
 
 impl<T: Send + 'static> FutureLocalKey<T> {
+    /// Returns a reference to the per-thread state of this key, and if it has not been
+    /// initialized, initializes it.
+    #[inline]
+    fn state(&'static self) -> &'static LocalState<T> {
+        match &self.0 {
+            Backend::Builtin(cell) => {
+                cell.set(LocalState::new);
+                cell.get()
+            }
+            Backend::Custom(get_or_init) => get_or_init(&(), LocalState::new),
+        }
+    }
+
     /// Returns a reference to the underlying thread local storage key, and if it has not been initialized,
     /// initializes it with the `None` value.
     ///
@@ -38,8 +136,7 @@ impl<T: Send + 'static> FutureLocalKey<T> {
     /// thread local key.
     #[inline]
     pub fn local_key(&'static self) -> &'static LocalKey<T> {
-        self.0.set(|| RefCell::new(None));
-        self.0.get()
+        &self.state().value
     }
 
     /// Swaps the underlying value and the given one, without deinitializing either one.
@@ -47,11 +144,231 @@ impl<T: Send + 'static> FutureLocalKey<T> {
     pub fn swap(this: &'static Self, other: &mut Option<T>) {
         std::mem::swap(other, &mut *this.local_key().borrow_mut());
     }
+
+    /// Replaces the underlying value by the given one, returning the previous value.
+    #[inline]
+    pub fn replace(this: &'static Self, value: Option<T>) -> Option<T> {
+        this.local_key().replace(value)
+    }
+
+    /// Returns `true` if the underlying value is set.
+    #[inline]
+    pub fn is_set(this: &'static Self) -> bool {
+        this.local_key().borrow().is_some()
+    }
+
+    /// Returns `true` if a scoped future of this key is being polled on the current thread.
+    #[inline]
+    pub fn is_polling(this: &'static Self) -> bool {
+        this.state().polling.get()
+    }
+
+    /// Replaces the flag which indicates that a scoped future of this key is being polled on the
+    /// current thread, returning the previous flag.
+    #[inline]
+    pub fn replace_polling(this: &'static Self, polling: bool) -> bool {
+        this.state().polling.replace(polling)
+    }
+
+    /// Returns the number of the scopes of this key entered on the current thread.
+    #[inline]
+    pub fn depth(this: &'static Self) -> usize {
+        this.state().depth.get()
+    }
+
+    /// Increments the number of the scopes of this key entered on the current thread.
+    #[inline]
+    fn enter_depth(this: &'static Self) {
+        let depth = &this.state().depth;
+        depth.set(depth.get() + 1);
+    }
+
+    /// Decrements the number of the scopes of this key entered on the current thread.
+    #[inline]
+    fn exit_depth(this: &'static Self) {
+        let depth = &this.state().depth;
+        depth.set(depth.get() - 1);
+    }
+
+    /// Marks the underlying value as read on the current thread.
+    #[cfg(feature = "debug-warn-unused")]
+    #[inline]
+    pub fn mark_read(&'static self) {
+        self.state().read.set(true);
+    }
+
+    /// Replaces the read flag of the underlying value by the given one, returning the previous
+    /// flag.
+    #[cfg(feature = "debug-warn-unused")]
+    #[inline]
+    pub fn replace_read(this: &'static Self, read: bool) -> bool {
+        this.state().read.replace(read)
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<T>> for FutureLocalKey<T> {
+    fn as_ref(&self) -> &FutureLocalKey<T> {
+        self
+    }
 }
 
 impl<T: Send + 'static + Debug> Debug for FutureLocalKey<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("FutureLocalKey").field(&self.0).finish()
+        let mut tuple = f.debug_tuple("FutureLocalKey");
+        match &self.0 {
+            Backend::Builtin(cell) => tuple.field(cell),
+            Backend::Custom(get_or_init) => tuple.field(get_or_init(&(), LocalState::new)),
+        };
+        tuple.finish()
+    }
+}
+
+/// A guard that keeps the future local value of a scoped future set while it is alive.
+///
+/// The value is swapped back to the scoped future when the guard is dropped, even if the code
+/// running within the guard panics.
+pub struct ScopeGuard<'a, T: Send + 'static> {
+    scope: &'static FutureLocalKey<T>,
+    value: &'a mut Option<T>,
+    outer: Option<T>,
+    policy: OnConflict,
+    outer_polling: bool,
+    #[cfg(feature = "debug-warn-unused")]
+    read: &'a mut bool,
+    #[cfg(feature = "debug-warn-unused")]
+    outer_read: bool,
+    /// The guard must not be sent to another thread, since the value is set in the thread local
+    /// storage of the current one.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T: Send + 'static> ScopeGuard<'a, T> {
+    pub(crate) fn enter(
+        scope: &'static FutureLocalKey<T>,
+        value: &'a mut Option<T>,
+        policy: OnConflict,
+        #[cfg(feature = "debug-warn-unused")] read: &'a mut bool,
+    ) -> Self {
+        let mut outer = None;
+        match policy {
+            OnConflict::Overwrite => FutureLocalKey::swap(scope, value),
+            OnConflict::Panic => {
+                assert!(
+                    !FutureLocalKey::is_polling(scope),
+                    "reentrant scope: a scoped future is polled inside a scoped future of the \
+                     same future local, future local value is already set by an outer scope"
+                );
+                assert!(
+                    !FutureLocalKey::is_set(scope),
+                    "future local value is already set by an outer scope"
+                );
+                FutureLocalKey::swap(scope, value);
+            }
+            OnConflict::Nest => outer = FutureLocalKey::replace(scope, value.take()),
+        }
+        let outer_polling = FutureLocalKey::replace_polling(scope, true);
+        FutureLocalKey::enter_depth(scope);
+        subscriber::emit(scope, ScopeEventKind::Enter);
+
+        Self {
+            scope,
+            value,
+            outer,
+            policy,
+            outer_polling,
+            // Reset the read flag, the outer one will be restored by the guard.
+            #[cfg(feature = "debug-warn-unused")]
+            outer_read: FutureLocalKey::replace_read(scope, false),
+            #[cfg(feature = "debug-warn-unused")]
+            read,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + 'static> Debug for ScopeGuard<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopeGuard")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> Drop for ScopeGuard<'_, T> {
+    fn drop(&mut self) {
+        match self.policy {
+            OnConflict::Overwrite | OnConflict::Panic => {
+                FutureLocalKey::swap(self.scope, self.value);
+            }
+            OnConflict::Nest => {
+                *self.value = FutureLocalKey::replace(self.scope, self.outer.take());
+            }
+        }
+        FutureLocalKey::replace_polling(self.scope, self.outer_polling);
+        FutureLocalKey::exit_depth(self.scope);
+        subscriber::emit(self.scope, ScopeEventKind::Exit);
+        #[cfg(feature = "debug-warn-unused")]
+        {
+            *self.read |= FutureLocalKey::replace_read(self.scope, self.outer_read);
+        }
+    }
+}
+
+/// A guard that keeps the future local value set on the current thread while it is alive.
+///
+/// The guard is returned by the [`FutureOnceCell::enter`](crate::FutureOnceCell::enter) method.
+/// The value is removed when the guard is dropped or [exited](ScopeEntered::exit), and the value
+/// of the outer scope, if any, is restored.
+#[must_use = "the future local value is removed as soon as the guard is dropped"]
+pub struct ScopeEntered<T: Send + 'static> {
+    scope: &'static FutureLocalKey<T>,
+    outer: Option<T>,
+    /// The guard must not be sent to another thread, since the value is set in the thread local
+    /// storage of the current one.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T: Send + 'static> ScopeEntered<T> {
+    pub(crate) fn enter(scope: &'static FutureLocalKey<T>, value: T) -> Self {
+        let outer = FutureLocalKey::replace(scope, Some(value));
+        FutureLocalKey::enter_depth(scope);
+        subscriber::emit(scope, ScopeEventKind::Enter);
+        Self {
+            scope,
+            outer,
+            _not_send: PhantomData,
+        }
+    }
+
+    fn restore(&mut self) -> Option<T> {
+        let value = FutureLocalKey::replace(self.scope, self.outer.take());
+        FutureLocalKey::exit_depth(self.scope);
+        subscriber::emit(self.scope, ScopeEventKind::Exit);
+        value
+    }
+
+    /// Removes the future local value and returns it.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the value has been taken out of the future local storage
+    /// inside the entered scope.
+    pub fn exit(self) -> T {
+        let mut this = std::mem::ManuallyDrop::new(self);
+        this.restore()
+            .expect("the future local value has been taken out of the entered scope")
+    }
+}
+
+impl<T: Send + 'static> Debug for ScopeEntered<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopeEntered").finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> Drop for ScopeEntered<T> {
+    fn drop(&mut self) {
+        self.restore();
     }
 }
 
@@ -0,0 +1,158 @@
+//! Concurrent composition of the scoped futures.
+
+use std::future::Future;
+
+use crate::{
+    future::{JoinScopes, TryJoinScopes},
+    ScopedFutureWithValue,
+};
+
+/// Polls both scoped futures concurrently and waits for both of them to complete.
+///
+/// The joined future returns the values and outputs of both scoped futures as the
+/// `((T1, F1::Output), (T2, F2::Output))` tuple, so they don't have to be unpacked from the
+/// nested tuples manually.
+///
+/// ```rust
+/// use future_local_storage::{join_scopes, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static USER: FutureOnceCell<&str> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let ((request_id, id), (user, len)) = join_scopes((
+///         REQUEST_ID.scope(42, async { REQUEST_ID.get() }),
+///         USER.scope("alice", async { USER.get().len() }),
+///     ))
+///     .await;
+///
+///     assert_eq!((request_id, id), (42, 42));
+///     assert_eq!((user, len), ("alice", 5));
+/// }
+/// ```
+pub fn join_scopes<T1, F1, T2, F2>(
+    (first, second): (ScopedFutureWithValue<T1, F1>, ScopedFutureWithValue<T2, F2>),
+) -> JoinScopes<T1, F1, T2, F2>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future,
+    F2: Future,
+{
+    JoinScopes::new(first, second)
+}
+
+/// Polls both fallible scoped futures concurrently and waits for both of them to complete
+/// successfully.
+///
+/// If one of the futures returns an error, the other one is cancelled, and the joined future
+/// returns the [`TryJoinError`](crate::TryJoinError), which keeps the error along with the future
+/// local values of both scoped futures. If both futures fail in the same poll, the error of the
+/// first one is returned.
+///
+/// ```rust
+/// use std::cell::RefCell;
+///
+/// use future_local_storage::{try_join_scopes, FutureOnceCell};
+///
+/// static TRACES: FutureOnceCell<RefCell<Vec<&str>>> = FutureOnceCell::new();
+///
+/// async fn step(name: &'static str, fail: bool) -> Result<(), String> {
+///     TRACES.with(|traces| traces.borrow_mut().push(name));
+///     if fail {
+///         return Err(format!("{name} failed"));
+///     }
+///     std::future::pending().await
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let error = try_join_scopes((
+///         TRACES.scope(RefCell::default(), step("fetch", false)),
+///         TRACES.scope(RefCell::default(), step("store", true)),
+///     ))
+///     .await
+///     .unwrap_err();
+///
+///     let (error, (fetch, store)) = error.into_parts();
+///     assert_eq!(error, "store failed");
+///     assert_eq!(fetch.into_inner(), vec!["fetch"]);
+///     assert_eq!(store.into_inner(), vec!["store"]);
+/// }
+/// ```
+pub fn try_join_scopes<T1, F1, T2, F2, R1, R2, E>(
+    (first, second): (ScopedFutureWithValue<T1, F1>, ScopedFutureWithValue<T2, F2>),
+) -> TryJoinScopes<T1, F1, T2, F2, R1, R2, E>
+where
+    T1: Send + 'static,
+    T2: Send + 'static,
+    F1: Future<Output = Result<R1, E>>,
+    F2: Future<Output = Result<R2, E>>,
+{
+    TryJoinScopes::new(first, second)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::FutureOnceCell;
+
+    #[tokio::test]
+    async fn test_join_scopes_concurrent_completion() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let ((first, first_output), (second, second_output)) = join_scopes((
+            VALUE.scope(1, async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                VALUE.get()
+            }),
+            VALUE.scope(2, async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                VALUE.get()
+            }),
+        ))
+        .await;
+
+        assert_eq!((first, first_output), (1, 1));
+        assert_eq!((second, second_output), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_try_join_scopes_recovers_completed_value() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let error = try_join_scopes((
+            VALUE.scope(1, async { Ok::<_, &str>(VALUE.get()) }),
+            VALUE.scope(2, async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Err::<(), _>("failed")
+            }),
+        ))
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.error(), &"failed");
+        assert_eq!(error.values(), (&1, &2));
+    }
+
+    #[tokio::test]
+    async fn test_try_join_scopes_ok() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let output = try_join_scopes((
+            VALUE.scope(1, async { Ok::<_, ()>(VALUE.get() * 10) }),
+            VALUE.scope(2, async {
+                tokio::task::yield_now().await;
+                Ok(VALUE.get() * 10)
+            }),
+        ))
+        .await;
+
+        assert_eq!(output.unwrap(), ((1, 10), (2, 20)));
+    }
+}
@@ -0,0 +1,180 @@
+//! A collection of the spawned scoped tasks.
+
+use std::{fmt::Debug, future::Future};
+
+use tokio::task::{JoinError, JoinSet};
+
+use crate::FutureOnceCell;
+
+/// A collection of the scoped tasks spawned on the Tokio runtime, each of which is tagged with a
+/// key `K`.
+///
+/// Each task is scoped by its own value of the future local `T`, which is returned along with the
+/// key and the task output on completion. This makes the "collect the context of all tasks"
+/// pattern first-class.
+///
+/// ```rust
+/// use std::cell::RefCell;
+///
+/// use future_local_storage::{FutureOnceCell, ScopedJoinSet};
+///
+/// static TRACES: FutureOnceCell<RefCell<Vec<String>>> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut set = ScopedJoinSet::new(&TRACES);
+///     for name in ["c", "a", "b"] {
+///         set.spawn(name, RefCell::default(), async move {
+///             TRACES.with(|traces| traces.borrow_mut().push(format!("{name} done")));
+///             name.len()
+///         });
+///     }
+///
+///     let results = set.join_all_sorted().await.unwrap();
+///     let traces = results
+///         .into_iter()
+///         .map(|(name, traces, _)| (name, traces.into_inner()))
+///         .collect::<Vec<_>>();
+///     assert_eq!(
+///         traces,
+///         vec![
+///             ("a", vec!["a done".to_owned()]),
+///             ("b", vec!["b done".to_owned()]),
+///             ("c", vec!["c done".to_owned()]),
+///         ]
+///     );
+/// }
+/// ```
+pub struct ScopedJoinSet<K, T, O>
+where
+    T: Send + 'static,
+{
+    scope: &'static FutureOnceCell<T>,
+    tasks: JoinSet<(K, T, O)>,
+}
+
+impl<K, T, O> ScopedJoinSet<K, T, O>
+where
+    K: Send + 'static,
+    T: Send + 'static,
+    O: Send + 'static,
+{
+    /// Creates an empty set of the tasks scoped by the given future local.
+    #[must_use]
+    pub fn new(scope: &'static FutureOnceCell<T>) -> Self {
+        Self {
+            scope,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Spawns the future `F` scoped by the given value on the current Tokio runtime, and tags it
+    /// with the given key.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if called outside of a Tokio runtime.
+    pub fn spawn<F>(&mut self, key: K, value: T, future: F)
+    where
+        F: Future<Output = O> + Send + 'static,
+    {
+        let future = self.scope.scope(value, future);
+        self.tasks.spawn(async move {
+            let (value, output) = future.await;
+            (key, value, output)
+        });
+    }
+
+    /// Waits until one of the tasks completes and returns its key, future local value and
+    /// output.
+    ///
+    /// Returns `None` if the set is empty. If the task panics or is aborted, the key and value
+    /// of the task are lost and the [`JoinError`] is returned.
+    pub async fn join_next(&mut self) -> Option<Result<(K, T, O), JoinError>> {
+        self.tasks.join_next().await
+    }
+
+    /// Waits until all the tasks complete and returns their results sorted by the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first [`JoinError`] if any of the tasks panics or is aborted, the remaining
+    /// tasks are aborted in this case.
+    pub async fn join_all_sorted(mut self) -> Result<Vec<(K, T, O)>, JoinError>
+    where
+        K: Ord,
+    {
+        let mut results = Vec::with_capacity(self.tasks.len());
+        while let Some(result) = self.tasks.join_next().await {
+            results.push(result?);
+        }
+        results.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        Ok(results)
+    }
+
+    /// Aborts all the tasks in this set.
+    pub fn abort_all(&mut self) {
+        self.tasks.abort_all();
+    }
+
+    /// Returns the number of the tasks in this set.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if this set contains no tasks.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+impl<K, T, O> Debug for ScopedJoinSet<K, T, O>
+where
+    T: Send + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopedJoinSet")
+            .field("len", &self.tasks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_scoped_join_set_collects_values() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut set = ScopedJoinSet::new(&VALUE);
+        for key in (0..8).rev() {
+            set.spawn(key, key * 10, async move {
+                tokio::task::yield_now().await;
+                VALUE.get() + key
+            });
+        }
+        assert_eq!(set.len(), 8);
+
+        let results = set.join_all_sorted().await.unwrap();
+        let expected = (0..8)
+            .map(|key| (key, key * 10, key * 11))
+            .collect::<Vec<_>>();
+        assert_eq!(results, expected);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_join_set_join_next() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut set = ScopedJoinSet::new(&VALUE);
+        set.spawn("answer", 42, async { VALUE.get() });
+        assert_eq!(set.join_next().await.unwrap().unwrap(), ("answer", 42, 42));
+        assert!(set.join_next().await.is_none());
+        assert!(set.is_empty());
+    }
+}
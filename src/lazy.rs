@@ -0,0 +1,362 @@
+//! Lazily initialized future local storage.
+
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt::Debug,
+    future::Future,
+};
+
+use crate::{
+    future::ScopedFutureWithValue, imp::FutureLocalKey, AccessError, FutureLocalStorage,
+    FutureLocalValue,
+};
+
+/// A future local value which is lazily initialized on the first access within a given scope.
+///
+/// Unlike the [`std::thread::LocalKey`], which initializes the value once per thread and thus
+/// shares it between all futures polled on the same thread, this cell initializes the value once
+/// per scoped future. Each future scoped by the [`FutureScopedLazy::scope`] gets its own value,
+/// which is created by the `init` function on the first access within this future, even if it is
+/// polled on the same thread as other ones.
+///
+/// After the execution finished the scoped future returns the [`OnceCell`] with the value, if it
+/// has been initialized.
+///
+/// ```rust
+/// use future_local_storage::FutureScopedLazy;
+///
+/// static REQUEST_ID: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 42);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (value, output) = REQUEST_ID.scope(async { REQUEST_ID.with(|id| *id + 1) }).await;
+///     assert_eq!(value.into_inner(), Some(42));
+///     assert_eq!(output, 43);
+///
+///     // The value is not initialized if it is not accessed.
+///     let (value, ()) = REQUEST_ID.scope(async {}).await;
+///     assert_eq!(value.into_inner(), None);
+/// }
+/// ```
+pub struct FutureScopedLazy<T> {
+    key: FutureLocalKey<OnceCell<T>>,
+    init: Init<T>,
+}
+
+/// An initializing function of the [`FutureScopedLazy`].
+enum Init<T> {
+    Fn(fn() -> T),
+    Boxed(Box<dyn Fn() -> T + Send + Sync>),
+}
+
+impl<T> Init<T> {
+    fn call(&self) -> T {
+        match self {
+            Self::Fn(init) => init(),
+            Self::Boxed(init) => init(),
+        }
+    }
+}
+
+impl<T> FutureScopedLazy<T> {
+    /// Creates a new lazy future local with the given initializing function.
+    #[must_use]
+    pub const fn new(init: fn() -> T) -> Self
+    where
+        T: FutureLocalValue,
+    {
+        Self {
+            key: FutureLocalKey::new(),
+            init: Init::Fn(init),
+        }
+    }
+
+    /// Creates a new lazy future local with the given boxed initializing closure.
+    ///
+    /// Unlike the [`FutureScopedLazy::new`], this constructor allows the initializer to capture
+    /// its environment, but it can't be used in the `const` context. The methods of this type
+    /// require a `'static` reference, so the runtime constructed instance has to be stored in a
+    /// static, for example, in the [`OnceLock`](std::sync::OnceLock), or leaked.
+    ///
+    /// ```rust
+    /// use std::sync::OnceLock;
+    ///
+    /// use future_local_storage::FutureScopedLazy;
+    ///
+    /// static GREETING: OnceLock<FutureScopedLazy<String>> = OnceLock::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let name = "rust".to_owned();
+    ///     let greeting = GREETING.get_or_init(|| {
+    ///         FutureScopedLazy::from_boxed_fn(Box::new(move || format!("hello, {name}")))
+    ///     });
+    ///
+    ///     let (_, output) = greeting.scope(async { greeting.with(String::clone) }).await;
+    ///     assert_eq!(output, "hello, rust");
+    /// }
+    /// ```
+    #[must_use]
+    pub fn from_boxed_fn(init: Box<dyn Fn() -> T + Send + Sync>) -> Self
+    where
+        T: FutureLocalValue,
+    {
+        Self {
+            key: FutureLocalKey::new(),
+            init: Init::Boxed(init),
+        }
+    }
+}
+
+impl<T: Send + 'static> FutureScopedLazy<T> {
+    /// Acquires a reference to the value in this future local storage, and if it has not been
+    /// initialized in the current scope, initializes it.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the scope of this future local.
+    #[inline]
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.key.mark_read();
+        let cell = self.key.local_key().borrow();
+        let cell = cell
+            .as_ref()
+            .expect("cannot access a lazy future local value outside of its scope");
+        f(cell.get_or_init(|| self.init.call()))
+    }
+
+    /// Returns a copy of the value in this future local storage, and if it has not been
+    /// initialized in the current scope, initializes it.
+    ///
+    /// The read costs about the same as the [`FutureCopyCell::get`](crate::FutureCopyCell::get),
+    /// since it is dominated by the thread local lookup rather than by the borrow tracking, see
+    /// the `lazy_get` benchmark.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if it's called outside of the scope of this future local.
+    #[inline]
+    #[track_caller]
+    pub fn get(&'static self) -> T
+    where
+        T: Copy,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.key.mark_read();
+        *self
+            .key
+            .local_key()
+            .borrow()
+            .as_ref()
+            .expect("cannot access a lazy future local value outside of its scope")
+            .get_or_init(|| self.init.call())
+    }
+
+    /// Returns a clone of the value in the current scope, or the [`Default`] value if it is called
+    /// outside of the scope or the value has not been initialized yet.
+    ///
+    /// Unlike the [`Self::with`] this method never initializes the value.
+    #[inline]
+    #[must_use]
+    pub fn get_or_default(&'static self) -> T
+    where
+        T: Default + Clone,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.key.mark_read();
+        self.key
+            .local_key()
+            .borrow()
+            .as_ref()
+            .and_then(OnceCell::get)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns a clone of the value in the current scope, or the [`AccessError`] if it is called
+    /// outside of the scope or the value has not been initialized yet.
+    ///
+    /// Unlike the [`Self::with`] this method never initializes the value.
+    #[inline]
+    pub fn current(&'static self) -> Result<T, AccessError>
+    where
+        T: Clone,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.key.mark_read();
+        self.key
+            .local_key()
+            .borrow()
+            .as_ref()
+            .and_then(OnceCell::get)
+            .cloned()
+            .ok_or(AccessError)
+    }
+
+    /// Returns `true` if the value in the current scope has been initialized, and it is equal to
+    /// the `expected` one.
+    ///
+    /// Unlike the [`Self::with`] this method never initializes the value.
+    #[inline]
+    #[must_use]
+    pub fn current_eq(&'static self, expected: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.key.mark_read();
+        self.key
+            .local_key()
+            .borrow()
+            .as_ref()
+            .and_then(OnceCell::get)
+            == Some(expected)
+    }
+
+    /// Returns a reference to the underlying thread local storage of this future local.
+    ///
+    /// This is a low-level escape hatch, see the
+    /// [`FutureOnceCell::raw_local_key`](crate::FutureOnceCell::raw_local_key) for the invariants
+    /// that the code using it must respect.
+    #[inline]
+    #[must_use]
+    pub fn raw_local_key(&'static self) -> &'static RefCell<Option<OnceCell<T>>> {
+        self.key.local_key()
+    }
+
+    /// Creates a new scope of this future local for the future `F`.
+    ///
+    /// The value will be initialized on the first access within the future `F`. On completion
+    /// the scoped future returns the [`OnceCell`] along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, future: F) -> ScopedFutureWithValue<OnceCell<T>, F>
+    where
+        F: Future,
+    {
+        future.with_scope(self, OnceCell::new())
+    }
+}
+
+impl<T: Debug + Send + 'static> Debug for FutureScopedLazy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureScopedLazy").field(&self.key).finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<OnceCell<T>>> for FutureScopedLazy<T> {
+    fn as_ref(&self) -> &FutureLocalKey<OnceCell<T>> {
+        &self.key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scoped_lazy_init_once_per_scope() {
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: FutureScopedLazy<usize> =
+            FutureScopedLazy::new(|| INITS.fetch_add(1, Ordering::SeqCst));
+
+        let future = || {
+            VALUE.scope(async {
+                let first = VALUE.with(|x| *x);
+                tokio::task::yield_now().await;
+                assert_eq!(VALUE.with(|x| *x), first);
+                first
+            })
+        };
+        // Both futures are polled on the same thread, but each of them has its own value.
+        let ((first, _), (second, _)) = futures_util::future::join(future(), future()).await;
+
+        assert_eq!(INITS.load(Ordering::SeqCst), 2);
+        assert_ne!(first.into_inner(), second.into_inner());
+    }
+
+    #[tokio::test]
+    async fn test_scoped_lazy_from_boxed_fn() {
+        let inits = std::sync::Arc::new(AtomicUsize::new(0));
+        let value: &'static FutureScopedLazy<usize> = Box::leak(Box::new({
+            let inits = inits.clone();
+            FutureScopedLazy::from_boxed_fn(Box::new(move || {
+                inits.fetch_add(1, Ordering::SeqCst) + 10
+            }))
+        }));
+
+        let (first, output) = value.scope(async { value.with(|x| *x) }).await;
+        assert_eq!(first.into_inner(), Some(10));
+        assert_eq!(output, 10);
+        let (second, ()) = value.scope(async { value.with(|_| {}) }).await;
+        assert_eq!(second.into_inner(), Some(11));
+        assert_eq!(inits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_lazy_get_or_default() {
+        static VALUE: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 42);
+
+        assert_eq!(VALUE.get_or_default(), 0);
+        let (value, (before, after)) = VALUE
+            .scope(async {
+                let before = VALUE.get_or_default();
+                VALUE.with(|_| {});
+                (before, VALUE.get_or_default())
+            })
+            .await;
+        // The value is not initialized by the `get_or_default` call.
+        assert_eq!((before, after), (0, 42));
+        assert_eq!(value.into_inner(), Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_lazy_current() {
+        static VALUE: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 42);
+
+        assert_eq!(VALUE.current(), Err(AccessError));
+        let (value, (before, after)) = VALUE
+            .scope(async {
+                let before = VALUE.current();
+                VALUE.with(|_| {});
+                (before, VALUE.current())
+            })
+            .await;
+        // The value is not initialized by the `current` call.
+        assert_eq!(before, Err(AccessError));
+        assert_eq!(after, Ok(42));
+        assert_eq!(value.into_inner(), Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_lazy_current_eq() {
+        static VALUE: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 42);
+
+        assert!(!VALUE.current_eq(&42));
+        let (value, ()) = VALUE
+            .scope(async {
+                // The value is not initialized by the `current_eq` call.
+                assert!(!VALUE.current_eq(&42));
+                VALUE.with(|_| {});
+                assert!(VALUE.current_eq(&42));
+            })
+            .await;
+        assert_eq!(value.into_inner(), Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_lazy_get_copy() {
+        static VALUE: FutureScopedLazy<u64> = FutureScopedLazy::new(|| 42);
+
+        let (value, output) = VALUE.scope(async { VALUE.get() + VALUE.get() }).await;
+        assert_eq!(value.into_inner(), Some(42));
+        assert_eq!(output, 84);
+    }
+}
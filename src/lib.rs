@@ -27,14 +27,102 @@
 //!     println!("trace: {trace:#?}");
 //! }
 //! ```
+//!
+//! ### Request extensions
+//!
+//! A value from the request metadata or extensions of a `tonic` service, or any other [`http`]
+//! service, can be mirrored into a future local by the `RequestScopeLayer` middleware, which
+//! requires the `tonic` feature. The handler future is scoped, so the value is accessible deep
+//! in the handler without passing the request around.
 
-use std::{fmt::Debug, future::Future};
+use std::{cell::RefCell, fmt::Debug, future::Future, ops::Deref, pin::Pin};
 
-use future::ScopedFutureWithValue;
+pub use any_collector::{AnyCollection, FutureAnyCollector};
+pub use arc::FutureArcCell;
+#[cfg(feature = "baggage")]
+pub use baggage::{BaggageCarrier, BAGGAGE_HEADER};
+pub use borrow::FutureRef;
+pub use builder::{ScopeBuilder, ScopeReport};
+pub use cancel::FutureCancellableCell;
+pub use collector::FutureCollector;
+pub use context::{register_error_context, with_context, ContextualError};
+pub use copy::FutureCopyCell;
+pub use deadline::FutureDeadline;
+pub use deferred::DeferredScope;
+#[cfg(feature = "baggage")]
+pub use error::BaggageError;
+pub use error::{AccessError, ScopeError, ScopeMapError, TryJoinError};
+pub use extensions::FutureExtensions;
+#[cfg(feature = "stream")]
+pub use future::ScopedStream;
+#[cfg(feature = "tracing")]
+use future::TracedScopedFuture;
+#[cfg(feature = "stream")]
+use future::TryScopedStream;
+use future::{
+    AsyncFinalizer, BorrowedScopedFuture, BoxedInitScopedFuture, CatchUnwind, LinkParent,
+    MergedScopedFuture, NoPanicScopedFuture, OnPendingScopedFuture, ReplaceScope,
+    RetryScopedFuture, SlotScopedFuture, StickyScopedFuture, ThreadAffineScopedFuture,
+    ThreadLocalBridge, WithScopeHandle,
+};
+#[cfg(feature = "tokio")]
+use future::{ChannelScopedFuture, LimitedScopedFuture, ObservedScopedFuture};
+pub use future::{OnConflict, ScopedFuture, ScopedFutureWithValue};
+#[cfg(feature = "macros")]
+pub use future_local_storage_macros::future_local;
+#[cfg(feature = "derive")]
+pub use future_local_storage_macros::FutureLocal;
+#[cfg(feature = "tokio")]
+pub use handle::ScopeSnapshots;
+pub use handle::{CancelToken, ScopeHandle, ScopeState};
 use imp::FutureLocalKey;
+pub use imp::{LocalState, ScopeEntered, ThreadLocalProvider};
+pub use join::{join_scopes, try_join_scopes};
+#[cfg(feature = "tokio")]
+pub use join_set::ScopedJoinSet;
+pub use lazy::FutureScopedLazy;
+pub use link::FutureLinkCell;
+#[cfg(feature = "parking_lot")]
+pub use mutex::FutureMutexLock;
+pub use registry::{register_named_cell, scope_from_map};
+#[cfg(feature = "tonic")]
+pub use request_scope::{RequestScopeLayer, RequestScopeService};
+pub use subscriber::{register_scope_subscriber, ScopeEvent, ScopeEventKind};
+pub use task_scope::{future_scope, FutureScope, ScopedTaskHandle};
+#[cfg(feature = "tokio")]
+pub use watch::FutureWatchCell;
 
+mod any_collector;
+mod arc;
+#[cfg(feature = "baggage")]
+mod baggage;
+mod borrow;
+mod builder;
+mod cancel;
+mod collector;
+mod context;
+mod copy;
+mod deadline;
+mod deferred;
+mod error;
+pub mod extensions;
 pub mod future;
+mod handle;
 mod imp;
+mod join;
+#[cfg(feature = "tokio")]
+mod join_set;
+mod lazy;
+mod link;
+#[cfg(feature = "parking_lot")]
+mod mutex;
+mod registry;
+#[cfg(feature = "tonic")]
+mod request_scope;
+mod subscriber;
+mod task_scope;
+#[cfg(feature = "tokio")]
+mod watch;
 
 /// An init-once-per-future cell for thread-local values.
 ///
@@ -44,18 +132,102 @@ mod imp;
 /// by an executor.
 ///
 /// After the execution finished the value moves from the future local cell to the future output.
+///
+/// # Moving values
+///
+/// The value is moved between the scoped future and the thread local storage on every poll and
+/// into the future output on completion, so its address isn't stable, even if the type is
+/// [`!Unpin`](Unpin). The value itself is never
+/// pinned, so this is sound, but an address-sensitive value must be stored behind a pinned
+/// pointer, like the `Pin<Box<T>>`, which keeps the pointee in place. The pinned reference to
+/// such a value is provided by the [`FutureOnceCell::with_pinned`] method.
+///
+/// # Synchronous callbacks
+///
+/// The value is set on the current thread for the whole duration of the `poll` call of a scoped
+/// future, so it's also visible to the synchronous callbacks invoked during this call, for
+/// example to the logging hooks called by a foreign library. If the foreign call is made outside
+/// of a scoped future, or the callback may be invoked later, when the future is suspended, the
+/// value can be set for the duration of the call by the [`FutureOnceCell::enter`] method.
+///
+/// ```rust
+/// use future_local_storage::FutureOnceCell;
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// extern "C" fn log_hook() -> u64 {
+///     REQUEST_ID.get()
+/// }
+///
+/// // A foreign function invoking the given callback synchronously.
+/// extern "C" fn foreign_call(hook: extern "C" fn() -> u64) -> u64 {
+///     hook()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (_, id) = REQUEST_ID.scope(42, async { foreign_call(log_hook) }).await;
+///     assert_eq!(id, 42);
+///
+///     let guard = REQUEST_ID.enter(7);
+///     assert_eq!(foreign_call(log_hook), 7);
+///     guard.exit();
+/// }
+/// ```
 pub struct FutureOnceCell<T>(imp::FutureLocalKey<T>);
 
 impl<T> FutureOnceCell<T> {
     /// Creates an empty future once cell.
     #[must_use]
-    pub const fn new() -> Self {
+    pub const fn new() -> Self
+    where
+        T: FutureLocalValue,
+    {
         Self(imp::FutureLocalKey::new())
     }
 }
 
-impl<T> Default for FutureOnceCell<T> {
+impl<T> FutureOnceCell<T> {
+    /// Creates an empty future once cell, which keeps its per-thread state in the given
+    /// [`ThreadLocalProvider`] instead of the default one.
+    ///
+    /// ```rust
+    /// use std::cell::OnceCell;
+    ///
+    /// use future_local_storage::{FutureOnceCell, LocalState, ThreadLocalProvider};
+    ///
+    /// /// Keeps the state in the standard library thread local storage.
+    /// struct StdProvider;
+    ///
+    /// impl ThreadLocalProvider<LocalState<u64>> for StdProvider {
+    ///     fn get_or_init(init: fn() -> LocalState<u64>) -> &'static LocalState<u64> {
+    ///         thread_local! {
+    ///             static STATE: OnceCell<&'static LocalState<u64>> = const { OnceCell::new() };
+    ///         }
+    ///         // The state is leaked once per thread to get the `'static` reference.
+    ///         STATE.with(|state| *state.get_or_init(|| Box::leak(Box::new(init()))))
+    ///     }
+    /// }
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::with_provider::<StdProvider>();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (value, output) = VALUE.scope(42, async { VALUE.get() }).await;
+    ///     assert_eq!((value, output), (42, 42));
+    /// }
+    /// ```
     #[must_use]
+    pub const fn with_provider<P>() -> Self
+    where
+        T: FutureLocalValue,
+        P: ThreadLocalProvider<LocalState<T>>,
+    {
+        Self(imp::FutureLocalKey::with_provider::<P>())
+    }
+}
+
+impl<T: FutureLocalValue> Default for FutureOnceCell<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -67,21 +239,134 @@ impl<T: Send + 'static> FutureOnceCell<T> {
     /// Unlike the [`std::thread::LocalKey::with`] this method does not initialize the value
     /// when called.
     ///
+    /// The value is borrowed immutably, so the nested calls to [`Self::with`] on the same cell
+    /// are allowed.
+    ///
     /// # Panics
     ///
     /// - This method will panic if the future local doesn't have a value set.
     ///
-    /// - If you the returned future inside the a call to [`Self::with`] on the same cell, then the
+    /// - If you poll a scoped future of the same cell inside a call to [`Self::with`], then the
     ///   call to `poll` will panic.
     #[inline]
+    #[track_caller]
     pub fn with<F, R>(&'static self, f: F) -> R
     where
         F: FnOnce(&T) -> R,
     {
+        self.with_expect(
+            "cannot access a future local value without setting it first",
+            f,
+        )
+    }
+
+    /// Acquires a reference to the value in this future local storage, and panics with the given
+    /// message if the value is not set.
+    ///
+    /// It works in the same way as the [`Self::with`], but allows to provide a more specific
+    /// message for the particular future local.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic with the given message if the future local doesn't have a value
+    /// set.
+    ///
+    /// ```rust,should_panic
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static DB_CONNECTION: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// // panics with "no db connection in scope"
+    /// DB_CONNECTION.with_expect("no db connection in scope", |connection| connection.len());
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn with_expect<F, R>(&'static self, msg: &'static str, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
         let value = self.0.local_key().borrow();
-        f(value
-            .as_ref()
-            .expect("cannot access a future local value without setting it first"))
+        f(value.as_ref().expect(msg))
+    }
+
+    /// Calls the given closure for each item of the iterator with a reference to the value in
+    /// this future local storage.
+    ///
+    /// Unlike calling the [`Self::with`] for each item, the value is borrowed only once, and the
+    /// closure may mutate its captured state between the calls.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static PREFIX: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, lines) = PREFIX
+    ///         .scope("> ".to_owned(), async {
+    ///             let mut lines = Vec::new();
+    ///             PREFIX.for_each_with(["a", "b"].into_iter(), |prefix, line| {
+    ///                 lines.push(format!("{prefix}{line}"));
+    ///             });
+    ///             lines
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(lines, vec!["> a", "> b"]);
+    /// }
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn for_each_with<I, F>(&'static self, iter: I, mut f: F)
+    where
+        I: Iterator,
+        F: FnMut(&T, I::Item),
+    {
+        self.with(|value| iter.for_each(|item| f(value, item)));
+    }
+
+    /// Immutably borrows the value in this future local storage.
+    ///
+    /// The borrow lasts until the returned guard is dropped, see the [`FutureRef`] for the
+    /// details and limitations.
+    ///
+    /// # Panics
+    ///
+    /// - This method will panic if the future local doesn't have a value set.
+    ///
+    /// - If you poll a scoped future of the same cell while the guard is alive, then the call to
+    ///   `poll` will panic.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static NAME: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, len) = NAME
+    ///         .scope("future".to_owned(), async { NAME.borrow().len() })
+    ///         .await;
+    ///     assert_eq!(len, 6);
+    /// }
+    /// ```
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn borrow(&'static self) -> FutureRef<T> {
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        FutureRef::new(std::cell::Ref::map(self.0.local_key().borrow(), |value| {
+            value
+                .as_ref()
+                .expect("cannot access a future local value without setting it first")
+        }))
     }
 
     /// Returns a copy of the contained value.
@@ -90,169 +375,4080 @@ impl<T: Send + 'static> FutureOnceCell<T> {
     ///
     /// This method will panic if the future local doesn't have a value set.
     #[inline]
+    #[track_caller]
     pub fn get(&'static self) -> T
     where
         T: Copy,
     {
-        self.0.local_key().borrow().unwrap()
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        self.0
+            .local_key()
+            .borrow()
+            .expect("cannot access a future local value without setting it first")
     }
 
-    /// Sets a value `T` as the future-local value for the future `F`.
+    /// Returns a clone of the contained value, or the [`AccessError`] if the future local doesn't
+    /// have a value set.
     ///
-    /// On completion of `scope`, the future-local value will be returned by the scoped future.
+    /// It's a shorthand for the `cell.with(T::clone)` call, which doesn't panic outside of the
+    /// scope.
     ///
     /// ```rust
-    /// use std::cell::Cell;
+    /// use future_local_storage::{AccessError, FutureOnceCell};
+    ///
+    /// static CONTEXT: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// fn handle() -> Result<usize, AccessError> {
+    ///     let context = CONTEXT.current()?;
+    ///     Ok(context.len())
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     assert!(handle().is_err());
     ///
+    ///     let (_, len) = CONTEXT.scope("context".to_owned(), async { handle() }).await;
+    ///     assert_eq!(len, Ok(7));
+    /// }
+    /// ```
+    #[inline]
+    pub fn current(&'static self) -> Result<T, AccessError>
+    where
+        T: Clone,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        self.0.local_key().borrow().clone().ok_or(AccessError)
+    }
+
+    /// Returns `true` if the future local has a value set, and it is equal to the `expected`
+    /// one.
+    ///
+    /// Unlike the `cell.with(|value| value == expected)` call, this method doesn't panic if the
+    /// value is not set, which makes the assertions in tests terse.
+    ///
+    /// ```rust
     /// use future_local_storage::FutureOnceCell;
     ///
-    /// static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let (output, answer) = VALUE.scope(Cell::from(0), async {
-    ///         VALUE.with(|x| {
-    ///             let value = x.get();
-    ///             x.set(value + 1);
-    ///         });
+    ///     assert!(!REQUEST_ID.current_eq(&42));
+    ///     REQUEST_ID
+    ///         .scope(42, async { assert!(REQUEST_ID.current_eq(&42)) })
+    ///         .await;
+    /// }
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn current_eq(&'static self, expected: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        self.0.local_key().borrow().as_ref() == Some(expected)
+    }
+
+    /// Returns the number of the scopes of this future local currently entered on the current
+    /// thread.
     ///
-    ///         42
-    ///     }).await;
+    /// A scope is entered while its scoped future is being polled, or while the guard returned by
+    /// the [`FutureOnceCell::enter`] is alive. The depth is greater than one only for the nested
+    /// scopes, so it allows to check that the nesting is balanced, or to detect a runaway
+    /// recursion.
+    ///
+    /// ```rust
+    /// use future_local_storage::{FutureLocalStorage, FutureOnceCell, OnConflict};
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, depth) = VALUE
+    ///         .scope(1, async {
+    ///             let (_, depth) = async { VALUE.scope_depth() }
+    ///                 .with_scope_with_policy(&VALUE, 2, OnConflict::Nest)
+    ///                 .await;
+    ///             depth
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(depth, 2);
+    ///     assert_eq!(VALUE.scope_depth(), 0);
     /// }
     /// ```
     #[inline]
-    pub fn scope<F>(&'static self, value: T, future: F) -> ScopedFutureWithValue<T, F>
+    #[must_use]
+    pub fn scope_depth(&'static self) -> usize {
+        FutureLocalKey::depth(&self.0)
+    }
+
+    /// Returns a clone of the contained value, or the [`Default`] value if the future local
+    /// doesn't have a value set.
+    ///
+    /// Unlike the [`Self::get`] this method never panics, which is useful when the missing value
+    /// has a reasonable default, for example, in the metrics labels.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     assert_eq!(TENANT.get_or_default(), "");
+    ///
+    ///     let (_, tenant) = TENANT
+    ///         .scope("acme".to_owned(), async { TENANT.get_or_default() })
+    ///         .await;
+    ///     assert_eq!(tenant, "acme");
+    /// }
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn get_or_default(&'static self) -> T
     where
-        F: Future,
+        T: Default + Clone,
     {
-        future.with_scope(self, value)
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        self.0.local_key().borrow().clone().unwrap_or_default()
     }
-}
 
-impl<T: Debug + Send + 'static> Debug for FutureOnceCell<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("FutureOnceCell").field(&self.0).finish()
+    /// Returns a reference to the underlying thread local storage of this future local.
+    ///
+    /// This is a low-level escape hatch which allows to implement custom logic on top of this
+    /// cell.
+    ///
+    /// # Important
+    ///
+    /// The scoped futures swap their values in and out of this storage on each poll, so the code
+    /// using this method must respect the following invariants:
+    ///
+    /// - Never hold a borrow of the returned cell across an `.await` point or while polling a
+    ///   scoped future of the same cell, otherwise the poll will panic.
+    /// - A value set outside of any scope remains in the thread local storage of the current
+    ///   thread and will be seen by other futures polled on this thread.
+    /// - A value taken inside a scope will not be returned by the scoped future, and the scoped
+    ///   future will return the value put in its place instead. If there is no value, the scoped
+    ///   future will panic on completion.
+    #[inline]
+    #[must_use]
+    pub fn raw_local_key(&'static self) -> &'static RefCell<Option<T>> {
+        self.0.local_key()
     }
-}
 
-impl<T> AsRef<FutureLocalKey<T>> for FutureOnceCell<T> {
-    fn as_ref(&self) -> &FutureLocalKey<T> {
-        &self.0
+    /// Returns the borrow state of this future local on the current thread.
+    ///
+    /// This is a debugging aid for the panics caused by polling a scoped future while the value
+    /// of the same cell is borrowed. The check itself doesn't leave the cell borrowed.
+    ///
+    /// ```rust
+    /// use future_local_storage::{BorrowState, FutureOnceCell};
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     assert_eq!(VALUE.borrow_state(), BorrowState::Unset);
+    ///     VALUE
+    ///         .scope(42, async {
+    ///             assert_eq!(VALUE.borrow_state(), BorrowState::Unborrowed);
+    ///             VALUE.with(|_| assert_eq!(VALUE.borrow_state(), BorrowState::Shared));
+    ///         })
+    ///         .await;
+    /// }
+    /// ```
+    #[cfg(feature = "debug")]
+    #[must_use]
+    pub fn borrow_state(&'static self) -> BorrowState {
+        let key = self.0.local_key();
+        match key.try_borrow_mut() {
+            Ok(value) if value.is_none() => BorrowState::Unset,
+            Ok(_) => BorrowState::Unborrowed,
+            Err(_) if key.try_borrow().is_ok() => BorrowState::Shared,
+            Err(_) => BorrowState::Mutable,
+        }
     }
-}
 
-/// Attaches future local storage values to a [`Future`].
-///
-/// Extension trait allowing futures to have their own static variables.
-pub trait FutureLocalStorage: Future + Sized + private::Sealed {
-    /// Sets a given value as the future local value of this future.
+    /// Takes the future-local value out of the current scope, leaving the [`Default`] value in
+    /// its place.
     ///
-    /// Each future instance will have its own state of the attached value.
+    /// It allows to hand off the accumulated value before the scoped future completes. After this
+    /// call the future local accesses inside the scope will see the `T::default()` value, and
+    /// the scoped future will return the value that remains in the scope at completion, i.e. the
+    /// default value with all modifications made after this call.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<RefCell<Vec<&str>>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (remaining, finished) = TRACES
+    ///         .scope(RefCell::default(), async {
+    ///             TRACES.with(|traces| traces.borrow_mut().push("first"));
+    ///             let finished = TRACES.finish_scope();
+    ///             TRACES.with(|traces| traces.borrow_mut().push("second"));
+    ///             finished
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(finished.into_inner(), vec!["first"]);
+    ///     assert_eq!(remaining.into_inner(), vec!["second"]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn finish_scope(&'static self) -> T
+    where
+        T: Default,
+    {
+        self.0
+            .local_key()
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .expect("cannot finish a future local scope without setting it first")
+    }
+
+    /// Sets a value `T` as the future-local value on the current thread until the returned guard
+    /// is dropped.
+    ///
+    /// This is a synchronous counterpart of the [`FutureOnceCell::scope`] for the blocking code,
+    /// which allows to control exactly when the scope ends. The value is removed and returned by
+    /// the [`ScopeEntered::exit`], or dropped along with the guard. If the future local already
+    /// has a value, it's restored when the guard is dropped.
+    ///
+    /// The guard must not be held across an `.await` point, since the value is bound to the
+    /// current thread and doesn't move with the task. For this reason the guard is not [`Send`].
     ///
     /// ```rust
     /// use std::cell::Cell;
     ///
-    /// use future_local_storage::{FutureOnceCell, FutureLocalStorage};
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static COUNTER: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// let guard = COUNTER.enter(Cell::new(0));
+    /// COUNTER.with(|counter| counter.set(counter.get() + 1));
+    /// assert_eq!(guard.exit().get(), 1);
+    /// ```
+    #[inline]
+    pub fn enter(&'static self, value: T) -> ScopeEntered<T> {
+        ScopeEntered::enter(&self.0, value)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`.
+    ///
+    /// On completion of `scope`, the future-local value will be returned by the scoped future
+    /// along with the future output as the `(T, F::Output)` tuple, see [`ScopedFutureWithValue`]
+    /// for details.
+    ///
+    /// The value is set on each poll of the future `F` only, including the first one, so the
+    /// body of an `async` block sees it from the very beginning. However, a function call which
+    /// returns a future is evaluated before the scope is created, thus the synchronous code in
+    /// such a function doesn't see the value, use the [`future::lazy_body`] in this case.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
     ///
     /// static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let (output, answer) = async {
+    ///     let (output, answer) = VALUE.scope(Cell::from(0), async {
     ///         VALUE.with(|x| {
     ///             let value = x.get();
     ///             x.set(value + 1);
     ///         });
     ///
     ///         42
-    ///     }.with_scope(&VALUE, Cell::from(0)).await;
+    ///     }).await;
     /// }
     /// ```
-    fn with_scope<T, S>(self, scope: &'static S, value: T) -> ScopedFutureWithValue<T, Self>
+    #[inline]
+    pub fn scope<F>(&'static self, value: T, future: F) -> ScopedFutureWithValue<T, F>
     where
-        T: Send,
-        S: AsRef<FutureLocalKey<T>>;
-}
-
-mod private {
-    use std::future::Future;
+        F: Future,
+    {
+        future.with_scope(self, value)
+    }
 
-    pub trait Sealed {}
+    /// Sets a value `T` as the future-local value for the future `F`, and discards the value on
+    /// completion.
+    ///
+    /// This is a shorthand for the [`Self::scope`] followed by the
+    /// [`ScopedFutureWithValue::discard_value`], so instead of
+    /// `let (_, output) = VALUE.scope(value, future).await` you can just write
+    /// `let output = VALUE.scope_out(value, future).await`.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// async fn handle_request() -> String {
+    ///     format!("handled request {}", REQUEST_ID.get())
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let output = REQUEST_ID.scope_out(42, handle_request()).await;
+    ///     assert_eq!(output, "handled request 42");
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_out<F>(&'static self, value: T, future: F) -> ScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        future.with_scope_out(self, value)
+    }
 
-    impl<F: Future> Sealed for F {}
+    /// Sets a clone of the current future-local value as the future-local value for the future
+    /// `F`.
+    ///
+    /// This is the way to create a child future that inherits the context of the parent one, for
+    /// example, to spawn it as a separate task.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set, i.e. if it's called
+    /// outside of any scope of this future local.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, child_id) = REQUEST_ID
+    ///         .scope("request-1".to_owned(), async {
+    ///             let child = REQUEST_ID.fork(async { REQUEST_ID.with(Clone::clone) });
+    ///             let (_, child_id) = tokio::spawn(child).await.unwrap();
+    ///             child_id
+    ///         })
+    ///         .await;
+    ///     assert_eq!(child_id, "request-1");
+    /// }
+    /// ```
+    #[inline]
+    pub fn fork<F>(&'static self, future: F) -> ScopedFutureWithValue<T, F>
+    where
+        T: Clone,
+        F: Future,
+    {
+        let value = self.with(T::clone);
+        self.scope(value, future)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and links it with the value
+    /// of the `parent` future local.
+    ///
+    /// On the first poll, right before the value is set, the `link` closure is called with the
+    /// mutable reference to the value and the current value of the `parent` future local, if any.
+    /// It allows to build the parent-child chains of the contexts, for example, span hierarchies.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// #[derive(Debug, Default)]
+    /// struct Span {
+    ///     id: u64,
+    ///     parent_id: Option<u64>,
+    /// }
+    ///
+    /// static SPAN: FutureOnceCell<Span> = FutureOnceCell::new();
+    /// static PARENT_SPAN: FutureOnceCell<Span> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let child = SPAN.scope_with_parent(
+    ///         Span { id: 2, ..Span::default() },
+    ///         &PARENT_SPAN,
+    ///         async {},
+    ///         |span, parent| span.parent_id = parent.map(|parent| parent.id),
+    ///     );
+    ///     let (_, (child, ())) = PARENT_SPAN.scope(Span { id: 1, ..Span::default() }, child).await;
+    ///     assert_eq!(child.parent_id, Some(1));
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_with_parent<P, F, H>(
+        &'static self,
+        value: T,
+        parent: &'static FutureOnceCell<P>,
+        future: F,
+        link: H,
+    ) -> LinkParent<T, P, F, H>
+    where
+        P: Send + 'static,
+        F: Future,
+        H: FnOnce(&mut T, Option<&P>),
+    {
+        LinkParent::new(self.scope(value, future), parent, link)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and pins the scoped future
+    /// in the box.
+    ///
+    /// The returned future is [`Unpin`] even if the future `F` is not, so it can be stored in
+    /// collections or used with APIs that require [`Unpin`] futures.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let futures = (0..4)
+    ///         .map(|i| {
+    ///             VALUE.scope_boxed(i, async {
+    ///                 tokio::task::yield_now().await;
+    ///                 VALUE.get() * 2
+    ///             })
+    ///         })
+    ///         .collect::<Vec<_>>();
+    ///
+    ///     let results = futures_util::future::join_all(futures).await;
+    ///     assert_eq!(results, vec![(0, 0), (1, 2), (2, 4), (3, 6)]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_boxed<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> Pin<Box<dyn Future<Output = (T, F::Output)> + Send>>
+    where
+        F: Future + Send + 'static,
+    {
+        Box::pin(self.scope(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and erases the scoped future
+    /// into the boxed [`Send`] and `'static` future, which returns the output of the future `F`
+    /// only.
+    ///
+    /// This is intended for the actor frameworks, like `actix`, which message handlers have to
+    /// return the type erased `'static` and [`Unpin`] futures with the handler result as the
+    /// output, so the future local value is discarded on completion.
+    ///
+    /// Both the value `T` and the future `F` must be [`Send`] and `'static`, so the future `F`
+    /// cannot borrow anything from the handler, for example, the actor itself. Move the required
+    /// data into the future instead.
+    ///
+    /// ```rust
+    /// use std::{future::Future, pin::Pin};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// // The result type of an actor message handler.
+    /// type ResponseFuture<R> = Pin<Box<dyn Future<Output = R> + Send + 'static>>;
+    ///
+    /// struct Actor {
+    ///     name: String,
+    /// }
+    ///
+    /// impl Actor {
+    ///     fn handle(&mut self, request_id: u64) -> ResponseFuture<String> {
+    ///         let name = self.name.clone();
+    ///         REQUEST_ID.scope_static(request_id, async move {
+    ///             format!("{name} handles request {}", REQUEST_ID.get())
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut actor = Actor { name: "actor".to_owned() };
+    ///     let response = tokio::spawn(actor.handle(42)).await.unwrap();
+    ///     assert_eq!(response, "actor handles request 42");
+    /// }
+    /// ```
+    ///
+    /// The future which borrows the handler data is rejected:
+    ///
+    /// ```rust,compile_fail
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// fn handle(name: &str) -> std::pin::Pin<Box<dyn std::future::Future<Output = usize> + Send>> {
+    ///     REQUEST_ID.scope_static(42, async move { name.len() })
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_static<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> Pin<Box<dyn Future<Output = F::Output> + Send + 'static>>
+    where
+        F: Future + Send + 'static,
+    {
+        Box::pin(self.scope_out(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F` for the entire lifetime of
+    /// the future.
+    ///
+    /// Unlike the [`Self::scope`] the value is not swapped out when the future `F` is suspended,
+    /// so it remains accessible in the `Drop` implementations that run outside of the future poll,
+    /// see [`StickyScopedFuture`] for the details and sharp edges. For the same reason the other
+    /// futures polled on the same thread can read the value between the polls of the scoped
+    /// future. This mode is only suitable for the current-thread runtimes.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// struct Guard;
+    ///
+    /// impl Drop for Guard {
+    ///     fn drop(&mut self) {
+    ///         assert_eq!(VALUE.get(), 42);
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let future = VALUE.scope_sticky(42, async {
+    ///         let _guard = Guard;
+    ///         std::future::pending::<()>().await;
+    ///     });
+    ///     // The guard is dropped along with the cancelled future, and the value is still set.
+    ///     let result = tokio::time::timeout(std::time::Duration::from_millis(10), future).await;
+    ///     assert!(result.is_err());
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_sticky<F>(&'static self, value: T, future: F) -> StickyScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        StickyScopedFuture::new(&self.0, value, future)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and requires the scoped
+    /// future to be polled on the same thread.
+    ///
+    /// The scoped future records the thread of the first poll and panics with a clear message
+    /// if it's polled on another thread later, for example, when it's accidentally spawned on a
+    /// multi-threaded executor. This is useful for the values which are [`Send`], but logically
+    /// bound to a thread, like handles of the thread specific resources. Unlike the
+    /// [`Self::scope_sticky`], the value is still swapped out between polls.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static RENDER_CONTEXT: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// async fn main() {
+    ///     let future = RENDER_CONTEXT.scope_thread_affine(1, async {
+    ///         tokio::task::yield_now().await;
+    ///         RENDER_CONTEXT.get()
+    ///     });
+    ///     // Spawning on the local task set keeps the future on the current thread.
+    ///     let local = tokio::task::LocalSet::new();
+    ///     let (_, output) = local.run_until(future).await;
+    ///     assert_eq!(output, 1);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_thread_affine<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> ThreadAffineScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        ThreadAffineScopedFuture::new(self.scope(value, future))
+    }
+
+    /// Replaces the current future-local value by the given one for the rest of the current scope,
+    /// and then runs the future `F`.
+    ///
+    /// The enclosing scope of this future local is the one which swaps the new value out
+    /// between the polls and takes it on completion. Unlike the [`Self::scope`], which restores
+    /// the outer value after each poll, the new value is kept: it remains set after the future
+    /// `F` completes, and the enclosing scope will return it instead of the original one. The
+    /// original value is taken out on the first poll and returned along with the future output.
+    ///
+    /// The original value is restored instead in the following cases:
+    ///
+    /// - If there is no outer value at the first poll, i.e. the future is polled outside of a
+    ///   scope of this future local, the new value is set for the future `F` only in the same way
+    ///   as the [`Self::scope`] does, it's dropped on completion, and the original value is
+    ///   `None`.
+    ///
+    /// - If the future is dropped before completion inside the enclosing scope, the original
+    ///   value is put back into the enclosing scope, and the new one is dropped.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static CONTEXT: FutureOnceCell<&str> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (last, original) = CONTEXT
+    ///         .scope("original", async {
+    ///             let (original, ()) = CONTEXT.replace_scope("replaced", async {}).await;
+    ///             assert_eq!(original, Some("original"));
+    ///             // The new context remains after the future completion.
+    ///             assert_eq!(CONTEXT.get(), "replaced");
+    ///             original
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(original, Some("original"));
+    ///     assert_eq!(last, "replaced");
+    /// }
+    /// ```
+    #[inline]
+    pub fn replace_scope<F>(&'static self, value: T, future: F) -> ReplaceScope<T, F>
+    where
+        F: Future,
+    {
+        ReplaceScope::new(&self.0, value, future)
+    }
+
+    /// Sets a value `T` as the future-local value for the fallible stream `S`.
+    ///
+    /// The value is set while the stream `S` is polled. Like the
+    /// [`try_for_each`](https://docs.rs/futures/latest/futures/stream/trait.TryStreamExt.html#method.try_for_each)
+    /// combinator, the scoped stream yields the first error item and then ends without polling the
+    /// stream `S` anymore. The value is returned by the [`TryScopedStream::take_value`] method in
+    /// both cases, whether the stream was exhausted or terminated by an error.
+    ///
+    /// ```rust
+    /// use std::{cell::Cell, pin::pin};
+    ///
+    /// use futures_util::{stream, StreamExt};
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static PROCESSED: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let items = stream::iter([Ok(1), Err("invalid item"), Ok(3)]).inspect(|item| {
+    ///         if item.is_ok() {
+    ///             PROCESSED.with(|x| x.set(x.get() + 1));
+    ///         }
+    ///     });
+    ///     let mut stream = pin!(PROCESSED.try_scope_stream(Cell::new(0), items));
+    ///
+    ///     assert_eq!(stream.next().await, Some(Ok(1)));
+    ///     assert_eq!(stream.next().await, Some(Err("invalid item")));
+    ///     // The stream ends after the first error.
+    ///     assert_eq!(stream.next().await, None);
+    ///     assert_eq!(stream.take_value().unwrap().get(), 1);
+    /// }
+    /// ```
+    #[cfg(feature = "stream")]
+    #[inline]
+    pub fn try_scope_stream<S, I, E>(&'static self, value: T, stream: S) -> TryScopedStream<T, S>
+    where
+        S: futures_core::Stream<Item = Result<I, E>>,
+    {
+        TryScopedStream::new(&self.0, value, stream)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and requires the future to
+    /// complete before the given `duration` has elapsed.
+    ///
+    /// The scoped future returns the future-local value even if the future `F` times out. In this
+    /// case the value contains all modifications made before the timeout, and the output is
+    /// [`Elapsed`](tokio::time::error::Elapsed) error.
+    ///
+    /// ```rust
+    /// use std::{cell::Cell, time::Duration};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static PROGRESS: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (progress, result) = PROGRESS
+    ///         .scope_timeout(Cell::new(0), Duration::from_millis(10), async {
+    ///             PROGRESS.with(|x| x.set(50));
+    ///             std::future::pending::<()>().await;
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(progress.get(), 50);
+    ///     assert!(result.is_err());
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn scope_timeout<F>(
+        &'static self,
+        value: T,
+        duration: std::time::Duration,
+        future: F,
+    ) -> ScopedFutureWithValue<T, tokio::time::Timeout<F>>
+    where
+        F: Future,
+    {
+        self.scope(value, tokio::time::timeout(duration, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and instruments the future
+    /// `F` with a [`tracing`] span, which fields are derived from the value.
+    ///
+    /// The `make_fields` closure is called once with the initial value, and its result is
+    /// recorded to the `fields` field of the `future_local_scope` span in the `key=value` form,
+    /// since the [`tracing`] spans can't have dynamically named fields. The span also has the
+    /// `scope` field with the value type name. The span is entered on each poll of the future
+    /// `F` and exited when it is suspended.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     tracing_subscriber::fmt().init();
+    ///
+    ///     // Prints something like:
+    ///     // INFO future_local_scope{scope="u64" fields=request_id=42}: handling request
+    ///     REQUEST_ID
+    ///         .scope_span(
+    ///             42,
+    ///             async { tracing::info!("handling request") },
+    ///             |id| vec![("request_id", id.to_string())],
+    ///         )
+    ///         .await;
+    /// }
+    /// ```
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub fn scope_span<F>(
+        &'static self,
+        value: T,
+        future: F,
+        make_fields: impl FnOnce(&T) -> Vec<(&'static str, String)>,
+    ) -> ScopedFutureWithValue<T, tracing::instrument::Instrumented<F>>
+    where
+        F: Future,
+    {
+        use std::fmt::Write as _;
+
+        use tracing::Instrument as _;
+
+        let fields = make_fields(&value).into_iter().enumerate().fold(
+            String::new(),
+            |mut fields, (i, (name, value))| {
+                let separator = if i == 0 { "" } else { " " };
+                // Writing to the string never fails.
+                let _ = write!(fields, "{separator}{name}={value}");
+                fields
+            },
+        );
+        let span = tracing::info_span!(
+            "future_local_scope",
+            scope = std::any::type_name::<T>(),
+            fields = %fields
+        );
+        self.scope(value, future.instrument(span))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and limits the number of
+    /// the concurrently running scopes by the given `semaphore`.
+    ///
+    /// The scoped future acquires a permit before the future `F` is polled for the first time,
+    /// and holds it until the future `F` completes or the scoped future is dropped. This is
+    /// useful when each scope represents a costly context, for example, a database transaction.
+    ///
+    /// The scoped future returns the future-local value even if the semaphore has been closed,
+    /// in this case the future `F` is never polled and the output is
+    /// [`AcquireError`](tokio::sync::AcquireError).
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    /// use tokio::sync::Semaphore;
+    ///
+    /// static TRANSACTION: FutureOnceCell<u64> = FutureOnceCell::new();
+    /// static LIMIT: Semaphore = Semaphore::const_new(2);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tasks = (0..10)
+    ///         .map(|id| {
+    ///             tokio::spawn(TRANSACTION.scope_limited(id, &LIMIT, async {
+    ///                 // At most two transactions are running at the same time.
+    ///                 TRANSACTION.get() * 2
+    ///             }))
+    ///         })
+    ///         .collect::<Vec<_>>();
+    ///
+    ///     for (id, task) in tasks.into_iter().enumerate() {
+    ///         let (_, output) = task.await.unwrap();
+    ///         assert_eq!(output.unwrap(), id as u64 * 2);
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn scope_limited<'a, F>(
+        &'static self,
+        value: T,
+        semaphore: &'a tokio::sync::Semaphore,
+        future: F,
+    ) -> LimitedScopedFuture<'a, T, F>
+    where
+        F: Future,
+    {
+        LimitedScopedFuture::new(self.scope(value, future), semaphore)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and mirrors it to the given
+    /// [`tokio::task_local!`] key.
+    ///
+    /// This eases the incremental migration from the tokio task locals: the code which uses
+    /// either this future local or the task local sees the same value inside the future `F`. The
+    /// task local gets a clone of the value, so the changes made through it are not reflected in
+    /// the value returned by the scoped future, unless the value shares its state, for example,
+    /// by the [`Arc`](std::sync::Arc).
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// tokio::task_local! {
+    ///     static LEGACY_REQUEST_ID: u64;
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, ids) = REQUEST_ID
+    ///         .mirror_task_local(&LEGACY_REQUEST_ID, 42, async {
+    ///             (REQUEST_ID.get(), LEGACY_REQUEST_ID.get())
+    ///         })
+    ///         .await;
+    ///     assert_eq!(ids, (42, 42));
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn mirror_task_local<F>(
+        &'static self,
+        task_local: &'static tokio::task::LocalKey<T>,
+        value: T,
+        future: F,
+    ) -> ScopedFutureWithValue<T, tokio::task::futures::TaskLocalFuture<T, F>>
+    where
+        T: Clone,
+        F: Future,
+    {
+        self.scope(value.clone(), task_local.scope(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and keeps the given
+    /// [`thread_local!`] in sync with it during each poll.
+    ///
+    /// Many synchronous libraries, like the formatters of the logging facades, read their context
+    /// from a thread local. Before each poll of the future `F` the thread local gets a clone of
+    /// the future local value, and the previous thread local value is restored after the poll.
+    /// The synchronization is two-way: if the thread local value has been changed during the
+    /// poll, the change is written back to the future local. Between the polls, and after the
+    /// scope ends, the thread local doesn't see the value.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// thread_local! {
+    ///     static LOG_REQUEST_ID: RefCell<Option<u64>> = const { RefCell::new(None) };
+    /// }
+    ///
+    /// // A synchronous library function reading the thread local.
+    /// fn log_prefix() -> String {
+    ///     LOG_REQUEST_ID.with(|id| format!("[{:?}]", id.borrow()))
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, prefix) = REQUEST_ID
+    ///         .bridge_thread_local(&LOG_REQUEST_ID, 42, async { log_prefix() })
+    ///         .await;
+    ///     assert_eq!(prefix, "[Some(42)]");
+    ///     assert_eq!(log_prefix(), "[None]");
+    /// }
+    /// ```
+    #[inline]
+    pub fn bridge_thread_local<F>(
+        &'static self,
+        thread_local: &'static std::thread::LocalKey<RefCell<Option<T>>>,
+        value: T,
+        future: F,
+    ) -> ScopedFutureWithValue<T, ThreadLocalBridge<T, F>>
+    where
+        T: Clone + PartialEq,
+        F: Future,
+    {
+        self.scope(value, ThreadLocalBridge::new(self, thread_local, future))
+    }
+
+    /// Spawns the future `F` on the Tokio runtime with a clone of the current future-local value.
+    ///
+    /// This is a shortcut for the `tokio::spawn(CELL.fork(future))`, which returns the output of
+    /// the future only. The bounds are checked on the call, so an attempt to spawn a future which
+    /// is not [`Send`] or borrows local data is reported as an unsatisfied bound of this method,
+    /// rather than as a deep trait resolution error inside the scoped future type.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (_, child) = REQUEST_ID
+    ///         .scope(42, async {
+    ///             let handle = REQUEST_ID.spawn_scoped(async { REQUEST_ID.get() });
+    ///             handle.await.unwrap()
+    ///         })
+    ///         .await;
+    ///     assert_eq!(child, 42);
+    /// }
+    /// ```
+    ///
+    /// The future which holds a non-[`Send`] value across an `.await` is rejected:
+    ///
+    /// ```rust,compile_fail
+    /// use std::rc::Rc;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// async fn spawn_child() {
+    ///     REQUEST_ID.spawn_scoped(async {
+    ///         let rc = Rc::new(REQUEST_ID.get());
+    ///         tokio::task::yield_now().await;
+    ///         *rc
+    ///     });
+    /// }
+    /// ```
+    ///
+    /// And so is the future which borrows the local data:
+    ///
+    /// ```rust,compile_fail
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// fn spawn_child(name: &str) {
+    ///     REQUEST_ID.spawn_scoped(async move { name.len() });
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// - This method will panic if the future local doesn't have a value set.
+    ///
+    /// - This method will panic if it's called outside of the Tokio runtime.
+    #[cfg(feature = "tokio")]
+    #[inline]
+    #[track_caller]
+    pub fn spawn_scoped<F>(&'static self, future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        T: Clone,
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        tokio::spawn(self.fork(future).discard_value())
+    }
+
+    /// Serializes the future local value and sets it under the given key in the
+    /// [`BAGGAGE_HEADER`] of the outgoing request headers.
+    ///
+    /// The value is encoded as a percent-encoded JSON, the other entries of the baggage header
+    /// are kept, except the previous entry with the same key. The receiving side reconstructs the
+    /// scope by the [`FutureOnceCell::extract_scope`] method.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    ///
+    /// use future_local_storage::{FutureOnceCell, BAGGAGE_HEADER};
+    ///
+    /// static TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut headers = HashMap::new();
+    ///     TENANT
+    ///         .scope("acme".to_owned(), async {
+    ///             TENANT.inject_into("tenant", &mut headers).unwrap();
+    ///         })
+    ///         .await;
+    ///     assert_eq!(headers[BAGGAGE_HEADER], "tenant=%22acme%22");
+    ///
+    ///     let (_, tenant) = TENANT
+    ///         .extract_scope("tenant", &headers, async { TENANT.with(String::clone) })
+    ///         .unwrap()
+    ///         .await;
+    ///     assert_eq!(tenant, "acme");
+    /// }
+    /// ```
+    #[cfg(feature = "baggage")]
+    pub fn inject_into<H>(&'static self, key: &str, headers: &mut H) -> Result<(), BaggageError>
+    where
+        T: serde::Serialize,
+        H: BaggageCarrier + ?Sized,
+    {
+        #[cfg(feature = "debug-warn-unused")]
+        self.0.mark_read();
+        let value = {
+            let value = self.0.local_key().borrow();
+            let value = value.as_ref().ok_or(BaggageError::Access(AccessError))?;
+            serde_json::to_string(value).map_err(BaggageError::Serde)?
+        };
+        baggage::inject(headers, key, &value)
+    }
+
+    /// Deserializes the value under the given key in the [`BAGGAGE_HEADER`] of the incoming
+    /// request headers, and sets it as the future-local value for the future `F`.
+    ///
+    /// It's the receiving counterpart of the [`FutureOnceCell::inject_into`] method.
+    #[cfg(feature = "baggage")]
+    pub fn extract_scope<H, F>(
+        &'static self,
+        key: &str,
+        headers: &H,
+        future: F,
+    ) -> Result<ScopedFutureWithValue<T, F>, BaggageError>
+    where
+        T: serde::de::DeserializeOwned,
+        H: BaggageCarrier + ?Sized,
+        F: Future,
+    {
+        let value = baggage::extract(headers, key)?;
+        let value = serde_json::from_str(&value).map_err(BaggageError::Serde)?;
+        Ok(self.scope(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and emits a [`tracing`]
+    /// event with the final value on completion.
+    ///
+    /// The event is emitted exactly once at the given `level` when the future `F` completes, and
+    /// has the `value` field with the [`Debug`] representation of the value. Use the
+    /// [`TracedScopedFuture::trace_cancel`] to emit the event also if the scoped future is
+    /// dropped before completion, such an event has the `cancelled` field set to `true`.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<RefCell<Vec<&str>>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     tracing_subscriber::fmt().init();
+    ///
+    ///     // Prints something like:
+    ///     // DEBUG future local scope finished value=RefCell { value: ["handled"] } cancelled=false
+    ///     TRACES
+    ///         .scope_traced(tracing::Level::DEBUG, RefCell::default(), async {
+    ///             TRACES.with(|traces| traces.borrow_mut().push("handled"));
+    ///         })
+    ///         .trace_cancel()
+    ///         .await;
+    /// }
+    /// ```
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub fn scope_traced<F>(
+        &'static self,
+        level: tracing::Level,
+        value: T,
+        future: F,
+    ) -> TracedScopedFuture<T, F>
+    where
+        T: Debug,
+        F: Future,
+    {
+        TracedScopedFuture::new(self.scope(value, future), level)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and sends the value to the
+    /// returned receiver on completion.
+    ///
+    /// Unlike the [`Self::scope`] the scoped future returns the output of the future `F` only.
+    /// This allows to spawn the scoped future as a separate task and collect the future-local
+    /// value later without joining the task. If the scoped future is dropped before completion,
+    /// the receiver gets the [`RecvError`](tokio::sync::oneshot::error::RecvError).
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<RefCell<Vec<&str>>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (traces, future) = TRACES.scope_channel(RefCell::default(), async {
+    ///         TRACES.with(|traces| traces.borrow_mut().push("handled"));
+    ///     });
+    ///     tokio::spawn(future);
+    ///
+    ///     assert_eq!(traces.await.unwrap().into_inner(), vec!["handled"]);
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn scope_channel<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> (tokio::sync::oneshot::Receiver<T>, ChannelScopedFuture<T, F>)
+    where
+        F: Future,
+    {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        (
+            receiver,
+            ChannelScopedFuture::new(self.scope(value, future), sender),
+        )
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and returns the stream of
+    /// the value snapshots along with the scoped future.
+    ///
+    /// The snapshot is taken each time the future `F` is suspended, i.e. once per poll, so the
+    /// changes made between two suspension points are observed together. On completion the
+    /// final value itself is sent to the stream, and the scoped future returns the output of the
+    /// future `F` only. This decouples the progress observation from the computation, for
+    /// example, the scoped future can be spawned while the snapshots are reported to a UI.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static PROGRESS: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (mut snapshots, future) = PROGRESS.scope_observed(Cell::new(0), async {
+    ///         for _ in 0..3 {
+    ///             PROGRESS.with(|x| x.set(x.get() + 1));
+    ///             tokio::task::yield_now().await;
+    ///         }
+    ///         "done"
+    ///     });
+    ///     assert_eq!(tokio::spawn(future).await.unwrap(), "done");
+    ///
+    ///     let mut progress = Vec::new();
+    ///     while let Some(snapshot) = snapshots.recv().await {
+    ///         progress.push(snapshot.get());
+    ///     }
+    ///     assert_eq!(progress, vec![1, 2, 3, 3]);
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn scope_observed<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> (ScopeSnapshots<T>, ObservedScopedFuture<T, F>)
+    where
+        T: Clone,
+        F: Future,
+    {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        (
+            ScopeSnapshots::new(receiver),
+            ObservedScopedFuture::new(self.scope(value, future), sender),
+        )
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and passes the value to the
+    /// asynchronous `finalizer` on completion.
+    ///
+    /// When the future `F` completes, the scoped future awaits the future returned by the
+    /// `finalizer` with the taken future-local value, for example, to flush the collected traces
+    /// to a file, and only then returns the output of the future `F`.
+    ///
+    /// The asynchronous finalizer can't be awaited if the scoped future is dropped before the
+    /// future `F` completes, in this case the synchronous `on_cancel` fallback is called with
+    /// the value instead. Pass the [`drop`] function to just drop the value. If the scoped
+    /// future is dropped while the finalizer is running, the finalizer future is dropped as is.
+    ///
+    /// ```rust
+    /// use std::{cell::RefCell, sync::Mutex};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<RefCell<Vec<&str>>> = FutureOnceCell::new();
+    /// static SINK: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let output = TRACES
+    ///         .scope_with_async_finalizer(
+    ///             RefCell::default(),
+    ///             async {
+    ///                 TRACES.with(|traces| traces.borrow_mut().push("handled"));
+    ///                 42
+    ///             },
+    ///             |traces| async move {
+    ///                 // Some asynchronous IO.
+    ///                 tokio::task::yield_now().await;
+    ///                 SINK.lock().unwrap().extend(traces.into_inner());
+    ///             },
+    ///             drop,
+    ///         )
+    ///         .await;
+    ///
+    ///     assert_eq!(output, 42);
+    ///     assert_eq!(*SINK.lock().unwrap(), vec!["handled"]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_with_async_finalizer<F, G, Fut, C>(
+        &'static self,
+        value: T,
+        future: F,
+        finalizer: G,
+        on_cancel: C,
+    ) -> AsyncFinalizer<T, F, G, Fut, C>
+    where
+        F: Future,
+        G: FnOnce(T) -> Fut,
+        Fut: Future<Output = ()>,
+        C: FnOnce(T),
+    {
+        AsyncFinalizer::new(self.scope(value, future), finalizer, on_cancel)
+    }
+
+    /// Lends a borrowed value `T` as the future-local value for the future `F`.
+    ///
+    /// The value is moved out of the borrowed place, leaving the [`Default`] value there, and it
+    /// is written back when the future `F` completes or the scoped future is dropped. This allows
+    /// to scope a large value without cloning it, since moving is cheap for the heap allocated
+    /// types like [`Vec`] or [`Box`].
+    ///
+    /// The future local can't contain a reference to the borrowed value itself, since the thread
+    /// local storage requires the `'static` values, and providing such a reference is not possible
+    /// without the `unsafe` code.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<RefCell<Vec<String>>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut traces = RefCell::new(vec!["started".to_owned()]);
+    ///     TRACES
+    ///         .scope_borrowed(&mut traces, async {
+    ///             TRACES.with(|traces| traces.borrow_mut().push("handled".to_owned()));
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(traces.into_inner(), vec!["started", "handled"]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_borrowed<'a, F>(
+        &'static self,
+        value: &'a mut T,
+        future: F,
+    ) -> BorrowedScopedFuture<'a, T, F>
+    where
+        T: Default,
+        F: Future,
+    {
+        BorrowedScopedFuture::new(self.scope(std::mem::take(value), future), value)
+    }
+
+    /// Sets the value from the given `slot` as the future-local value for the future `F`, and
+    /// writes the final value back to the slot.
+    ///
+    /// The value is taken out of the slot on the first poll, and written back when the future
+    /// `F` completes or the scoped future is dropped. This allows to reuse a single buffer across
+    /// many scoped runs, for example, in a hot loop.
+    ///
+    /// # Panics
+    ///
+    /// The scoped future will panic on the first poll if the slot is empty.
+    ///
+    /// ```rust
+    /// use std::cell::RefCell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static BUFFER: FutureOnceCell<RefCell<String>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut slot = Some(RefCell::new(String::with_capacity(64)));
+    ///     for i in 0..3 {
+    ///         BUFFER
+    ///             .scope_into(&mut slot, async move {
+    ///                 BUFFER.with(|buffer| buffer.borrow_mut().push_str(&i.to_string()));
+    ///             })
+    ///             .await;
+    ///     }
+    ///
+    ///     assert_eq!(slot.unwrap().into_inner(), "012");
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_into<'a, F>(
+        &'static self,
+        slot: &'a mut Option<T>,
+        future: F,
+    ) -> SlotScopedFuture<'a, T, F>
+    where
+        F: Future,
+    {
+        SlotScopedFuture::new(&self.0, slot, future)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, or merges it into the value
+    /// of an outer scope of this future local.
+    ///
+    /// If the future local already has a value set by an outer scope at the first poll, the
+    /// `merge` closure combines the outer value with the given one in place, and the future `F`
+    /// runs with the merged outer value. In this case the scoped future returns `None` instead
+    /// of the value, since it remains in the outer scope. Otherwise, the given value is set as a
+    /// fresh scope, and the scoped future returns it in the same way as the [`Self::scope`].
+    ///
+    /// The outer value is taken out of the future local while the `merge` closure runs, so the
+    /// closure sees no value. After the merge, the merged value is moved into the scoped future
+    /// for the duration of each poll and moved back to the outer scope afterwards.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TRACES: FutureOnceCell<Vec<&str>> = FutureOnceCell::new();
+    ///
+    /// async fn child() {
+    ///     let (traces, ()) = TRACES
+    ///         .scope_merged(vec!["child"], async {}, |outer, traces| outer.extend(traces))
+    ///         .await;
+    ///     // The child traces are appended to the parent ones.
+    ///     assert_eq!(traces, None);
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (traces, ()) = TRACES.scope(vec!["parent"], child()).await;
+    ///     assert_eq!(traces, vec!["parent", "child"]);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Once the value has been merged, the scoped future panics if it's polled outside of the
+    /// outer scope.
+    #[inline]
+    pub fn scope_merged<F, M>(
+        &'static self,
+        value: T,
+        future: F,
+        merge: M,
+    ) -> MergedScopedFuture<T, F, M>
+    where
+        F: Future,
+        M: FnOnce(&mut T, T),
+    {
+        MergedScopedFuture::new(self.scope(value, future), merge)
+    }
+
+    /// Creates a builder of a scoped future with the given value, which allows to combine
+    /// several scope options, see the [`ScopeBuilder`] for details.
+    #[inline]
+    pub fn build_scope(&'static self, value: T) -> ScopeBuilder<T> {
+        ScopeBuilder::new(self, value)
+    }
+
+    /// Runs the fallible future created by `make_future` with a fresh future-local value created
+    /// by `make_value`, and retries it up to `retries` times if it returns an error.
+    ///
+    /// Each attempt gets its own clean context, for example, a fresh trace id, so the changes
+    /// made by the failed attempt are not visible to the next one. The scoped future returns the
+    /// value and the output of the last attempt, which is either the first successful one, or the
+    /// last failed one if all the retries are exhausted.
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static ATTEMPT_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let next_id = AtomicU64::new(1);
+    ///     let (attempt_id, output) = ATTEMPT_ID
+    ///         .scope_retry(
+    ///             || next_id.fetch_add(1, Ordering::Relaxed),
+    ///             || async {
+    ///                 let id = ATTEMPT_ID.get();
+    ///                 if id < 3 {
+    ///                     Err(format!("attempt {id} failed"))
+    ///                 } else {
+    ///                     Ok(id * 10)
+    ///                 }
+    ///             },
+    ///             5,
+    ///         )
+    ///         .await;
+    ///
+    ///     assert_eq!(attempt_id, 3);
+    ///     assert_eq!(output, Ok(30));
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_retry<V, M, Fut, O, E>(
+        &'static self,
+        make_value: V,
+        make_future: M,
+        retries: usize,
+    ) -> RetryScopedFuture<T, V, M, Fut>
+    where
+        V: FnMut() -> T,
+        M: FnMut() -> Fut,
+        Fut: Future<Output = Result<O, E>>,
+    {
+        RetryScopedFuture::new(self, make_value, make_future, retries)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and calls the `on_pending`
+    /// callback with the value each time the future `F` is suspended.
+    ///
+    /// The callback sees the value with all the modifications made during the poll, and it is
+    /// not called on completion. This allows to observe the accumulating context periodically,
+    /// for example, to report the progress.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static PROGRESS: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut reports = Vec::new();
+    ///     PROGRESS
+    ///         .scope_on_pending(
+    ///             Cell::new(0),
+    ///             async {
+    ///                 for _ in 0..3 {
+    ///                     PROGRESS.with(|x| x.set(x.get() + 1));
+    ///                     tokio::task::yield_now().await;
+    ///                 }
+    ///             },
+    ///             |progress| reports.push(progress.get()),
+    ///         )
+    ///         .await;
+    ///
+    ///     assert_eq!(reports, vec![1, 2, 3]);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_on_pending<F, P>(
+        &'static self,
+        value: T,
+        future: F,
+        on_pending: P,
+    ) -> OnPendingScopedFuture<T, F, P>
+    where
+        F: Future,
+        P: FnMut(&T),
+    {
+        OnPendingScopedFuture::new(self.scope(value, future), on_pending)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and catches a panic of the
+    /// future `F`.
+    ///
+    /// Unlike the [`Self::scope`] the scoped future returns the future-local value even if the
+    /// future `F` panics, along with the panic payload. This is useful to keep the context of the
+    /// crashed future, for example, for logging.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (request_id, result) = REQUEST_ID
+    ///         .scope_catch(42, async { panic!("request failed") })
+    ///         .await;
+    ///
+    ///     assert_eq!(request_id, 42);
+    ///     assert!(result.is_err());
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_catch<F>(&'static self, value: T, future: F) -> CatchUnwind<T, F>
+    where
+        F: Future,
+    {
+        CatchUnwind::new(self.scope(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and converts a panic of
+    /// the future `F` into the [`ScopeError`].
+    ///
+    /// It works in the same way as the [`Self::scope_catch`], but provides a single fallible
+    /// entry point, which never propagates panics out of the scope. The error keeps the
+    /// future-local value, and allows to distinguish the panics caused by accessing a future
+    /// local without setting it first by the [`ScopeError::is_access_error`] method.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    /// static USER: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = REQUEST_ID.scope_no_panic(42, async { USER.with(String::len) }).await;
+    ///
+    ///     let error = result.unwrap_err();
+    ///     assert!(error.is_access_error());
+    ///     assert_eq!(error.into_value(), 42);
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_no_panic<F>(&'static self, value: T, future: F) -> NoPanicScopedFuture<T, F>
+    where
+        F: Future,
+    {
+        NoPanicScopedFuture::new(self.scope_catch(value, future))
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`, and returns a handle which
+    /// allows to observe the state of the scoped future from outside of it.
+    ///
+    /// The handle reports whether the scoped future is still running, has been completed, or has
+    /// been dropped before completion, i.e. cancelled.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (handle, future) = REQUEST_ID.scope_with_handle(42, async { REQUEST_ID.get() });
+    ///     assert!(handle.is_running());
+    ///
+    ///     tokio::spawn(future).await.unwrap();
+    ///     assert!(handle.is_completed());
+    /// }
+    /// ```
+    #[inline]
+    pub fn scope_with_handle<F>(
+        &'static self,
+        value: T,
+        future: F,
+    ) -> (ScopeHandle, WithScopeHandle<T, F>)
+    where
+        F: Future,
+    {
+        let handle = ScopeHandle::new();
+        let future = WithScopeHandle::new(self.scope(value, future), handle.clone());
+        (handle, future)
+    }
+
+    /// Sets a sensitive value `T` as the future-local value for the future `F`, and zeroes it
+    /// once the scope ends.
+    ///
+    /// The value is swapped out of the thread local storage between the polls, so it never stays
+    /// in the slot of a worker thread after the poll returns. However, a regular scope simply
+    /// drops the value, leaving its content in the freed memory. This scope calls the
+    /// [`Zeroize::zeroize`](zeroize::Zeroize::zeroize) on the value right before it's dropped,
+    /// both when the future `F` completes and when the scoped future is cancelled, so the auth
+    /// tokens or keys are overwritten. Thus the value is not returned on completion.
+    ///
+    /// Note that only the final location of the value is zeroed, the copies left by the moves of
+    /// the value itself are not. For the heap allocated values, like [`Vec`] or [`String`], only
+    /// the pointer is moved, so their content is zeroed in full.
+    ///
+    /// ```rust
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// static TOKEN: FutureOnceCell<Vec<u8>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let len = TOKEN
+    ///         .scope_zeroizing(b"secret".to_vec(), async { TOKEN.with(Vec::len) })
+    ///         .await;
+    ///     // The token is zeroed before it has been dropped.
+    ///     assert_eq!(len, 6);
+    /// }
+    /// ```
+    #[cfg(feature = "zeroize")]
+    #[inline]
+    pub fn scope_zeroizing<F>(&'static self, value: T, future: F) -> ScopedFuture<T, F>
+    where
+        T: zeroize::Zeroize,
+        F: Future,
+    {
+        self.scope(value, future)
+            .wipe_on_drop(T::zeroize)
+            .discard_value()
+    }
+}
+
+impl<P> FutureOnceCell<Pin<P>>
+where
+    P: Deref + Send + 'static,
+{
+    /// Acquires a pinned reference to the value in this future local storage.
+    ///
+    /// The value stored inline in a future local can't be pinned: a scoped future swaps it in and
+    /// out of the thread local storage on every poll, so it is moved between the polls. Thus the
+    /// pinned access is provided only for the values stored behind a pinned pointer, like the
+    /// `Pin<Box<T>>` or `Pin<Arc<T>>`. The pointer itself is moved by the swaps, but the pointee
+    /// stays in place until it's dropped, which upholds the pinning guarantees without any
+    /// `unsafe` code.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    ///
+    /// ```rust
+    /// use std::{marker::PhantomPinned, pin::Pin};
+    ///
+    /// use future_local_storage::FutureOnceCell;
+    ///
+    /// struct Buffer {
+    ///     data: [u8; 4],
+    ///     _pinned: PhantomPinned,
+    /// }
+    ///
+    /// static BUFFER: FutureOnceCell<Pin<Box<Buffer>>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let buffer = Box::pin(Buffer {
+    ///         data: [1, 2, 3, 4],
+    ///         _pinned: PhantomPinned,
+    ///     });
+    ///     let address = std::ptr::addr_of!(*buffer);
+    ///
+    ///     let (_, (len, same)) = BUFFER
+    ///         .scope(buffer, async move {
+    ///             tokio::task::yield_now().await;
+    ///             BUFFER.with_pinned(|buffer: Pin<&Buffer>| {
+    ///                 (buffer.data.len(), std::ptr::eq(&*buffer, address))
+    ///             })
+    ///         })
+    ///         .await;
+    ///     assert_eq!(len, 4);
+    ///     // The buffer hasn't been moved across the suspension.
+    ///     assert!(same);
+    /// }
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn with_pinned<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(Pin<&P::Target>) -> R,
+    {
+        self.with(|value| f(value.as_ref()))
+    }
+}
+
+impl<T: Debug + Send + 'static> Debug for FutureOnceCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureOnceCell").field(&self.0).finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<T>> for FutureOnceCell<T> {
+    fn as_ref(&self) -> &FutureLocalKey<T> {
+        &self.0
+    }
+}
+
+/// A marker trait for the types which can be stored in a future local storage.
+///
+/// Future local values migrate between the executor threads along with the future, so they must
+/// be [`Send`] and `'static`. This trait is implemented for all such types, it only exists to
+/// provide a clear compiler error message if this requirement isn't met.
+///
+/// ```rust,compile_fail
+/// use std::rc::Rc;
+///
+/// use future_local_storage::FutureOnceCell;
+///
+/// // error: `Rc<u64>` cannot be stored in a future local storage
+/// static VALUE: FutureOnceCell<Rc<u64>> = FutureOnceCell::new();
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be stored in a future local storage",
+    label = "future local values must be `Send + 'static`",
+    note = "future local values migrate between the executor threads along with the future",
+    note = "consider using a thread-safe alternative, for example `Arc` instead of `Rc`"
+)]
+pub trait FutureLocalValue: Send + 'static {}
+
+impl<T: Send + 'static> FutureLocalValue for T {}
+
+/// The borrow state of a future local, returned by the [`FutureOnceCell::borrow_state`] method.
+///
+/// The [`RefCell`] doesn't expose the number of the shared borrows, so it is not reported.
+#[cfg(feature = "debug")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorrowState {
+    /// The future local doesn't have a value set and is not borrowed.
+    Unset,
+    /// The future local has a value set, which is not borrowed.
+    Unborrowed,
+    /// The future local is immutably borrowed, for example, by the [`FutureOnceCell::with`]
+    /// call.
+    Shared,
+    /// The future local is mutably borrowed, for example, while a scoped future swaps its value.
+    Mutable,
+}
+
+/// Acquires references to the values of the two future local storages at once.
+///
+/// This is a shorthand for the nested [`FutureOnceCell::with`] calls, see also the [`with!`] macro
+/// for an arbitrary number of cells.
+///
+/// # Panics
+///
+/// This function will panic if any of the future locals doesn't have a value set.
+///
+/// ```rust
+/// use future_local_storage::{with2, FutureOnceCell};
+///
+/// static TRACE_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static SPAN_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let future = async { with2(&TRACE_ID, &SPAN_ID, |trace, span| format!("{trace}:{span}")) };
+///     let (_, (_, ids)) = TRACE_ID.scope(1, SPAN_ID.scope(2, future)).await;
+///     assert_eq!(ids, "1:2");
+/// }
+/// ```
+#[inline]
+pub fn with2<A, B, R>(
+    a: &'static FutureOnceCell<A>,
+    b: &'static FutureOnceCell<B>,
+    f: impl FnOnce(&A, &B) -> R,
+) -> R
+where
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    a.with(|a| b.with(|b| f(a, b)))
+}
+
+/// Acquires references to the values of the several future local storages at once.
+///
+/// The macro expands to the nested `with` calls in the given order, with the closure
+/// arguments bound to the values of the corresponding cells.
+///
+/// # Panics
+///
+/// The expanded code will panic if any of the future locals doesn't have a value set.
+///
+/// ```rust
+/// use future_local_storage::{with, FutureOnceCell};
+///
+/// static A: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static B: FutureOnceCell<String> = FutureOnceCell::new();
+/// static C: FutureOnceCell<bool> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let future = async { with!(A, B, C => |a, b, c| format!("{a} {b} {c}")) };
+///     let (_, (_, (_, output))) = A.scope(1, B.scope("2".to_owned(), C.scope(true, future))).await;
+///     assert_eq!(output, "1 2 true");
+/// }
+/// ```
+#[macro_export]
+macro_rules! with {
+    (@nest [$cell:expr $(, $cells:expr)*] [$arg:pat_param $(, $args:pat_param)*] $body:expr) => {
+        $cell.with(|$arg| $crate::with!(@nest [$($cells),*] [$($args),*] $body))
+    };
+    (@nest [] [] $body:expr) => {
+        $body
+    };
+    ($($cell:expr),+ => |$($arg:pat_param),+| $body:expr) => {
+        $crate::with!(@nest [$($cell),+] [$($arg),+] $body)
+    };
+}
+
+/// Sets the value of a future local storage for the remainder of the current async function.
+///
+/// The macro takes the rest of the function body after the `CELL, value;` header, and must be
+/// the tail expression of the function. It expands to the scoped `async move` block awaited in
+/// place, so the early `return` and the `?` operator inside the body work as usual, and the
+/// value is unset when the rest of the body completes. The value itself is discarded, use the
+/// [`FutureOnceCell::scope`] to get it back.
+///
+/// A guard object, which sets the value until the end of the enclosing block, can't be sound
+/// under the swapping model: the guard doesn't know when the enclosing future is suspended, so
+/// the value would leak to the other futures polled on the same thread.
+///
+/// ```rust
+/// use future_local_storage::{scope_here, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// async fn handle(raw_id: &str) -> Result<u64, std::num::ParseIntError> {
+///     let request_id = raw_id.parse()?;
+///     scope_here!(REQUEST_ID, request_id;
+///         tokio::task::yield_now().await;
+///         if REQUEST_ID.get() == 0 {
+///             return Ok(0);
+///         }
+///         let offset: u64 = "1".parse()?;
+///         Ok(REQUEST_ID.get() + offset)
+///     )
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     assert_eq!(handle("41").await, Ok(42));
+///     assert_eq!(handle("0").await, Ok(0));
+///     assert!(handle("invalid").await.is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! scope_here {
+    ($cell:expr, $value:expr; $($body:tt)*) => {
+        $crate::FutureLocalStorage::with_scope_out(async move { $($body)* }, &$cell, $value).await
+    };
+}
+
+/// Sets the values of the several future local storages for a [`Future`] at once.
+///
+/// The macro expands to the nested scopes in the given order, so the first cell is the
+/// outermost one. Each scope is described by the `CELL => name = value` clause, where the
+/// `= value` part can be omitted to use the variable `name` as the value.
+///
+/// On completion the scoped future returns a struct, which fields are named after the scopes
+/// and hold the corresponding future local values, along with the future output.
+///
+/// ```rust
+/// use future_local_storage::{scope_all, with, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static USER: FutureOnceCell<String> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let user = "alice".to_owned();
+///     let (values, output) = scope_all! {
+///         REQUEST_ID => request_id = 42,
+///         USER => user;
+///         async { with!(REQUEST_ID, USER => |id, user| format!("{id}:{user}")) }
+///     }
+///     .await;
+///
+///     assert_eq!(values.request_id, 42);
+///     assert_eq!(values.user, "alice");
+///     assert_eq!(output, "42:alice");
+/// }
+/// ```
+#[macro_export]
+macro_rules! scope_all {
+    (@value $name:ident = $value:expr) => {
+        $value
+    };
+    (@value $name:ident) => {
+        $name
+    };
+    (@chain [$cell:expr, $name:ident $(, $cells:expr, $names:ident)*] $future:expr) => {
+        $crate::FutureLocalStorage::with_scope(
+            $crate::scope_all!(@chain [$($cells, $names),*] $future),
+            &$cell,
+            $name,
+        )
+    };
+    (@chain [] $future:expr) => {
+        $future
+    };
+    (@pattern [$name:ident $(, $names:ident)*] $output:ident) => {
+        ($name, $crate::scope_all!(@pattern [$($names),*] $output))
+    };
+    (@pattern [] $output:ident) => {
+        $output
+    };
+    ($($cell:expr => $name:ident $(= $value:expr)?),+ $(,)?; $future:expr $(,)?) => {{
+        #[allow(non_camel_case_types, dead_code)]
+        #[derive(Debug)]
+        struct ScopeValues<$($name),+> {
+            $($name: $name),+
+        }
+
+        // The future is evaluated first, so the value bindings do not shadow its variables.
+        let future = $future;
+        $(let $name = $crate::scope_all!(@value $name $(= $value)?);)+
+        let future = $crate::scope_all!(@chain [$($cell, $name),+] future);
+        async move {
+            let $crate::scope_all!(@pattern [$($name),+] output) = future.await;
+            (ScopeValues { $($name),+ }, output)
+        }
+    }};
+}
+
+/// Sets the value of a future local storage for a `select!` branch future.
+///
+/// The macro expands to the [`ScopedFuture`], which yields just the output of the given future,
+/// so it can be used as a branch of the `tokio::select!` or `futures::select!` directly, without
+/// naming the scoped future type or destructuring the value. To poll the same scoped future by
+/// reference in a loop, pin it first, for example, by the `tokio::pin!`; it can be polled again
+/// after completion only if the inner future allows it.
+///
+/// ```rust
+/// use future_local_storage::{scoped_arm, FutureOnceCell};
+///
+/// static SOURCE: FutureOnceCell<&str> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let source = tokio::select! {
+///         source = scoped_arm!(SOURCE, "cache", async { SOURCE.get() }) => source,
+///         source = scoped_arm!(SOURCE, "db", async {
+///             std::future::pending::<()>().await;
+///             SOURCE.get()
+///         }) => source,
+///     };
+///     assert_eq!(source, "cache");
+/// }
+/// ```
+#[macro_export]
+macro_rules! scoped_arm {
+    ($cell:expr, $value:expr, $future:expr $(,)?) => {
+        $crate::FutureLocalStorage::with_scope_out($future, &$cell, $value)
+    };
+}
+
+/// Attaches future local storage values to a [`Future`].
+///
+/// Extension trait allowing futures to have their own static variables.
+///
+/// The boxed futures, like the ones returned by the `async-trait` methods, are futures
+/// themselves, so they can be scoped in the same way:
+///
+/// ```rust
+/// use std::{future::Future, pin::Pin};
+///
+/// use future_local_storage::{FutureLocalStorage, FutureOnceCell};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// // This is what the `async-trait` expands an `async fn handle(&self) -> u64` method to.
+/// trait Handler {
+///     fn handle(&self) -> Pin<Box<dyn Future<Output = u64> + Send + '_>>;
+/// }
+///
+/// struct Echo;
+///
+/// impl Handler for Echo {
+///     fn handle(&self) -> Pin<Box<dyn Future<Output = u64> + Send + '_>> {
+///         Box::pin(async { REQUEST_ID.get() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (_, output) = Echo.handle().with_scope(&REQUEST_ID, 42).await;
+///     assert_eq!(output, 42);
+/// }
+/// ```
+pub trait FutureLocalStorage: Future + Sized + private::Sealed {
+    /// Sets a given value as the future local value of this future.
+    ///
+    /// Each future instance will have its own state of the attached value.
+    ///
+    /// ```rust
+    /// use std::cell::Cell;
+    ///
+    /// use future_local_storage::{FutureOnceCell, FutureLocalStorage};
+    ///
+    /// static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (output, answer) = async {
+    ///         VALUE.with(|x| {
+    ///             let value = x.get();
+    ///             x.set(value + 1);
+    ///         });
+    ///
+    ///         42
+    ///     }.with_scope(&VALUE, Cell::from(0)).await;
+    /// }
+    /// ```
+    fn with_scope<T, S>(self, scope: &'static S, value: T) -> ScopedFutureWithValue<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>;
+
+    /// Sets a given value as the future local value of this future, with the given `policy`
+    /// applied if the future local already has a value set by an outer scope.
+    ///
+    /// ```rust
+    /// use future_local_storage::{FutureLocalStorage, FutureOnceCell, OnConflict};
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (outer, inner) = async {
+    ///         let (_, inner) = async { VALUE.get() }
+    ///             .with_scope_with_policy(&VALUE, 2, OnConflict::Nest)
+    ///             .await;
+    ///         assert_eq!(VALUE.get(), 1);
+    ///         inner
+    ///     }
+    ///     .with_scope(&VALUE, 1)
+    ///     .await;
+    ///
+    ///     assert_eq!((outer, inner), (1, 2));
+    /// }
+    /// ```
+    fn with_scope_with_policy<T, S>(
+        self,
+        scope: &'static S,
+        value: T,
+        policy: OnConflict,
+    ) -> ScopedFutureWithValue<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>;
+
+    /// Sets a given value as the future local value of this future, and discards the value on
+    /// completion.
+    ///
+    /// It is a shorthand for the [`FutureLocalStorage::with_scope`] followed by the
+    /// [`ScopedFutureWithValue::discard_value`].
+    ///
+    /// ```rust
+    /// use future_local_storage::{FutureLocalStorage, FutureOnceCell};
+    ///
+    /// static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let answer = async { VALUE.get() * 2 }.with_scope_out(&VALUE, 21).await;
+    ///     assert_eq!(answer, 42);
+    /// }
+    /// ```
+    fn with_scope_out<T, S>(self, scope: &'static S, value: T) -> ScopedFuture<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>,
+    {
+        self.with_scope(scope, value).discard_value()
+    }
+
+    /// Sets a value produced by the given boxed `init` closure as the future local value of this
+    /// future.
+    ///
+    /// The closure is called on the first poll of the scoped future, so the value is not
+    /// constructed if the future is never polled. The type erased constructor allows to choose
+    /// the value construction logic at runtime, for example, by dynamically registered
+    /// middlewares.
+    ///
+    /// ```rust
+    /// use future_local_storage::{FutureLocalStorage, FutureOnceCell};
+    ///
+    /// static TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+    ///
+    /// fn tenant_init(prefix: &'static str) -> Box<dyn FnOnce() -> String + Send> {
+    ///     Box::new(move || format!("{prefix}-tenant"))
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tenant, len) = async { TENANT.with(String::len) }
+    ///         .with_scope_boxed_init(&TENANT, tenant_init("acme"))
+    ///         .await;
+    ///
+    ///     assert_eq!(tenant, "acme-tenant");
+    ///     assert_eq!(len, 11);
+    /// }
+    /// ```
+    fn with_scope_boxed_init<T, S>(
+        self,
+        scope: &'static S,
+        init: Box<dyn FnOnce() -> T + Send>,
+    ) -> BoxedInitScopedFuture<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>;
+}
+
+/// Attaches future local values to streams.
+///
+/// The value is set while the stream is polled, so it is available inside the stream body
+/// across the yield points, including the generator-like streams created by the `stream!`
+/// macro of the `async-stream` crate or by the [`unfold`] function. Such streams are not
+/// [`Unpin`], so the scoped stream has to be pinned before polling, for example, by the
+/// [`std::pin::pin!`] macro.
+///
+/// [`unfold`]: https://docs.rs/futures/latest/futures/stream/fn.unfold.html
+///
+/// ```rust
+/// use std::pin::pin;
+///
+/// use futures_util::{stream, StreamExt};
+/// use future_local_storage::{FutureOnceCell, StreamLocalStorage};
+///
+/// static PREFIX: FutureOnceCell<&str> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let lines = stream::unfold(0, |i| async move {
+///         tokio::task::yield_now().await;
+///         (i < 2).then(|| (format!("{}{i}", PREFIX.get()), i + 1))
+///     });
+///     let mut lines = pin!(lines.with_scope(&PREFIX, "> "));
+///
+///     assert_eq!(lines.next().await.as_deref(), Some("> 0"));
+///     assert_eq!(lines.next().await.as_deref(), Some("> 1"));
+///     assert_eq!(lines.next().await, None);
+///     assert_eq!(lines.take_value(), Some("> "));
+/// }
+/// ```
+#[cfg(feature = "stream")]
+pub trait StreamLocalStorage: futures_core::Stream + Sized + private::SealedStream {
+    /// Sets a given value as the future local value of this stream.
+    ///
+    /// The value is returned by the [`ScopedStream::take_value`] method.
+    fn with_scope<T, S>(self, scope: &'static S, value: T) -> ScopedStream<T, Self>
+    where
+        T: Send,
+        S: AsRef<FutureLocalKey<T>>;
+}
+
+mod private {
+    use std::future::Future;
+
+    pub trait Sealed {}
+
+    impl<F: Future> Sealed for F {}
+
+    #[cfg(feature = "stream")]
+    pub trait SealedStream {}
+
+    #[cfg(feature = "stream")]
+    impl<S: futures_core::Stream> SealedStream for S {}
 }
 
-#[cfg(test)]
-mod tests {
-    use std::cell::{Cell, RefCell};
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        sync::Mutex,
+        task::{Context, Poll},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::FutureLocalStorage;
+
+    #[test]
+    fn test_once_cell_without_future() {
+        static LOCK: FutureOnceCell<RefCell<String>> = FutureOnceCell::new();
+        LOCK.raw_local_key()
+            .borrow_mut()
+            .replace(RefCell::new("0".to_owned()));
+
+        assert_eq!(LOCK.with(|x| x.borrow().clone()), "0".to_owned());
+        LOCK.with(|x| x.replace("42".to_owned()));
+        assert_eq!(LOCK.with(|x| x.borrow().clone()), "42".to_owned());
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_nested_with() {
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        let (_, output) = VALUE
+            .scope("42".to_owned(), async {
+                VALUE.with(|outer| VALUE.with(|inner| format!("{outer}{inner}")))
+            })
+            .await;
+
+        assert_eq!(output, "4242");
+    }
+
+    #[test]
+    #[should_panic(expected = "no db connection in scope")]
+    fn test_future_once_cell_with_expect() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        VALUE.with_expect("no db connection in scope", |_| {});
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_output() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let (output, ()) = VALUE
+            .scope(Cell::from(0), async {
+                VALUE.with(|x| {
+                    let value = x.get();
+                    x.set(value + 1);
+                });
+            })
+            .await;
+
+        assert_eq!(output.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_scoped_future_auto_traits() {
+        fn assert_send<T: Send>(_: &T) {}
+        fn assert_unpin<T: Unpin>(_: &T) {}
+
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        let future = VALUE.scope(String::new(), std::future::ready(42));
+        assert_send(&future);
+        assert_unpin(&future);
+        assert_send(&future.discard_value());
+
+        // The erased future is `Unpin` even if the inner one is not.
+        let future = VALUE.scope_static(String::new(), async {
+            tokio::task::yield_now().await;
+        });
+        assert_send(&future);
+        assert_unpin(&future);
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_discard_value() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let fut_1 = async {
+            for _ in 0..42 {
+                VALUE.with(|x| {
+                    let value = x.get();
+                    x.set(value + 1);
+                });
+                tokio::task::yield_now().await;
+            }
+
+            VALUE.with(Cell::get)
+        }
+        .with_scope(&VALUE, Cell::new(0))
+        .discard_value();
+
+        let fut_2 = async { VALUE.with(Cell::get) }
+            .with_scope(&VALUE, Cell::new(15))
+            .discard_value();
+
+        assert_eq!(fut_1.await, 42);
+        assert_eq!(fut_2.await, 15);
+        assert_eq!(
+            tokio::spawn(
+                async { VALUE.with(Cell::get) }
+                    .with_scope(&VALUE, Cell::new(115))
+                    .discard_value()
+            )
+            .await
+            .unwrap(),
+            115
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_map_output() {
+        static VALUE: FutureOnceCell<RefCell<Vec<u64>>> = FutureOnceCell::new();
+
+        let output = VALUE
+            .scope(RefCell::new(vec![1]), async {
+                tokio::task::yield_now().await;
+                VALUE.with(|x| x.borrow_mut().push(2));
+                3
+            })
+            .discard_value()
+            .map_output(|output| VALUE.with(|x| x.borrow().iter().sum::<u64>() + output))
+            .await;
+
+        assert_eq!(output, 6);
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_inspect_complete() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let calls = Cell::new(0);
+        let (value, output) = VALUE
+            .scope(vec![1, 2], async {
+                tokio::task::yield_now().await;
+                3
+            })
+            .inspect_complete(|value, output| {
+                calls.set(calls.get() + 1);
+                assert_eq!((value.as_slice(), *output), ([1, 2].as_slice(), 3));
+                // The scope is still installed.
+                assert_eq!(VALUE.with(Vec::len), 2);
+            })
+            .await;
+        assert_eq!((value, output), (vec![1, 2], 3));
+        assert_eq!(calls.get(), 1);
+
+        // The closure isn't called on cancellation.
+        let mut future = Box::pin(
+            VALUE
+                .scope(vec![], std::future::pending::<()>())
+                .inspect_complete(|_, ()| calls.set(calls.get() + 1)),
+        );
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_scope_catch() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let (value, result) = VALUE
+            .scope_catch(Cell::new(0), async {
+                VALUE.with(|x| x.set(1));
+                tokio::task::yield_now().await;
+                VALUE.with(|x| x.set(2));
+                panic!("something went wrong");
+            })
+            .await;
+
+        assert_eq!(value.get(), 2);
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<&str>(),
+            Some(&"something went wrong")
+        );
+        // Make sure that the value has been swapped back.
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_scope_sticky() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut future = Box::pin(VALUE.scope_sticky(42, async {
+            tokio::task::yield_now().await;
+            VALUE.get()
+        }));
+        // The value remains set while the future is suspended.
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        assert_eq!(VALUE.get(), 42);
+        // And it's removed on completion.
+        assert_eq!(future.await, (42, 42));
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_scope_sticky_drop() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut future = Box::pin(VALUE.scope_sticky(42, std::future::pending::<()>()));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        assert_eq!(VALUE.get(), 42);
+
+        drop(future);
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_fork() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let (parent, child) = VALUE
+            .scope(Cell::new(1), async {
+                let child = VALUE.fork(async {
+                    VALUE.with(|x| x.set(x.get() + 10));
+                    tokio::task::yield_now().await;
+                });
+                VALUE.with(|x| x.set(x.get() + 1));
+
+                let (child, ()) = tokio::spawn(child).await.unwrap();
+                child
+            })
+            .await;
+        // Parent and child values are independent.
+        assert_eq!(parent.get(), 2);
+        assert_eq!(child.get(), 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot access a future local value without setting it first")]
+    fn test_future_once_cell_fork_outside_scope() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        drop(VALUE.fork(async {}));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_replace_scope() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (last, (replaced, restored)) = VALUE
+            .scope(1, async {
+                // The nested scope restores the outer value.
+                let (_, nested) = async {
+                    tokio::task::yield_now().await;
+                    VALUE.get()
+                }
+                .with_scope_with_policy(&VALUE, 2, OnConflict::Nest)
+                .await;
+                assert_eq!(nested, 2);
+                let restored = VALUE.get();
+                // The replacement scope keeps the new value.
+                let (original, ()) = VALUE
+                    .replace_scope(3, async {
+                        tokio::task::yield_now().await;
+                        assert_eq!(VALUE.get(), 3);
+                    })
+                    .await;
+                (original, restored)
+            })
+            .await;
+
+        assert_eq!(restored, 1);
+        assert_eq!(replaced, Some(1));
+        assert_eq!(last, 3);
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_replace_scope_without_outer_scope() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (original, seen) = VALUE
+            .replace_scope(5, async {
+                tokio::task::yield_now().await;
+                VALUE.get()
+            })
+            .await;
+        assert_eq!((original, seen), (None, 5));
+        // The value is not leaked to the thread local storage.
+        assert_eq!(VALUE.current(), Err(AccessError));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_replace_scope_cancelled() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (last, restored) = VALUE
+            .scope(1, async {
+                let mut replace = Box::pin(VALUE.replace_scope(2, std::future::pending::<()>()));
+                assert!(futures_util::poll!(replace.as_mut()).is_pending());
+                assert_eq!(VALUE.get(), 2);
+                // The original value is restored on cancellation.
+                drop(replace);
+                VALUE.get()
+            })
+            .await;
+
+        assert_eq!(restored, 1);
+        assert_eq!(last, 1);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_cancelled_by_timeout() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let result = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            VALUE.scope(42, std::future::pending::<()>()),
+        )
+        .await;
+
+        assert!(result.is_err());
+        // Make sure that the value didn't leak into the thread local storage.
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_scope_moves_unpinned_value() {
+        /// Remembers the address it has been registered at.
+        struct MoveSensitive {
+            registered_at: Cell<usize>,
+            _pinned: std::marker::PhantomPinned,
+        }
+
+        impl MoveSensitive {
+            fn register(&self) {
+                self.registered_at.set(std::ptr::addr_of!(*self) as usize);
+            }
+
+            fn is_moved(&self) -> bool {
+                self.registered_at.get() != std::ptr::addr_of!(*self) as usize
+            }
+        }
+
+        static VALUE: FutureOnceCell<MoveSensitive> = FutureOnceCell::new();
+
+        let value = MoveSensitive {
+            registered_at: Cell::new(0),
+            _pinned: std::marker::PhantomPinned,
+        };
+        let (value, ()) = VALUE
+            .scope(value, async {
+                VALUE.with(MoveSensitive::register);
+                tokio::task::yield_now().await;
+            })
+            .await;
+        // The value is moved out of the thread local storage on completion, so it must not rely
+        // on its address unless it's pinned behind a pointer.
+        assert!(value.is_moved());
+    }
+
+    #[tokio::test]
+    async fn test_foreign_callback_reads_value() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        extern "C" fn callback(level: u32) -> u64 {
+            VALUE.with(|value| value + u64::from(level))
+        }
+
+        extern "C" fn foreign_call(callback: extern "C" fn(u32) -> u64) -> u64 {
+            callback(1)
+        }
+
+        let (_, outputs) = VALUE
+            .scope(41, async {
+                let before = foreign_call(callback);
+                tokio::task::yield_now().await;
+                (before, foreign_call(callback))
+            })
+            .await;
+        assert_eq!(outputs, (42, 42));
+
+        // Outside of a scoped future the value is set for the duration of the call.
+        let guard = VALUE.enter(9);
+        assert_eq!(foreign_call(callback), 10);
+        assert_eq!(guard.exit(), 9);
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_scope_zero_sized_value() {
+        static MARKER: FutureOnceCell<()> = FutureOnceCell::new();
+
+        let ((), (set, output)) = MARKER
+            .scope((), async {
+                tokio::task::yield_now().await;
+                (
+                    FutureLocalKey::is_set(MARKER.as_ref()),
+                    MARKER.with(|()| 42),
+                )
+            })
+            .await;
+        assert!(set);
+        assert_eq!(output, 42);
+        assert!(!FutureLocalKey::is_set(MARKER.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_scope_empty_value() {
+        static ITEMS: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let items = Vec::new();
+        let ptr = items.as_ptr();
+        let (items, len) = ITEMS
+            .scope(items, async {
+                tokio::task::yield_now().await;
+                ITEMS.with(Vec::len)
+            })
+            .await;
+        assert_eq!(len, 0);
+        // The empty value is returned unchanged, without any allocation.
+        assert_eq!(items.capacity(), 0);
+        assert_eq!(items.as_ptr(), ptr);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[tokio::test]
+    async fn test_scope_zeroizing() {
+        static WIPED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+        /// Records its content on drop.
+        struct Secret(Vec<u8>);
+
+        impl zeroize::Zeroize for Secret {
+            fn zeroize(&mut self) {
+                // Keep the length to check the content.
+                self.0.as_mut_slice().zeroize();
+            }
+        }
+
+        impl Drop for Secret {
+            fn drop(&mut self) {
+                WIPED.lock().unwrap().push(self.0.clone());
+            }
+        }
+
+        static TOKEN: FutureOnceCell<Secret> = FutureOnceCell::new();
+
+        // The value is zeroed on cancellation.
+        let mut future =
+            Box::pin(TOKEN.scope_zeroizing(Secret(b"abc".to_vec()), std::future::pending::<()>()));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        // The value is swapped out between the polls.
+        assert!(!FutureLocalKey::is_set(TOKEN.as_ref()));
+        drop(future);
+        assert!(!FutureLocalKey::is_set(TOKEN.as_ref()));
+
+        // And on completion.
+        let seen = TOKEN
+            .scope_zeroizing(Secret(b"xyz".to_vec()), async {
+                tokio::task::yield_now().await;
+                TOKEN.with(|secret| secret.0.clone())
+            })
+            .await;
+        assert_eq!(seen, b"xyz");
+        assert!(!FutureLocalKey::is_set(TOKEN.as_ref()));
+
+        assert_eq!(*WIPED.lock().unwrap(), [vec![0, 0, 0], vec![0, 0, 0]]);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_arm_select() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let slow = scoped_arm!(VALUE, 1, async {
+            for _ in 0..3 {
+                tokio::task::yield_now().await;
+            }
+            VALUE.get() * 10
+        });
+        tokio::pin!(slow);
+
+        let mut outputs = Vec::new();
+        loop {
+            // The pinned scoped arm is polled by reference on each iteration, and the other arm
+            // is scoped to its own value.
+            tokio::select! {
+                biased;
+                output = &mut slow => {
+                    outputs.push(output);
+                    break;
+                }
+                output = scoped_arm!(VALUE, 2, async { VALUE.get() }) => outputs.push(output),
+            }
+        }
+
+        assert_eq!(outputs, [2, 2, 2, 10]);
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_bridge_thread_local() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        thread_local! {
+            static BRIDGED: RefCell<Option<u64>> = const { RefCell::new(None) };
+        }
+
+        fn read_bridged() -> Option<u64> {
+            BRIDGED.with(|value| *value.borrow())
+        }
+
+        let mut future = Box::pin(VALUE.bridge_thread_local(&BRIDGED, 1, async {
+            let before = read_bridged();
+            // A synchronous library updates the thread local.
+            BRIDGED.with(|value| *value.borrow_mut() = Some(5));
+            tokio::task::yield_now().await;
+            (before, VALUE.get(), read_bridged())
+        }));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        // The thread local doesn't see the value between the polls.
+        assert_eq!(read_bridged(), None);
+
+        let (value, output) = future.await;
+        assert_eq!(output, (Some(1), 5, Some(5)));
+        assert_eq!(value, 5);
+        assert_eq!(read_bridged(), None);
+    }
+
+    #[tokio::test]
+    async fn test_with_pinned_keeps_address() {
+        static VALUE: FutureOnceCell<Pin<Box<(u64, std::marker::PhantomPinned)>>> =
+            FutureOnceCell::new();
+
+        let value = Box::pin((42, std::marker::PhantomPinned));
+        let address = std::ptr::addr_of!(*value) as usize;
+        let (_, addresses) = VALUE
+            .scope(value, async {
+                let before = VALUE.with_pinned(|value| std::ptr::addr_of!(*value) as usize);
+                tokio::task::yield_now().await;
+                let after = VALUE.with_pinned(|value| {
+                    assert_eq!(value.0, 42);
+                    std::ptr::addr_of!(*value) as usize
+                });
+                (before, after)
+            })
+            .await;
+        assert_eq!(addresses, (address, address));
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_drop_sees_value() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+        static SEEN: Mutex<Vec<Option<u64>>> = Mutex::new(Vec::new());
+
+        struct ReadOnDrop;
+
+        impl Drop for ReadOnDrop {
+            fn drop(&mut self) {
+                let value = FutureLocalKey::is_set(VALUE.as_ref()).then(|| VALUE.get());
+                SEEN.lock().unwrap().push(value);
+            }
+        }
+
+        let outer = VALUE.scope(1, async {
+            let mut inner = Box::pin(VALUE.scope(2, async {
+                let _guard = ReadOnDrop;
+                std::future::pending::<()>().await;
+            }));
+            assert!(futures_util::poll!(inner.as_mut()).is_pending());
+            // The inner future is dropped with its value installed, and the outer one is
+            // restored afterwards.
+            drop(inner);
+            VALUE.get()
+        });
+        assert_eq!(outer.await, (1, 1));
+        assert_eq!(*SEEN.lock().unwrap(), [Some(2)]);
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_future_once_cell_scope_timeout() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let (value, result) = VALUE
+            .scope_timeout(Cell::new(0), std::time::Duration::from_millis(10), async {
+                VALUE.with(|x| x.set(1));
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                VALUE.with(|x| x.set(2));
+            })
+            .await;
+
+        assert_eq!(value.get(), 1);
+        assert!(result.is_err());
+        assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+
+        let (value, result) = VALUE
+            .scope_timeout(Cell::new(0), std::time::Duration::from_secs(60), async {
+                VALUE.with(|x| x.set(3));
+            })
+            .await;
+        assert_eq!(value.get(), 3);
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_scope_with_parent() {
+        static TRACE: FutureOnceCell<Vec<&str>> = FutureOnceCell::new();
+
+        let child = |name| {
+            TRACE.scope_with_parent(vec![name], &TRACE, async {}, |trace, parent| {
+                trace.extend(parent.into_iter().flatten());
+            })
+        };
+
+        let (root, (first, second)) = TRACE
+            .scope(vec!["root"], async {
+                let (first, ()) = child("first").await;
+                let (second, ()) = TRACE.scope(vec!["nested"], child("second")).await.1;
+                (first, second)
+            })
+            .await;
+
+        assert_eq!(root, vec!["root"]);
+        assert_eq!(first, vec!["first", "root"]);
+        assert_eq!(second, vec!["second", "nested"]);
+        // Without a parent scope.
+        assert_eq!(child("orphan").await.0, vec!["orphan"]);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_poll_by_reference() {
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let mut polls = 0;
+        let mut scoped = VALUE.scope(
+            Cell::new(0),
+            std::future::poll_fn(|cx| {
+                VALUE.with(|x| x.set(x.get() + 1));
+                if VALUE.with(Cell::get) == 3 {
+                    Poll::Ready("done")
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            }),
+        );
+
+        let (value, output) = std::future::poll_fn(|cx| {
+            polls += 1;
+            Pin::new(&mut scoped).poll(cx)
+        })
+        .await;
+
+        assert_eq!(polls, 3);
+        assert_eq!(value.get(), 3);
+        assert_eq!(output, "done");
+    }
+
+    #[tokio::test]
+    async fn test_scoped_future_and_then_scope() {
+        static FIRST: FutureOnceCell<u64> = FutureOnceCell::new();
+        static SECOND: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (first, second, output) = FIRST
+            .scope(1, async {
+                tokio::task::yield_now().await;
+                FIRST.get() + 1
+            })
+            .and_then_scope(
+                &SECOND,
+                |output| output * 10,
+                |output| async move {
+                    tokio::task::yield_now().await;
+                    // The first stage scope is already closed.
+                    assert!(!FutureLocalKey::is_set(FIRST.as_ref()));
+                    SECOND.get() + output
+                },
+            )
+            .await;
+
+        assert_eq!((first, second, output), (1, 20, 22));
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_scope_with_handle() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (handle, future) = VALUE.scope_with_handle(1, async {
+            tokio::task::yield_now().await;
+            VALUE.get()
+        });
+        assert_eq!(handle.state(), ScopeState::Running);
+        assert_eq!(future.await, (1, 1));
+        assert_eq!(handle.state(), ScopeState::Completed);
+
+        let (handle, future) = VALUE.scope_with_handle(2, std::future::pending::<()>());
+        let mut future = Box::pin(future);
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        assert!(handle.is_running());
+        drop(future);
+        assert!(handle.is_cancelled());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_future_once_cell_scope_span() {
+        use std::{
+            io,
+            sync::{Arc, Mutex},
+        };
+
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let output = Output::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer({
+                let output = output.clone();
+                move || output.clone()
+            })
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (request_id, ()) = REQUEST_ID
+            .scope_span(
+                42,
+                async {
+                    tokio::task::yield_now().await;
+                    tracing::info!("handling request {}", REQUEST_ID.get());
+                },
+                |id| vec![("request_id", id.to_string()), ("user", "alice".to_owned())],
+            )
+            .await;
+        assert_eq!(request_id, 42);
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            output.contains(
+                "future_local_scope{scope=\"u64\" fields=request_id=42 user=alice}: \
+                 future_local_storage::tests: handling request 42"
+            ),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn test_future_once_cell_with_provider() {
+        use std::{
+            cell::OnceCell,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Provider;
+
+        impl ThreadLocalProvider<LocalState<u64>> for Provider {
+            fn get_or_init(init: fn() -> LocalState<u64>) -> &'static LocalState<u64> {
+                thread_local! {
+                    static STATE: OnceCell<&'static LocalState<u64>> = const { OnceCell::new() };
+                }
+                STATE.with(|state| {
+                    *state.get_or_init(|| {
+                        INITS.fetch_add(1, Ordering::SeqCst);
+                        Box::leak(Box::new(init()))
+                    })
+                })
+            }
+        }
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::with_provider::<Provider>();
+
+        let threads = (0..4).map(|i| {
+            std::thread::spawn(move || {
+                let output = futures_util::FutureExt::now_or_never(
+                    VALUE.scope(i, async { VALUE.get() * 2 }),
+                );
+                assert_eq!(output, Some((i, i * 2)));
+                assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+            })
+        });
+        threads
+            .into_iter()
+            .try_for_each(std::thread::JoinHandle::join)
+            .unwrap();
+
+        // Each thread has its own state.
+        assert_eq!(INITS.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_async_trait_boxed_future_scope() {
+        #[async_trait::async_trait]
+        trait Handler {
+            async fn handle(&self, request: u64) -> u64;
+        }
+
+        struct Service {
+            factor: u64,
+        }
+
+        #[async_trait::async_trait]
+        impl Handler for Service {
+            async fn handle(&self, request: u64) -> u64 {
+                tokio::task::yield_now().await;
+                request * self.factor + REQUEST_ID.get()
+            }
+        }
+
+        static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let service = Service { factor: 10 };
+        let handler: &(dyn Handler + Sync) = &service;
+        let (request_id, output) = handler.handle(4).with_scope(&REQUEST_ID, 2).await;
+        assert_eq!((request_id, output), (2, 42));
+
+        // The scoped boxed future is still `Send`, so it can be spawned.
+        let future = REQUEST_ID.scope(3, async move { Service { factor: 1 }.handle(1).await });
+        assert_eq!(tokio::spawn(future).await.unwrap(), (3, 4));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_future_once_cell_scope_channel_cancelled() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (receiver, future) = VALUE.scope_channel(1, std::future::pending::<()>());
+        let handle = tokio::spawn(future);
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        // The sender is dropped along with the cancelled future.
+        assert!(receiver.await.is_err());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_future_once_cell_borrow_state() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let key = VALUE.raw_local_key();
+        {
+            let _guard = key.borrow_mut();
+            assert_eq!(VALUE.borrow_state(), BorrowState::Mutable);
+        }
+        {
+            let _guard = key.borrow();
+            assert_eq!(VALUE.borrow_state(), BorrowState::Shared);
+        }
+        // The check itself doesn't leave the cell borrowed.
+        assert_eq!(VALUE.borrow_state(), BorrowState::Unset);
+        assert!(key.try_borrow_mut().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_future_once_cell_async_finalizer_cancelled() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let finalized = &Cell::new(None);
+        let cancelled = Cell::new(None);
+        let mut future = Box::pin(VALUE.scope_with_async_finalizer(
+            1,
+            async {
+                VALUE.with(|_| {});
+                std::future::pending::<()>().await;
+            },
+            |value| async move { finalized.set(Some(value)) },
+            |value| cancelled.set(Some(value)),
+        ));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+
+        assert_eq!(finalized.get(), None);
+        assert_eq!(cancelled.get(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_scope_all() {
+        static A: FutureOnceCell<u64> = FutureOnceCell::new();
+        static B: FutureOnceCell<String> = FutureOnceCell::new();
+        static C: FutureOnceCell<RefCell<Vec<u64>>> = FutureOnceCell::new();
+
+        let b = "b".to_owned();
+        let (values, output) = scope_all! {
+            A => a = 1,
+            B => b,
+            C => c = RefCell::default();
+            async {
+                tokio::task::yield_now().await;
+                C.with(|c| c.borrow_mut().push(A.get()));
+                B.with(String::len)
+            }
+        }
+        .await;
+
+        assert_eq!(values.a, 1);
+        assert_eq!(values.b, "b");
+        assert_eq!(values.c.into_inner(), vec![1]);
+        assert_eq!(output, 1);
+        assert!(!FutureLocalKey::is_set(A.as_ref()));
+    }
+
+    #[test]
+    fn test_scoped_future_migrates_between_threads() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let poll_on_new_thread = |mut future: Pin<Box<ScopedFuture<u64, _>>>| {
+            std::thread::spawn(move || {
+                let waker = futures_util::task::noop_waker();
+                let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+                // The value must not be left on the thread the future was polled on.
+                assert!(!FutureLocalKey::is_set(VALUE.as_ref()));
+                (future, poll)
+            })
+            .join()
+            .unwrap()
+        };
+
+        let future = Box::pin(VALUE.scope_out(42, async {
+            let before = VALUE.get();
+            tokio::task::yield_now().await;
+            (before, VALUE.get())
+        }));
+
+        let (future, poll) = poll_on_new_thread(future);
+        assert!(poll.is_pending());
+        let (_, poll) = poll_on_new_thread(future);
+        assert_eq!(poll, Poll::Ready((42, 42)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_scoped_future_migrates_between_workers() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        // Tasks are moved between workers on `yield_now`, so they are likely to be resumed on
+        // different threads than they were suspended on.
+        let tasks = (0..16).map(|i| {
+            tokio::spawn(VALUE.scope(i, async move {
+                for _ in 0..100 {
+                    assert_eq!(VALUE.get(), i);
+                    tokio::task::yield_now().await;
+                }
+                VALUE.get()
+            }))
+        });
+
+        for (i, task) in futures_util::future::join_all(tasks)
+            .await
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(task.unwrap(), (i as u64, i as u64));
+        }
+    }
+
+    async fn nested_scopes(policy: OnConflict) -> (u64, u64) {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (outer, inner) = async {
+            let (inner, inner_output) = async {
+                tokio::task::yield_now().await;
+                VALUE.get()
+            }
+            .with_scope_with_policy(&VALUE, 2, policy)
+            .await;
+            assert_eq!(inner, 2);
+            // Make sure that the outer value has been restored.
+            assert_eq!(VALUE.get(), 1);
+            inner_output
+        }
+        .with_scope(&VALUE, 1)
+        .await;
+        (outer, inner)
+    }
+
+    #[tokio::test]
+    async fn test_on_conflict_overwrite() {
+        assert_eq!(nested_scopes(OnConflict::Overwrite).await, (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_on_conflict_nest() {
+        assert_eq!(nested_scopes(OnConflict::Nest).await, (1, 2));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "future local value is already set by an outer scope")]
+    async fn test_on_conflict_panic() {
+        nested_scopes(OnConflict::Panic).await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "reentrant scope")]
+    async fn test_on_conflict_panic_reentrant() {
+        nested_scopes(OnConflict::Panic).await;
+    }
+
+    #[tokio::test]
+    async fn test_on_conflict_panic_sequential() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        // Sequential scopes within the same outer future are not reentrant.
+        let (first, second) = async {
+            let (first, ()) = async {}
+                .with_scope_with_policy(&VALUE, 1, OnConflict::Panic)
+                .await;
+            let (second, ()) = async {}
+                .with_scope_with_policy(&VALUE, 2, OnConflict::Panic)
+                .await;
+            (first, second)
+        }
+        .await;
+        assert_eq!((first, second), (1, 2));
+        assert!(!FutureLocalKey::is_polling(VALUE.as_ref()));
+    }
+
+    #[cfg(feature = "debug-warn-unused")]
+    #[tokio::test]
+    async fn test_debug_warn_unused() {
+        use std::sync::Mutex;
+
+        struct Logger(Mutex<Vec<String>>);
+
+        impl log::Log for Logger {
+            fn enabled(&self, _metadata: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        #[derive(Debug, Clone, Copy)]
+        struct Unused;
+        #[derive(Debug, Clone, Copy)]
+        struct Used;
+
+        static LOGGER: Logger = Logger(Mutex::new(Vec::new()));
+        static UNUSED: FutureOnceCell<Unused> = FutureOnceCell::new();
+        static USED: FutureOnceCell<Used> = FutureOnceCell::new();
+
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Warn);
+
+        // The inner scope reads its own value only, so the outer one is still unused.
+        UNUSED
+            .scope(Unused, USED.scope(Used, async { USED.get() }))
+            .await;
+        // The value read in a nested scope of the same future local is not counted as a read of
+        // the outer one.
+        let nested = async { USED.get() }.with_scope_with_policy(&USED, Used, OnConflict::Nest);
+        USED.scope(Used, nested).await;
+
+        let warnings = LOGGER.0.lock().unwrap();
+        let count = |name: &str| warnings.iter().filter(|msg| msg.contains(name)).count();
+        assert_eq!(count(std::any::type_name::<Unused>()), 1);
+        assert_eq!(count(std::any::type_name::<Used>()), 1);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_try_scope_stream_exhausted() {
+        use futures_util::StreamExt as _;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let items = futures_util::stream::iter([Ok::<_, ()>(1), Ok(2)]).map(|item| {
+            // The value is set while the inner stream is polled only.
+            item.map(|x| x + VALUE.get())
+        });
+        let mut stream = std::pin::pin!(VALUE.try_scope_stream(40, items));
+
+        assert_eq!(stream.next().await, Some(Ok(41)));
+        assert!(VALUE.raw_local_key().borrow().is_none());
+        assert_eq!(stream.next().await, Some(Ok(42)));
+        assert_eq!(stream.next().await, None);
+        assert!(stream.is_terminated());
+        assert_eq!(stream.as_mut().take_value(), Some(40));
+        assert_eq!(stream.take_value(), None);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_try_scope_stream_early_error() {
+        use futures_core::Stream as _;
+        use futures_util::StreamExt as _;
+
+        static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
+
+        let items = futures_util::stream::iter([Ok(1), Err("error"), Ok(3)]).inspect(|_| {
+            VALUE.with(|x| x.set(x.get() + 1));
+        });
+        let stream = VALUE.try_scope_stream(Cell::new(0), items);
+        assert_eq!(stream.size_hint(), (0, Some(3)));
+        let mut stream = std::pin::pin!(stream);
 
-    use pretty_assertions::assert_eq;
+        let items = stream.as_mut().collect::<Vec<_>>().await;
+        assert_eq!(items, vec![Ok(1), Err("error")]);
+        assert_eq!(stream.size_hint(), (0, Some(0)));
+        // The stream after the error item is not polled anymore.
+        assert_eq!(stream.take_value().unwrap().get(), 2);
+    }
 
-    use super::*;
-    use crate::FutureLocalStorage;
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_try_scope_stream_inside_outer_scope() {
+        use futures_util::StreamExt as _;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (outer, items) = VALUE
+            .scope(1, async {
+                let items = futures_util::stream::iter([Ok(1), Err("error")])
+                    .map(|item| item.map(|x| x + VALUE.get()));
+                let items = VALUE.try_scope_stream(40, items).collect::<Vec<_>>().await;
+                // The outer value is restored after each poll of the stream.
+                (items, VALUE.get())
+            })
+            .await;
+        assert_eq!(outer, 1);
+        assert_eq!(items, (vec![Ok(41), Err("error")], 1));
+    }
+
+    #[tokio::test]
+    async fn test_current_snapshot() {
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        assert_eq!(VALUE.current(), Err(AccessError));
+        let (value, snapshot) = VALUE
+            .scope("outer".to_owned(), async {
+                let snapshot = VALUE.current();
+                // The snapshot is owned, so the scope may continue to change the value.
+                VALUE
+                    .raw_local_key()
+                    .borrow_mut()
+                    .replace("changed".to_owned());
+                snapshot
+            })
+            .await;
+        assert_eq!(snapshot.as_deref(), Ok("outer"));
+        assert_eq!(value, "changed");
+        assert_eq!(
+            AccessError.to_string(),
+            "cannot access a future local value without setting it first"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scope_borrowed_restores_on_cancel() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let mut value = vec![1];
+        let output = VALUE
+            .scope_borrowed(&mut value, async {
+                VALUE.raw_local_key().borrow_mut().as_mut().unwrap().push(2);
+                VALUE.with(Vec::len)
+            })
+            .await;
+        assert_eq!(output, 2);
+        assert_eq!(value, vec![1, 2]);
+
+        let mut future = Box::pin(VALUE.scope_borrowed(&mut value, async {
+            VALUE.raw_local_key().borrow_mut().as_mut().unwrap().push(3);
+            std::future::pending::<()>().await;
+        }));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+        // The value is written back to the borrowed place even if the future is cancelled.
+        assert_eq!(value, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_scope_no_panic() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+        static UNSET: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let result = VALUE.scope_no_panic(1, async { VALUE.get() + 1 }).await;
+        assert_eq!(result.unwrap(), (1, 2));
+
+        let error = VALUE
+            .scope_no_panic(2, async { panic!("request {} failed", VALUE.get()) })
+            .await
+            .unwrap_err();
+        assert!(!error.is_access_error());
+        assert_eq!(error.message(), Some("request 2 failed"));
+        assert_eq!(
+            error.to_string(),
+            "scoped future panicked: request 2 failed"
+        );
+        // The key is swapped back after the panic.
+        assert!(VALUE.raw_local_key().borrow().is_none());
+        assert_eq!(error.into_value(), 2);
+
+        let error = VALUE
+            .scope_no_panic(3, async { UNSET.get() })
+            .await
+            .unwrap_err();
+        assert!(error.is_access_error());
+        assert_eq!(*error.value(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_with_scope_boxed_init_is_lazy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+
+        let init = || -> Box<dyn FnOnce() -> u64 + Send> {
+            Box::new(|| {
+                INITS.fetch_add(1, Ordering::SeqCst);
+                42
+            })
+        };
+
+        let future = async { VALUE.get() }.with_scope_boxed_init(&VALUE, init());
+        assert_eq!(INITS.load(Ordering::SeqCst), 0);
+        assert_eq!(future.await, (42, 42));
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+
+        // The value is not created if the future is never polled.
+        drop(async {}.with_scope_boxed_init(&VALUE, init()));
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_future_once_cell_scope_traced() {
+        use std::{
+            io,
+            sync::{Arc, Mutex},
+        };
+
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        static NAME: FutureOnceCell<String> = FutureOnceCell::new();
+
+        let output = Output::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer({
+                let output = output.clone();
+                move || output.clone()
+            })
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (name, ()) = NAME
+            .scope_traced(tracing::Level::DEBUG, "first".to_owned(), async {
+                tokio::task::yield_now().await;
+                NAME.raw_local_key()
+                    .borrow_mut()
+                    .replace("completed".to_owned());
+            })
+            .await;
+        assert_eq!(name, "completed");
+        // The cancellation is not traced by default.
+        drop(NAME.scope_traced(tracing::Level::DEBUG, "skipped".to_owned(), async {}));
+        drop(
+            NAME.scope_traced(tracing::Level::WARN, "cancelled".to_owned(), async {})
+                .trace_cancel(),
+        );
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{output}");
+        assert!(
+            lines[0].contains(
+                "DEBUG future_local_storage::future: future local scope finished \
+                 value=\"completed\" cancelled=false"
+            ),
+            "{output}"
+        );
+        assert!(
+            lines[1].contains(
+                "WARN future_local_storage::future: future local scope finished \
+                 value=\"cancelled\" cancelled=true"
+            ),
+            "{output}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scope_into_reuses_slot() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let mut slot = Some(Vec::with_capacity(16));
+        let capacity = slot.as_ref().unwrap().capacity();
+        for i in 0..3 {
+            let len = VALUE
+                .scope_into(&mut slot, async move {
+                    VALUE.raw_local_key().borrow_mut().as_mut().unwrap().push(i);
+                    VALUE.with(Vec::len)
+                })
+                .await;
+            assert_eq!(len, usize::try_from(i).unwrap() + 1);
+        }
+        let value = slot.as_ref().unwrap();
+        assert_eq!(*value, vec![0, 1, 2]);
+        assert_eq!(value.capacity(), capacity);
+
+        // The value is not taken out of the slot until the first poll, and it is written back
+        // on cancellation.
+        let mut future = Box::pin(VALUE.scope_into(&mut slot, async {
+            VALUE.raw_local_key().borrow_mut().as_mut().unwrap().clear();
+            std::future::pending::<()>().await;
+        }));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+        assert_eq!(slot, Some(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn test_timed_measures_from_first_poll() {
+        use std::time::Duration;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let future = VALUE
+            .scope(1, async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                VALUE.get()
+            })
+            .timed();
+        // The time before the first poll is not included.
+        std::thread::sleep(Duration::from_millis(200));
+        let (elapsed, value, output) = future.await;
+
+        assert_eq!((value, output), (1, 1));
+        assert!(elapsed >= Duration::from_millis(20), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(200), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    #[should_panic = "cannot access a future local value without setting it first"]
+    async fn test_scope_evaluates_future_expression_outside() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        fn make_future() -> impl Future<Output = u64> {
+            let value = VALUE.get();
+            async move { value }
+        }
+
+        let _ = VALUE.scope(1, make_future()).await;
+    }
+
+    #[tokio::test]
+    async fn test_lazy_body() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        fn make_future() -> impl Future<Output = u64> {
+            let value = VALUE.get();
+            async move {
+                tokio::task::yield_now().await;
+                value + VALUE.get()
+            }
+        }
+
+        let future = VALUE.scope(1, future::lazy_body(make_future));
+        // The inner future is not created until the first poll.
+        assert!(VALUE.raw_local_key().borrow().is_none());
+        assert_eq!(future.await, (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_current_eq() {
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        assert!(!VALUE.current_eq(&String::new()));
+        VALUE
+            .scope("value".to_owned(), async {
+                assert!(VALUE.current_eq(&"value".to_owned()));
+                assert!(!VALUE.current_eq(&"other".to_owned()));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_count_polls() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        const YIELDS: u64 = 5;
+        let (polls, value, output) = VALUE
+            .scope(1, async {
+                for _ in 0..YIELDS {
+                    tokio::task::yield_now().await;
+                }
+                VALUE.get()
+            })
+            .count_polls()
+            .await;
+
+        assert_eq!(polls, YIELDS + 1);
+        assert_eq!((value, output), (1, 1));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_count_migrations() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let future = VALUE
+            .scope(1, async {
+                for _ in 0..100 {
+                    tokio::task::yield_now().await;
+                }
+                VALUE.get()
+            })
+            .count_migrations();
+        let (threads, value, output) = tokio::spawn(future).await.unwrap();
+
+        assert!(
+            (1..=4).contains(&threads),
+            "unexpected threads count {threads}"
+        );
+        assert_eq!((value, output), (1, 1));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_scope_limited() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use tokio::sync::Semaphore;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+        static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+        static MAX_ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+        let semaphore = Semaphore::new(2);
+        let futures = (0..5).map(|i| {
+            VALUE.scope_limited(i, &semaphore, async {
+                let active = ACTIVE.fetch_add(1, Ordering::SeqCst) + 1;
+                MAX_ACTIVE.fetch_max(active, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                ACTIVE.fetch_sub(1, Ordering::SeqCst);
+                VALUE.get()
+            })
+        });
+        let outputs = futures_util::future::join_all(futures).await;
+        for (i, (value, output)) in (0..).zip(outputs) {
+            assert_eq!((value, output.unwrap()), (i, i));
+        }
+        assert_eq!(MAX_ACTIVE.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.available_permits(), 2);
+
+        // The permit is released if the scoped future is cancelled.
+        let mut future = Box::pin(VALUE.scope_limited(0, &semaphore, std::future::pending::<()>()));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        assert_eq!(semaphore.available_permits(), 1);
+        drop(future);
+        assert_eq!(semaphore.available_permits(), 2);
+
+        // The value is returned even if the semaphore is closed.
+        semaphore.close();
+        let (value, output) = VALUE.scope_limited(42, &semaphore, async {}).await;
+        assert_eq!(value, 42);
+        assert!(output.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scope_merged() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let merged = || {
+            VALUE.scope_merged(
+                vec![2],
+                async {
+                    tokio::task::yield_now().await;
+                    VALUE.with(Vec::clone)
+                },
+                Extend::extend,
+            )
+        };
+
+        // There is no outer scope, so the value is set as a fresh one.
+        let (value, seen) = merged().await;
+        assert_eq!(value, Some(vec![2]));
+        assert_eq!(seen, vec![2]);
+
+        // The value is merged into the outer one.
+        let (outer, (value, seen)) = VALUE.scope(vec![1], merged()).await;
+        assert_eq!(value, None);
+        assert_eq!(seen, vec![1, 2]);
+        assert_eq!(outer, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_scope_merged_accesses_value_in_merge() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let merged = VALUE.scope_merged(
+            vec![2],
+            async {
+                tokio::task::yield_now().await;
+                VALUE.with(Vec::clone)
+            },
+            |outer, value| {
+                // The outer value is taken out of the future local during the merge.
+                assert_eq!(VALUE.current(), Err(AccessError));
+                outer.extend(value);
+            },
+        );
+        let (outer, (value, seen)) = VALUE.scope(vec![1], merged).await;
+        assert_eq!(value, None);
+        assert_eq!(seen, vec![1, 2]);
+        assert_eq!(outer, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_scope_merged_panic_keeps_outer_value() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        // The inner future panics.
+        let (outer, result) = VALUE
+            .scope_catch(vec![1], async {
+                VALUE
+                    .scope_merged(
+                        vec![2],
+                        async {
+                            tokio::task::yield_now().await;
+                            panic!("inner future panicked");
+                        },
+                        Extend::extend,
+                    )
+                    .await
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(outer, vec![1, 2]);
+
+        // The merge closure panics.
+        let (outer, result) = VALUE
+            .scope_catch(vec![1], async {
+                VALUE
+                    .scope_merged(vec![2], async {}, |outer: &mut Vec<u64>, _| {
+                        outer.push(3);
+                        panic!("merge panicked");
+                    })
+                    .await
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(outer, vec![1, 3]);
+        assert_eq!(VALUE.current(), Err(AccessError));
+    }
 
     #[test]
-    fn test_once_cell_without_future() {
-        static LOCK: FutureOnceCell<RefCell<String>> = FutureOnceCell::new();
-        LOCK.0
-            .local_key()
-            .borrow_mut()
-            .replace(RefCell::new("0".to_owned()));
+    fn test_scope_merged_polled_outside_outer_scope() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
 
-        assert_eq!(LOCK.with(|x| x.borrow().clone()), "0".to_owned());
-        LOCK.with(|x| x.replace("42".to_owned()));
-        assert_eq!(LOCK.with(|x| x.borrow().clone()), "42".to_owned());
+        let mut merged =
+            Box::pin(VALUE.scope_merged(vec![2], tokio::task::yield_now(), Extend::extend));
+        let entered = VALUE.enter(vec![1]);
+        assert!(futures_util::FutureExt::now_or_never(merged.as_mut()).is_none());
+        // The merged value is kept in the outer scope between the polls.
+        assert_eq!(VALUE.current(), Ok(vec![1, 2]));
+        assert_eq!(entered.exit(), vec![1, 2]);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            futures_util::FutureExt::now_or_never(merged.as_mut())
+        }));
+        let message = result.unwrap_err();
+        assert!(message
+            .downcast_ref::<&str>()
+            .is_some_and(|message| message.contains("must be polled inside the outer scope")));
     }
 
+    #[cfg(feature = "stream")]
     #[tokio::test]
-    async fn test_future_once_cell_output() {
+    async fn test_stream_with_scope_generator() {
+        use futures_util::StreamExt as _;
+
         static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
 
-        let (output, ()) = VALUE
-            .scope(Cell::from(0), async {
-                VALUE.with(|x| {
-                    let value = x.get();
-                    x.set(value + 1);
+        // The generator keeps its state across the yield points and is `!Unpin`.
+        let generator = async_stream::stream! {
+            loop {
+                tokio::task::yield_now().await;
+                let value = VALUE.with(|x| {
+                    x.set(x.get() + 1);
+                    x.get()
                 });
+                if value > 3 {
+                    break;
+                }
+                yield value;
+            }
+        };
+        let mut stream = std::pin::pin!(generator.with_scope(&VALUE, Cell::new(0)));
+
+        assert_eq!(stream.next().await, Some(1));
+        // The value is not set between the polls of the stream.
+        assert!(VALUE.raw_local_key().borrow().is_none());
+        assert_eq!(stream.as_mut().collect::<Vec<_>>().await, vec![2, 3]);
+        assert_eq!(stream.as_mut().take_value().unwrap().get(), 4);
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_with_scope_inside_outer_scope() {
+        use futures_util::StreamExt as _;
+
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (outer, items) = VALUE
+            .scope(1, async {
+                let generator = async_stream::stream! {
+                    for _ in 0..2 {
+                        tokio::task::yield_now().await;
+                        yield VALUE.get();
+                    }
+                };
+                let items = generator.with_scope(&VALUE, 2).collect::<Vec<_>>().await;
+                // The outer value is restored after each poll of the stream.
+                (items, VALUE.get())
             })
             .await;
+        assert_eq!(outer, 1);
+        assert_eq!(items, (vec![2, 2], 1));
+    }
 
-        assert_eq!(output.into_inner(), 1);
+    #[tokio::test]
+    async fn test_scope_here_rest_of_function() {
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        async fn handle(name: &str) -> Option<usize> {
+            let value = name.to_owned();
+            scope_here!(VALUE, value;
+                tokio::task::yield_now().await;
+                let first = VALUE.with(|value| value.chars().next())?;
+                Some(usize::from(first.is_uppercase()) + VALUE.with(String::len))
+            )
+        }
+
+        assert_eq!(handle("Alice").await, Some(6));
+        assert_eq!(handle("").await, None);
+        // The value is unset after the function completes.
+        assert!(VALUE.raw_local_key().borrow().is_none());
+    }
+
+    #[test]
+    fn test_scope_thread_affine_panics_on_thread_hop() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut future = Box::pin(VALUE.scope_thread_affine(1, async {
+            tokio::task::yield_now().await;
+            VALUE.get()
+        }));
+        let waker = futures_util::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+
+        // Move the future to another thread, as a multi-threaded executor may do.
+        let panic = std::thread::spawn(move || {
+            let waker = futures_util::task::noop_waker();
+            let _ = future.as_mut().poll(&mut Context::from_waker(&waker));
+        })
+        .join()
+        .unwrap_err();
+        assert!(panic
+            .downcast_ref::<String>()
+            .unwrap()
+            .contains("thread affine scoped future must not be polled on different threads"));
     }
 
+    #[cfg(feature = "tokio")]
     #[tokio::test]
-    async fn test_future_once_cell_discard_value() {
+    async fn test_mirror_task_local() {
+        static VALUE: FutureOnceCell<String> = FutureOnceCell::new();
+
+        tokio::task_local! {
+            static TASK_LOCAL: String;
+        }
+
+        let (value, (cell, task_local)) = VALUE
+            .mirror_task_local(&TASK_LOCAL, "value".to_owned(), async {
+                tokio::task::yield_now().await;
+                (VALUE.with(String::clone), TASK_LOCAL.with(String::clone))
+            })
+            .await;
+
+        assert_eq!(value, "value");
+        assert_eq!((cell.as_str(), task_local.as_str()), ("value", "value"));
+        assert!(TASK_LOCAL.try_with(|_| ()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scope_on_pending() {
+        static VALUE: FutureOnceCell<Vec<u64>> = FutureOnceCell::new();
+
+        let snapshots = RefCell::new(Vec::new());
+        let (value, ()) = VALUE
+            .scope_on_pending(
+                Vec::new(),
+                async {
+                    for i in 0..3 {
+                        VALUE.raw_local_key().borrow_mut().as_mut().unwrap().push(i);
+                        tokio::task::yield_now().await;
+                    }
+                    // This modification is not reported, since the future completes.
+                    VALUE.raw_local_key().borrow_mut().as_mut().unwrap().push(3);
+                },
+                |value| snapshots.borrow_mut().push(value.clone()),
+            )
+            .await;
+
+        assert_eq!(value, vec![0, 1, 2, 3]);
+        assert_eq!(
+            snapshots.into_inner(),
+            vec![vec![0], vec![0, 1], vec![0, 1, 2]]
+        );
+    }
+
+    #[test]
+    fn test_enter_restores_outer_value() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let outer = VALUE.enter(1);
+        {
+            let _inner = VALUE.enter(2);
+            assert_eq!(VALUE.get(), 2);
+        }
+        assert_eq!(VALUE.get(), 1);
+        let inner = VALUE.enter(3);
+        assert_eq!(inner.exit(), 3);
+        assert_eq!(outer.exit(), 1);
+        assert!(VALUE.current().is_err());
+    }
+
+    #[test]
+    #[should_panic = "the future local value has been taken out of the entered scope"]
+    fn test_enter_exit_after_take() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let guard = VALUE.enter(1);
+        VALUE.raw_local_key().borrow_mut().take();
+        let _ = guard.exit();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_spawn_scoped_snapshots_value() {
         static VALUE: FutureOnceCell<Cell<u64>> = FutureOnceCell::new();
 
-        let fut_1 = async {
-            for _ in 0..42 {
-                VALUE.with(|x| {
-                    let value = x.get();
-                    x.set(value + 1);
+        let (value, child) = VALUE
+            .scope(Cell::new(1), async {
+                let handle = VALUE.spawn_scoped(async {
+                    tokio::task::yield_now().await;
+                    VALUE.with(|value| value.set(value.get() + 10));
+                    VALUE.with(Cell::get)
                 });
+                VALUE.with(|value| value.set(2));
+                handle.await.unwrap()
+            })
+            .await;
+
+        // The spawned task works on its own snapshot of the value.
+        assert_eq!(child, 11);
+        assert_eq!(value.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scope_depth_balanced() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let (_, depths) = VALUE
+            .scope(1, async {
+                let outer = VALUE.scope_depth();
+                let (_, inner) = async {
+                    tokio::task::yield_now().await;
+                    let _guard = VALUE.enter(3);
+                    (VALUE.scope_depth(), VALUE.get())
+                }
+                .with_scope_with_policy(&VALUE, 2, OnConflict::Nest)
+                .await;
+                (outer, inner, VALUE.scope_depth())
+            })
+            .await;
+
+        assert_eq!(depths, (1, (3, 3), 1));
+        assert_eq!(VALUE.scope_depth(), 0);
+    }
+
+    #[cfg(all(feature = "tokio", feature = "stream"))]
+    #[tokio::test]
+    async fn test_scope_observed_intermediate_snapshots() {
+        use futures_util::{FutureExt, StreamExt};
+
+        static VALUE: FutureOnceCell<RefCell<Vec<u64>>> = FutureOnceCell::new();
+
+        let (mut snapshots, future) = VALUE.scope_observed(RefCell::default(), async {
+            for i in 0..2 {
+                VALUE.with(|value| value.borrow_mut().push(i));
                 tokio::task::yield_now().await;
             }
+            VALUE.with(|value| value.borrow().len())
+        });
+        let mut future = Box::pin(future);
 
-            VALUE.with(Cell::get)
-        }
-        .with_scope(&VALUE, Cell::new(0))
-        .discard_value();
+        // Each suspension is observed before the next poll.
+        assert!((&mut future).now_or_never().is_none());
+        assert_eq!(snapshots.next().await, Some(RefCell::new(vec![0])));
+        assert!((&mut future).now_or_never().is_none());
+        assert_eq!(snapshots.next().await, Some(RefCell::new(vec![0, 1])));
+        assert_eq!(future.await, 2);
+        assert_eq!(
+            snapshots.collect::<Vec<_>>().await,
+            vec![RefCell::new(vec![0, 1])]
+        );
+    }
 
-        let fut_2 = async { VALUE.with(Cell::get) }
-            .with_scope(&VALUE, Cell::new(15))
-            .discard_value();
+    #[tokio::test]
+    async fn test_scope_retry_independent_attempts() {
+        static TRACES: FutureOnceCell<RefCell<Vec<u64>>> = FutureOnceCell::new();
 
-        assert_eq!(fut_1.await, 42);
-        assert_eq!(fut_2.await, 15);
-        assert_eq!(
-            tokio::spawn(
-                async { VALUE.with(Cell::get) }
-                    .with_scope(&VALUE, Cell::new(115))
-                    .discard_value()
+        let attempts = Cell::new(0);
+        let (traces, output) = TRACES
+            .scope_retry(
+                RefCell::default,
+                || async {
+                    attempts.set(attempts.get() + 1);
+                    TRACES.with(|traces| traces.borrow_mut().push(attempts.get()));
+                    tokio::task::yield_now().await;
+                    // Each attempt starts with the empty traces.
+                    let len = TRACES.with(|traces| traces.borrow().len());
+                    if attempts.get() < 3 {
+                        Err(len)
+                    } else {
+                        Ok(len)
+                    }
+                },
+                5,
             )
-            .await
-            .unwrap(),
-            115
-        );
+            .await;
+
+        assert_eq!(output, Ok(1));
+        assert_eq!(traces.into_inner(), vec![3]);
+
+        let (_, output) = TRACES
+            .scope_retry(RefCell::default, || async { Err::<(), _>("failed") }, 2)
+            .await;
+        assert_eq!(output, Err("failed"));
     }
 }
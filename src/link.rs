@@ -0,0 +1,171 @@
+//! Future local storage which redirects to another cell chosen at runtime.
+
+use std::{fmt::Debug, future::Future};
+
+use crate::{AccessError, FutureOnceCell, ScopedFuture};
+
+/// A future local cell which aliases the value of another [`FutureOnceCell`].
+///
+/// The scope of this cell sets the target cell instead of the value, and the accessors resolve
+/// to the value currently set in the target cell. It allows to build layered context APIs, where
+/// a generic cell proxies to a more specific one chosen at runtime, for example, a "current
+/// tenant" cell, which reads either from the user or from the service account context.
+///
+/// The accessors behave the same as if the target cell is accessed directly, so they panic if
+/// the target cell has no value set, even if this cell is linked.
+///
+/// ```rust
+/// use future_local_storage::{FutureLinkCell, FutureOnceCell};
+///
+/// static USER_TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+/// static SERVICE_TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+/// static TENANT: FutureLinkCell<String> = FutureLinkCell::new();
+///
+/// async fn handle() -> String {
+///     TENANT.get()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (_, tenant) = USER_TENANT
+///         .scope("acme".to_owned(), TENANT.scope_linked(&USER_TENANT, handle()))
+///         .await;
+///     assert_eq!(tenant, "acme");
+///
+///     let (_, tenant) = SERVICE_TENANT
+///         .scope("system".to_owned(), TENANT.scope_linked(&SERVICE_TENANT, handle()))
+///         .await;
+///     assert_eq!(tenant, "system");
+/// }
+/// ```
+pub struct FutureLinkCell<T: Send + 'static>(FutureOnceCell<&'static FutureOnceCell<T>>);
+
+impl<T: Send + 'static> FutureLinkCell<T> {
+    /// Creates an empty future link cell.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(FutureOnceCell::new())
+    }
+
+    /// Acquires a reference to the value of the linked cell.
+    ///
+    /// # Panics
+    ///
+    /// - This method will panic if it's called outside of the [`FutureLinkCell::scope_linked`].
+    ///
+    /// - This method will panic if the linked cell doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.target()
+            .with_expect("the linked future local doesn't have a value set", f)
+    }
+
+    /// Returns a clone of the value of the linked cell.
+    ///
+    /// # Panics
+    ///
+    /// - This method will panic if it's called outside of the [`FutureLinkCell::scope_linked`].
+    ///
+    /// - This method will panic if the linked cell doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn get(&'static self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Returns a clone of the value of the linked cell, or the [`AccessError`] if this cell is
+    /// not linked, or the linked cell doesn't have a value set.
+    #[inline]
+    pub fn current(&'static self) -> Result<T, AccessError>
+    where
+        T: Clone,
+    {
+        self.0.current()?.current()
+    }
+
+    /// Links this cell to the `target` cell for the future `F`.
+    ///
+    /// The target cell is resolved on each access, so the nested scopes of the target cell are
+    /// visible through this cell as well.
+    #[inline]
+    pub fn scope_linked<F>(
+        &'static self,
+        target: &'static FutureOnceCell<T>,
+        future: F,
+    ) -> ScopedFuture<&'static FutureOnceCell<T>, F>
+    where
+        F: Future,
+    {
+        self.0.scope(target, future).discard_value()
+    }
+
+    #[track_caller]
+    fn target(&'static self) -> &'static FutureOnceCell<T> {
+        self.0.with_expect(
+            "cannot access a linked future local outside of its scope",
+            |target| *target,
+        )
+    }
+}
+
+impl<T: Send + 'static> Default for FutureLinkCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> Debug for FutureLinkCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureLinkCell").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{FutureLocalStorage, OnConflict};
+
+    #[tokio::test]
+    async fn test_future_link_cell_redirects_to_target() {
+        static TARGET: FutureOnceCell<u64> = FutureOnceCell::new();
+        static LINK: FutureLinkCell<u64> = FutureLinkCell::new();
+
+        let output = LINK
+            .scope_linked(&TARGET, async {
+                // The target cell is not set yet.
+                let unset = LINK.current();
+                let (_, outer) = TARGET
+                    .scope(1, async {
+                        let (_, inner) = async { LINK.get() }
+                            .with_scope_with_policy(&TARGET, 2, OnConflict::Nest)
+                            .await;
+                        (LINK.get(), inner)
+                    })
+                    .await;
+                (unset, outer)
+            })
+            .await;
+
+        assert_eq!(output, (Err(AccessError), (1, 2)));
+        assert_eq!(LINK.current(), Err(AccessError));
+    }
+
+    #[tokio::test]
+    #[should_panic = "the linked future local doesn't have a value set"]
+    async fn test_future_link_cell_target_unset() {
+        static TARGET: FutureOnceCell<u64> = FutureOnceCell::new();
+        static LINK: FutureLinkCell<u64> = FutureLinkCell::new();
+
+        LINK.scope_linked(&TARGET, async { LINK.get() }).await;
+    }
+}
@@ -0,0 +1,140 @@
+//! Future local storage shared between the parent and child futures.
+
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use parking_lot::Mutex;
+
+use crate::{future::ScopedFutureWithValue, imp::FutureLocalKey, FutureLocalValue, FutureOnceCell};
+
+/// A future local value guarded by a mutex, which can be shared between a future and the child
+/// futures spawned from it.
+///
+/// Unlike the [`FutureOnceCell`], which value belongs to a single future, this cell stores the
+/// value in the [`Arc<Mutex<T>>`], so the child futures created by the [`FutureMutexLock::fork`]
+/// share the same value with the parent one, even if they are spawned as separate tasks. The
+/// value is accessed by locking the mutex with the [`FutureMutexLock::lock`].
+///
+/// ```rust
+/// use future_local_storage::FutureMutexLock;
+///
+/// static EVENTS: FutureMutexLock<Vec<&str>> = FutureMutexLock::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (events, ()) = EVENTS
+///         .scope(Vec::new(), async {
+///             EVENTS.lock(|events| events.push("parent"));
+///             let child = EVENTS.fork(async { EVENTS.lock(|events| events.push("child")) });
+///             tokio::spawn(child).await.unwrap();
+///         })
+///         .await;
+///
+///     assert_eq!(*events.lock(), vec!["parent", "child"]);
+/// }
+/// ```
+pub struct FutureMutexLock<T>(FutureOnceCell<Arc<Mutex<T>>>);
+
+impl<T> FutureMutexLock<T> {
+    /// Creates an empty future mutex lock.
+    #[must_use]
+    pub const fn new() -> Self
+    where
+        T: FutureLocalValue,
+    {
+        Self(FutureOnceCell::new())
+    }
+}
+
+impl<T: FutureLocalValue> Default for FutureMutexLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send + 'static> FutureMutexLock<T> {
+    /// Locks the mutex of the current future local value and calls the given closure with the
+    /// mutable reference to it.
+    ///
+    /// # Panics
+    ///
+    /// - This method will panic if the future local doesn't have a value set.
+    ///
+    /// - The mutex is not reentrant, so calling this method inside the given closure will
+    ///   deadlock.
+    #[inline]
+    #[track_caller]
+    pub fn lock<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let value = self.0.with(Arc::clone);
+        let mut guard = value.lock();
+        f(&mut guard)
+    }
+
+    /// Sets a value `T` as the future-local value for the future `F`.
+    ///
+    /// On completion the scoped future returns the shared value along with the future output.
+    #[inline]
+    pub fn scope<F>(&'static self, value: T, future: F) -> ScopedFutureWithValue<Arc<Mutex<T>>, F>
+    where
+        F: Future,
+    {
+        self.0.scope(Arc::new(Mutex::new(value)), future)
+    }
+
+    /// Shares the current future-local value with the future `F`.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    pub fn fork<F>(&'static self, future: F) -> ScopedFutureWithValue<Arc<Mutex<T>>, F>
+    where
+        F: Future,
+    {
+        self.0.fork(future)
+    }
+}
+
+impl<T: Debug + Send + 'static> Debug for FutureMutexLock<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureMutexLock").field(&self.0).finish()
+    }
+}
+
+impl<T> AsRef<FutureLocalKey<Arc<Mutex<T>>>> for FutureMutexLock<T> {
+    fn as_ref(&self) -> &FutureLocalKey<Arc<Mutex<T>>> {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_future_mutex_lock_concurrent_mutation() {
+        static COUNTER: FutureMutexLock<u64> = FutureMutexLock::new();
+
+        let (counter, ()) = COUNTER
+            .scope(0, async {
+                let child = tokio::spawn(COUNTER.fork(async {
+                    for _ in 0..100 {
+                        COUNTER.lock(|x| *x += 1);
+                        tokio::task::yield_now().await;
+                    }
+                }));
+                for _ in 0..100 {
+                    COUNTER.lock(|x| *x += 1);
+                    tokio::task::yield_now().await;
+                }
+                child.await.unwrap();
+            })
+            .await;
+
+        assert_eq!(*counter.lock(), 200);
+    }
+}
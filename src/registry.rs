@@ -0,0 +1,222 @@
+//! Registry of the named future locals, which are scoped by the values from a map.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{PoisonError, RwLock},
+};
+
+use crate::{future::MapScopedFuture, imp::ScopeEntered, FutureOnceCell, ScopeMapError};
+
+/// A type-erased value of a registered future local, which can be set for the duration of a
+/// closure call.
+pub(crate) trait ErasedSlot: Send {
+    /// Sets the value for the duration of the closure call.
+    fn within(&mut self, f: &mut dyn FnMut());
+
+    /// Consumes the slot, returning the value.
+    fn into_value(self: Box<Self>) -> Box<dyn Any + Send>;
+}
+
+struct TypedSlot<T: Send + 'static> {
+    cell: &'static FutureOnceCell<T>,
+    value: Option<T>,
+}
+
+impl<T: Send + 'static> ErasedSlot for TypedSlot<T> {
+    fn within(&mut self, f: &mut dyn FnMut()) {
+        let value = self
+            .value
+            .take()
+            .expect("future local value has already been taken");
+        let guard = ScopeEntered::enter(self.cell.as_ref(), value);
+        f();
+        self.value = Some(guard.exit());
+    }
+
+    fn into_value(self: Box<Self>) -> Box<dyn Any + Send> {
+        Box::new(
+            self.value
+                .expect("future local value has already been taken"),
+        )
+    }
+}
+
+type MakeSlot =
+    Box<dyn Fn(Box<dyn Any + Send>) -> Option<Box<dyn ErasedSlot>> + Send + Sync + 'static>;
+
+struct Registration {
+    type_name: &'static str,
+    make_slot: MakeSlot,
+}
+
+static REGISTRY: RwLock<Option<HashMap<&'static str, Registration>>> = RwLock::new(None);
+
+/// Registers the future local cell under the given name, so it can be scoped by the
+/// [`scope_from_map`] function.
+///
+/// Registering another cell under the same name replaces the previous registration.
+///
+/// # Panics
+///
+/// Registering a cell inside the [`Drop`] implementation of a value passed to the
+/// [`scope_from_map`] leads to a deadlock or panic.
+pub fn register_named_cell<T>(name: &'static str, cell: &'static FutureOnceCell<T>)
+where
+    T: Send + 'static,
+{
+    let make_slot = move |value: Box<dyn Any + Send>| {
+        let value = *value.downcast::<T>().ok()?;
+        Some(Box::new(TypedSlot {
+            cell,
+            value: Some(value),
+        }) as Box<dyn ErasedSlot>)
+    };
+    REGISTRY
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(HashMap::new)
+        .insert(
+            name,
+            Registration {
+                type_name: std::any::type_name::<T>(),
+                make_slot: Box::new(make_slot),
+            },
+        );
+}
+
+/// Sets the values from the map as the future-local values of the cells registered under the
+/// same names by the [`register_named_cell`] function for the future `F`.
+///
+/// This allows to set up the context in a data-driven way, for example, from the deserialized
+/// request metadata. The names and types of the values are checked before the future is
+/// created, so the error is returned if a name is not registered, or the value type doesn't
+/// match the cell type. On completion the scoped future returns the map of the values along
+/// with the future output. Any iterator of the name and value pairs is accepted as well.
+///
+/// ```rust
+/// use std::{any::Any, collections::HashMap};
+///
+/// use future_local_storage::{register_named_cell, scope_from_map, FutureOnceCell};
+///
+/// static TENANT: FutureOnceCell<String> = FutureOnceCell::new();
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     register_named_cell("tenant", &TENANT);
+///     register_named_cell("request_id", &REQUEST_ID);
+///
+///     let mut values: HashMap<&str, Box<dyn Any + Send>> = HashMap::new();
+///     values.insert("tenant", Box::new("acme".to_owned()));
+///     values.insert("request_id", Box::new(42_u64));
+///
+///     let (_, output) = scope_from_map(values, async {
+///         format!("{}:{}", TENANT.with(String::clone), REQUEST_ID.get())
+///     })
+///     .unwrap()
+///     .await;
+///     assert_eq!(output, "acme:42");
+/// }
+/// ```
+///
+/// # Errors
+///
+/// - [`ScopeMapError::UnknownName`] if a name is not registered.
+///
+/// - [`ScopeMapError::TypeMismatch`] if a value type doesn't match the type of the registered
+///   cell.
+pub fn scope_from_map<'a, I, F>(values: I, future: F) -> Result<MapScopedFuture<F>, ScopeMapError>
+where
+    I: IntoIterator<Item = (&'a str, Box<dyn Any + Send>)>,
+    F: Future,
+{
+    let registry = REGISTRY.read().unwrap_or_else(PoisonError::into_inner);
+    let slots = values
+        .into_iter()
+        .map(|(name, value)| {
+            let (&name, registration) = registry
+                .as_ref()
+                .and_then(|registry| registry.get_key_value(name))
+                .ok_or_else(|| ScopeMapError::UnknownName(name.to_owned()))?;
+            let slot = (registration.make_slot)(value).ok_or(ScopeMapError::TypeMismatch {
+                name,
+                expected: registration.type_name,
+            })?;
+            Ok((name, slot))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(MapScopedFuture::new(slots, future))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_from_map_two_contexts() {
+        static TENANT: FutureOnceCell<RefCell<String>> = FutureOnceCell::new();
+        static USER_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        register_named_cell("test_map_tenant", &TENANT);
+        register_named_cell("test_map_user_id", &USER_ID);
+
+        let mut values: HashMap<&str, Box<dyn Any + Send>> = HashMap::new();
+        values.insert("test_map_tenant", Box::new(RefCell::new("acme".to_owned())));
+        values.insert("test_map_user_id", Box::new(7_u64));
+        let (mut values, output) = scope_from_map(values, async {
+            tokio::task::yield_now().await;
+            TENANT.with(|tenant| tenant.borrow_mut().push_str("-corp"));
+            (TENANT.with(|tenant| tenant.borrow().clone()), USER_ID.get())
+        })
+        .unwrap()
+        .await;
+
+        assert_eq!(output, ("acme-corp".to_owned(), 7));
+        let tenant = values.remove("test_map_tenant").unwrap();
+        assert_eq!(
+            *tenant.downcast_ref::<RefCell<String>>().unwrap().borrow(),
+            "acme-corp"
+        );
+        assert!(TENANT.current().is_err());
+    }
+
+    #[test]
+    fn test_scope_from_map_errors() {
+        static COUNTER: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        register_named_cell("test_map_counter", &COUNTER);
+
+        let mut values: HashMap<&str, Box<dyn Any + Send>> = HashMap::new();
+        values.insert("test_map_unknown", Box::new(1_u64));
+        let Err(error) = scope_from_map(values, async {}) else {
+            panic!("the scope must not be created");
+        };
+        assert_eq!(
+            error,
+            ScopeMapError::UnknownName("test_map_unknown".to_owned())
+        );
+
+        let mut values: HashMap<&str, Box<dyn Any + Send>> = HashMap::new();
+        values.insert("test_map_counter", Box::new("one"));
+        let Err(error) = scope_from_map(values, async {}) else {
+            panic!("the scope must not be created");
+        };
+        assert_eq!(
+            error,
+            ScopeMapError::TypeMismatch {
+                name: "test_map_counter",
+                expected: "u64",
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "future local `test_map_counter` expects a value of type `u64`"
+        );
+    }
+}
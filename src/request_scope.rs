@@ -0,0 +1,254 @@
+//! Tower middleware which scopes the request handlers, e.g. the `tonic` services.
+
+use std::{
+    fmt::{self, Debug},
+    task::{Context, Poll},
+};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{FutureOnceCell, ScopedFuture};
+
+/// A [`Layer`] which sets a value extracted from each request as the future-local value for the
+/// response future of the inner service.
+///
+/// It's intended for the `tonic` servers and the other [`http`] services: the value is
+/// extracted from the request parts, i.e. the headers, which carry the gRPC metadata, and the
+/// extensions, which may be set by a `tonic` interceptor, so the handler code can read it from
+/// the future local deep in the call tree without passing the request around. Only the response
+/// future is scoped, the synchronous part of the inner service `call` runs without the value.
+///
+/// ```rust
+/// use std::{future::Future, pin::Pin};
+///
+/// use future_local_storage::{FutureOnceCell, RequestScopeLayer};
+/// use tower_layer::Layer;
+/// use tower_service::Service;
+///
+/// #[derive(Debug, Clone, Default)]
+/// struct RequestId(u64);
+///
+/// static REQUEST_ID: FutureOnceCell<RequestId> = FutureOnceCell::new();
+///
+/// // A handler reading the future local.
+/// struct Handler;
+///
+/// impl Service<http::Request<()>> for Handler {
+///     type Response = u64;
+///     type Error = std::convert::Infallible;
+///     type Future = Pin<Box<dyn Future<Output = Result<u64, Self::Error>>>>;
+///
+///     fn poll_ready(
+///         &mut self,
+///         _cx: &mut std::task::Context<'_>,
+///     ) -> std::task::Poll<Result<(), Self::Error>> {
+///         std::task::Poll::Ready(Ok(()))
+///     }
+///
+///     fn call(&mut self, _request: http::Request<()>) -> Self::Future {
+///         // The value is only available inside the response future.
+///         Box::pin(async { Ok(REQUEST_ID.with(|id| id.0)) })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut service = RequestScopeLayer::new(&REQUEST_ID, |parts: &http::request::Parts| {
+///         parts
+///             .extensions
+///             .get::<RequestId>()
+///             .cloned()
+///             .unwrap_or_default()
+///     })
+///     .layer(Handler);
+///
+///     let mut request = http::Request::new(());
+///     request.extensions_mut().insert(RequestId(42));
+///     assert_eq!(service.call(request).await, Ok(42));
+/// }
+/// ```
+pub struct RequestScopeLayer<T: Send + 'static, E> {
+    cell: &'static FutureOnceCell<T>,
+    extract: E,
+}
+
+impl<T, E> RequestScopeLayer<T, E>
+where
+    T: Send + 'static,
+    E: Fn(&http::request::Parts) -> T,
+{
+    /// Creates a layer which sets the value returned by the `extract` closure to the given cell.
+    pub fn new(cell: &'static FutureOnceCell<T>, extract: E) -> Self {
+        Self { cell, extract }
+    }
+}
+
+impl<T: Send + 'static, E: Clone> Clone for RequestScopeLayer<T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell,
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static, E> Debug for RequestScopeLayer<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestScopeLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S, T, E> Layer<S> for RequestScopeLayer<T, E>
+where
+    T: Send + 'static,
+    E: Clone,
+{
+    type Service = RequestScopeService<S, T, E>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestScopeService {
+            inner,
+            cell: self.cell,
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+/// A [`Service`] returned by the [`RequestScopeLayer`].
+pub struct RequestScopeService<S, T: Send + 'static, E> {
+    inner: S,
+    cell: &'static FutureOnceCell<T>,
+    extract: E,
+}
+
+impl<S: Clone, T: Send + 'static, E: Clone> Clone for RequestScopeService<S, T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            cell: self.cell,
+            extract: self.extract.clone(),
+        }
+    }
+}
+
+impl<S: Debug, T: Send + 'static, E> Debug for RequestScopeService<S, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestScopeService")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, B, T, E> Service<http::Request<B>> for RequestScopeService<S, T, E>
+where
+    S: Service<http::Request<B>>,
+    T: Send + 'static,
+    E: Fn(&http::request::Parts) -> T,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ScopedFuture<T, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let (parts, body) = request.into_parts();
+        let value = (self.extract)(&parts);
+        let future = self.inner.call(http::Request::from_parts(parts, body));
+        self.cell.scope_out(value, future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, future::Future, pin::Pin};
+
+    use pretty_assertions::assert_eq;
+    use tonic::service::interceptor::InterceptedService;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct RequestId(String);
+
+    static REQUEST_ID: FutureOnceCell<RequestId> = FutureOnceCell::new();
+
+    /// A mock `tonic` handler, which reads the request id from the future local.
+    #[derive(Debug, Clone)]
+    struct Handler;
+
+    impl Service<http::Request<tonic::body::BoxBody>> for Handler {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _request: http::Request<tonic::body::BoxBody>) -> Self::Future {
+            Box::pin(async {
+                tokio::task::yield_now().await;
+                let request_id = REQUEST_ID.with(|id| id.0.clone());
+                let mut response = http::Response::new(tonic::body::empty_body());
+                response
+                    .headers_mut()
+                    .insert("x-request-id", request_id.parse().unwrap());
+                Ok(response)
+            })
+        }
+    }
+
+    fn extract_request_id(parts: &http::request::Parts) -> RequestId {
+        parts
+            .extensions
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // The `tonic` interceptors return a large `tonic::Status` error.
+    #[allow(clippy::result_large_err)]
+    #[tokio::test]
+    async fn test_request_scope_with_tonic_interceptor() {
+        // The interceptor sets the request id from the metadata to the request extensions.
+        let interceptor = |mut request: tonic::Request<()>| {
+            let request_id = request
+                .metadata()
+                .get("x-request-id")
+                .ok_or_else(|| tonic::Status::unauthenticated("no request id"))?
+                .to_str()
+                .map_err(|_| tonic::Status::invalid_argument("invalid request id"))?
+                .to_owned();
+            request.extensions_mut().insert(RequestId(request_id));
+            Ok(request)
+        };
+        let mut service = InterceptedService::new(
+            RequestScopeLayer::new(&REQUEST_ID, extract_request_id).layer(Handler),
+            interceptor,
+        );
+
+        let mut request = http::Request::new(tonic::body::empty_body());
+        request
+            .headers_mut()
+            .insert("x-request-id", "req-42".parse().unwrap());
+        let response = service.call(request).await.unwrap();
+
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+        assert_eq!(REQUEST_ID.current(), Err(crate::AccessError));
+    }
+
+    #[tokio::test]
+    async fn test_request_scope_default_value() {
+        let mut service = RequestScopeLayer::new(&REQUEST_ID, extract_request_id).layer(Handler);
+
+        let response = service
+            .call(http::Request::new(tonic::body::empty_body()))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "");
+    }
+}
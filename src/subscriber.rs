@@ -0,0 +1,166 @@
+//! Global subscribers of the scoped futures lifecycle events.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    PoisonError, RwLock,
+};
+
+use crate::imp::FutureLocalKey;
+
+type Subscriber = Box<dyn Fn(ScopeEvent) + Send + Sync + 'static>;
+
+/// Whether at least one subscriber has been registered, it allows to skip the subscribers lock
+/// if there are no subscribers.
+static HAS_SUBSCRIBERS: AtomicBool = AtomicBool::new(false);
+static SUBSCRIBERS: RwLock<Vec<Subscriber>> = RwLock::new(Vec::new());
+
+/// The kind of a [`ScopeEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeEventKind {
+    /// The future local value has been set before polling the inner future.
+    Enter,
+    /// The future local value has been unset after polling the inner future.
+    Exit,
+    /// The scoped future has been completed.
+    Complete,
+    /// The scoped future has been dropped before completion.
+    Cancel,
+}
+
+/// A lifecycle event of a scoped future, which is passed to the subscribers registered by the
+/// [`register_scope_subscriber`] function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeEvent {
+    scope_id: usize,
+    scope_name: &'static str,
+    kind: ScopeEventKind,
+}
+
+impl ScopeEvent {
+    /// Returns the identifier of the future local, which scoped future emitted this event.
+    ///
+    /// The identifier is unique for each future local during the program execution.
+    #[must_use]
+    pub fn scope_id(&self) -> usize {
+        self.scope_id
+    }
+
+    /// Returns the name of the future local value type.
+    #[must_use]
+    pub fn scope_name(&self) -> &'static str {
+        self.scope_name
+    }
+
+    /// Returns the kind of this event.
+    #[must_use]
+    pub fn kind(&self) -> ScopeEventKind {
+        self.kind
+    }
+}
+
+/// Registers a global subscriber, which is called on each lifecycle event of all scoped futures.
+///
+/// This is intended for the profilers and other instrumentation tools which want to correlate
+/// the execution of the async code with the future local scopes. The subscribers are called
+/// synchronously on the thread which polls or drops the scoped future, so they should be fast.
+/// If no subscribers are registered, emitting an event costs a single atomic load.
+///
+/// # Panics
+///
+/// Registering a subscriber inside a subscriber call leads to a deadlock or panic.
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// use future_local_storage::{register_scope_subscriber, FutureOnceCell, ScopeEventKind};
+///
+/// static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+/// static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     register_scope_subscriber(|event| {
+///         if event.kind() == ScopeEventKind::Complete {
+///             COMPLETED.fetch_add(1, Ordering::Relaxed);
+///         }
+///     });
+///
+///     REQUEST_ID.scope(42, async {}).await;
+///     assert_eq!(COMPLETED.load(Ordering::Relaxed), 1);
+/// }
+/// ```
+pub fn register_scope_subscriber(subscriber: impl Fn(ScopeEvent) + Send + Sync + 'static) {
+    SUBSCRIBERS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(Box::new(subscriber));
+    HAS_SUBSCRIBERS.store(true, Ordering::Release);
+}
+
+/// Emits the event of the given future local to the registered subscribers, if any.
+#[inline]
+pub(crate) fn emit<T>(scope: &'static FutureLocalKey<T>, kind: ScopeEventKind) {
+    if HAS_SUBSCRIBERS.load(Ordering::Acquire) {
+        emit_slow(ScopeEvent {
+            scope_id: std::ptr::from_ref(scope) as usize,
+            scope_name: std::any::type_name::<T>(),
+            kind,
+        });
+    }
+}
+
+#[cold]
+fn emit_slow(event: ScopeEvent) {
+    let subscribers = SUBSCRIBERS.read().unwrap_or_else(PoisonError::into_inner);
+    for subscriber in subscribers.iter() {
+        subscriber(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::FutureOnceCell;
+
+    #[tokio::test]
+    async fn test_scope_subscriber_events() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+        static EVENTS: Mutex<Vec<ScopeEventKind>> = Mutex::new(Vec::new());
+
+        let scope_id = std::ptr::from_ref(VALUE.as_ref()) as usize;
+        // Other tests may run concurrently, so only the events of this future local are recorded.
+        register_scope_subscriber(move |event| {
+            if event.scope_id() == scope_id {
+                assert_eq!(event.scope_name(), "u64");
+                EVENTS.lock().unwrap().push(event.kind());
+            }
+        });
+
+        VALUE
+            .scope(1, async {
+                tokio::task::yield_now().await;
+            })
+            .await;
+        let mut future = Box::pin(VALUE.scope(2, std::future::pending::<()>()));
+        assert!(futures_util::poll!(future.as_mut()).is_pending());
+        drop(future);
+
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            [
+                ScopeEventKind::Enter,
+                ScopeEventKind::Exit,
+                ScopeEventKind::Enter,
+                ScopeEventKind::Exit,
+                ScopeEventKind::Complete,
+                ScopeEventKind::Enter,
+                ScopeEventKind::Exit,
+                ScopeEventKind::Cancel,
+            ]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Concurrent scoped futures which borrow the local data.
+
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{future::FutureScopeJoin, FutureOnceCell};
+
+/// A type-erased scoped future registered by the [`FutureScope::spawn_scoped`].
+pub(crate) type ScopeTask<'env> = Pin<Box<dyn Future<Output = ()> + Send + 'env>>;
+
+/// Creates a scope for the concurrent scoped futures, which may borrow the local data.
+///
+/// This is an async counterpart of the [`std::thread::scope`]: the closure registers the scoped
+/// futures by the [`FutureScope::spawn_scoped`] method, and the returned future polls all of
+/// them concurrently, and completes with the closure result when all of them complete. The
+/// registered futures are not `'static`, they may borrow anything which outlives the scope.
+///
+/// Unlike the real spawning, the futures run within the task which awaits the scope, so the
+/// scope can't outlive the borrowed data even if it's leaked. If the scope is dropped before
+/// completion, the remaining futures are dropped along with their future local values. If one
+/// of the futures panics, the panic is propagated to the awaiting task.
+///
+/// ```rust
+/// use future_local_storage::{future_scope, FutureOnceCell};
+///
+/// static WORKER_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let rows = vec![1, 2, 3, 4];
+///     let (first, second) = future_scope(|s| {
+///         let (left, right) = rows.split_at(2);
+///         (
+///             s.spawn_scoped(&WORKER_ID, 1, async { left.iter().sum::<u64>() * WORKER_ID.get() }),
+///             s.spawn_scoped(&WORKER_ID, 2, async { right.iter().sum::<u64>() * WORKER_ID.get() }),
+///         )
+///     })
+///     .await;
+///
+///     assert_eq!(first.into_output(), (1, 3));
+///     assert_eq!(second.into_output(), (2, 14));
+/// }
+/// ```
+pub fn future_scope<'env, C, R>(f: C) -> FutureScopeJoin<'env, R>
+where
+    C: FnOnce(&mut FutureScope<'env>) -> R,
+{
+    let mut scope = FutureScope { tasks: Vec::new() };
+    let output = f(&mut scope);
+    FutureScopeJoin::new(scope.tasks, output)
+}
+
+/// A scope for the concurrent scoped futures created by the [`future_scope`] function.
+pub struct FutureScope<'env> {
+    tasks: Vec<ScopeTask<'env>>,
+}
+
+impl<'env> FutureScope<'env> {
+    /// Registers the future `F` scoped by the future local `value` to run within this scope.
+    ///
+    /// The returned handle gets the future local value and the future output when the future
+    /// completes.
+    pub fn spawn_scoped<T, F>(
+        &mut self,
+        cell: &'static FutureOnceCell<T>,
+        value: T,
+        future: F,
+    ) -> ScopedTaskHandle<T, F::Output>
+    where
+        T: Send + 'static,
+        F: Future + Send + 'env,
+        F::Output: Send + 'env,
+    {
+        let slot = Arc::new(Mutex::new(None));
+        let handle = ScopedTaskHandle(slot.clone());
+        let future = cell.scope(value, future);
+        self.tasks.push(Box::pin(async move {
+            let output = future.await;
+            *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(output);
+        }));
+        handle
+    }
+}
+
+impl Debug for FutureScope<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FutureScope")
+            .field("tasks", &self.tasks.len())
+            .finish()
+    }
+}
+
+/// A handle of the scoped future registered by the [`FutureScope::spawn_scoped`] method.
+pub struct ScopedTaskHandle<T, O>(Arc<Mutex<Option<(T, O)>>>);
+
+impl<T, O> ScopedTaskHandle<T, O> {
+    /// Returns `true` if the scoped future has completed.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    /// Consumes the handle, returning the future local value and the output of the scoped
+    /// future.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the scoped future has not completed, i.e. if it's called before
+    /// the scope completes.
+    #[must_use]
+    pub fn into_output(self) -> (T, O) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .expect("the scoped task has not been completed")
+    }
+}
+
+impl<T, O> Debug for ScopedTaskHandle<T, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopedTaskHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_future_scope_borrows_local_data() {
+        static TRACES: FutureOnceCell<RefCell<Vec<u64>>> = FutureOnceCell::new();
+
+        let data = vec![1, 2, 3];
+        let mut handles = future_scope(|s| {
+            (0..3)
+                .map(|i| {
+                    let data = &data;
+                    s.spawn_scoped(&TRACES, RefCell::default(), async move {
+                        for item in data {
+                            TRACES.with(|traces| traces.borrow_mut().push(item * 10 + i));
+                            // The futures are interleaved.
+                            tokio::task::yield_now().await;
+                        }
+                        data.len()
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+
+        let (traces, len) = handles.remove(2).into_output();
+        assert_eq!(traces.into_inner(), vec![12, 22, 32]);
+        assert_eq!(len, 3);
+        assert!(handles.iter().all(ScopedTaskHandle::is_finished));
+        // The data is not borrowed anymore.
+        drop(data);
+    }
+
+    #[test]
+    #[should_panic = "the scoped task has not been completed"]
+    fn test_future_scope_handle_before_completion() {
+        static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+        let mut handle = None;
+        // The scope is dropped without being awaited.
+        drop(future_scope(|s| {
+            handle = Some(s.spawn_scoped(&VALUE, 1, async {}));
+        }));
+        let _ = handle.unwrap().into_output();
+    }
+}
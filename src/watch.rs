@@ -0,0 +1,209 @@
+//! Future local storage for shared values observable by the child tasks.
+
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use tokio::sync::watch;
+
+use crate::{FutureOnceCell, ScopedFuture};
+
+/// A future local cell for a shared value, which can be updated by any task of the scope and
+/// observed by the others through a [`watch`] channel.
+///
+/// The scope of this cell holds the sending half of the channel behind an [`Arc`]. The child
+/// scopes created by the [`FutureWatchCell::fork`] capture a clone of this [`Arc`] on creation,
+/// so a task tree spawned from the scope shares the same live value: an update made by the
+/// parent is visible to the children, and vice versa, and the tasks can wait for the changes
+/// via the receiver returned by the [`FutureWatchCell::subscribe`].
+///
+/// ```rust
+/// use future_local_storage::FutureWatchCell;
+///
+/// static CONFIG: FutureWatchCell<u64> = FutureWatchCell::new();
+///
+/// #[tokio::main]
+/// async fn main() {
+///     CONFIG
+///         .scope(1, async {
+///             let child = tokio::spawn(CONFIG.fork(async {
+///                 let mut config = CONFIG.subscribe();
+///                 config.wait_for(|config| *config == 2).await.unwrap();
+///                 CONFIG.get()
+///             }));
+///
+///             CONFIG.set(2);
+///             assert_eq!(child.await.unwrap(), 2);
+///         })
+///         .await;
+/// }
+/// ```
+pub struct FutureWatchCell<T>(FutureOnceCell<Arc<watch::Sender<T>>>);
+
+impl<T: Send + Sync + 'static> FutureWatchCell<T> {
+    /// Creates an empty future watch cell.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(FutureOnceCell::new())
+    }
+
+    /// Acquires a reference to the current value in this future local storage.
+    ///
+    /// The value is locked while the closure runs, so the updates of the value block until it
+    /// returns.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.0.with(|sender| f(&sender.borrow()))
+    }
+
+    /// Returns a clone of the current value in this future local storage.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn get(&'static self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Replaces the shared value, notifying all the subscribers.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn set(&'static self, value: T) {
+        self.0.with(|sender| sender.send_replace(value));
+    }
+
+    /// Modifies the shared value in place, notifying all the subscribers.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn update<F>(&'static self, f: F)
+    where
+        F: FnOnce(&mut T),
+    {
+        self.0.with(|sender| sender.send_modify(f));
+    }
+
+    /// Returns a receiver, which observes the changes of the shared value.
+    ///
+    /// The current value is marked as seen, so the [`watch::Receiver::changed`] waits for the
+    /// next update. The receiver stays valid after the scope ends, but it's closed once all the
+    /// scopes sharing the value are completed.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    #[must_use]
+    pub fn subscribe(&'static self) -> watch::Receiver<T> {
+        self.0.with(|sender| sender.subscribe())
+    }
+
+    /// Sets a value `T` as the shared future-local value for the future `F`.
+    #[inline]
+    pub fn scope<F>(&'static self, value: T, future: F) -> ScopedFuture<Arc<watch::Sender<T>>, F>
+    where
+        F: Future,
+    {
+        let (sender, _) = watch::channel(value);
+        self.0.scope(Arc::new(sender), future).discard_value()
+    }
+
+    /// Shares the value of the current scope with the future `F`.
+    ///
+    /// The value is captured when this method is called, so the returned future may be spawned
+    /// as a separate task, and it observes the updates made by the current scope.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the future local doesn't have a value set.
+    #[inline]
+    #[track_caller]
+    pub fn fork<F>(&'static self, future: F) -> ScopedFuture<Arc<watch::Sender<T>>, F>
+    where
+        F: Future,
+    {
+        self.0.fork(future).discard_value()
+    }
+}
+
+impl<T: Send + Sync + 'static> Default for FutureWatchCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> Debug for FutureWatchCell<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FutureWatchCell").field(&self.0).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_future_watch_cell_child_sees_update() {
+        static VALUE: FutureWatchCell<Vec<u64>> = FutureWatchCell::new();
+
+        let seen = VALUE
+            .scope(vec![1], async {
+                let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+                let child = tokio::spawn(VALUE.fork(async move {
+                    let mut updates = VALUE.subscribe();
+                    let initial = VALUE.get();
+                    ready_tx.send(()).unwrap();
+                    updates.changed().await.unwrap();
+                    // The parent's mid-flight update is visible both via the receiver and the
+                    // future local itself.
+                    let received = updates.borrow_and_update().clone();
+                    (initial, received, VALUE.get())
+                }));
+
+                ready_rx.await.unwrap();
+                VALUE.update(|value| value.push(2));
+                child.await.unwrap()
+            })
+            .await;
+
+        assert_eq!(seen, (vec![1], vec![1, 2], vec![1, 2]));
+    }
+
+    #[tokio::test]
+    async fn test_future_watch_cell_closed_after_scope() {
+        static VALUE: FutureWatchCell<u64> = FutureWatchCell::new();
+
+        let mut updates = VALUE
+            .scope(1, async {
+                let updates = VALUE.subscribe();
+                VALUE.set(2);
+                updates
+            })
+            .await;
+
+        assert_eq!(*updates.borrow_and_update(), 2);
+        assert!(updates.changed().await.is_err());
+    }
+}
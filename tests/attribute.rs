@@ -0,0 +1,55 @@
+#![cfg(feature = "macros")]
+
+use std::cell::RefCell;
+
+use future_local_storage::{future_local, FutureOnceCell};
+use pretty_assertions::assert_eq;
+
+static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+static TRACES: FutureOnceCell<RefCell<Vec<String>>> = FutureOnceCell::new();
+
+#[future_local(REQUEST_ID = request_id, TRACES = RefCell::default())]
+async fn handle_request(request_id: u64) -> Result<String, std::num::ParseIntError> {
+    tokio::task::yield_now().await;
+    TRACES.with(|traces| traces.borrow_mut().push("parse".to_owned()));
+    let parsed = "2".parse::<u64>()?;
+    Ok(format!("{}:{parsed}", REQUEST_ID.get()))
+}
+
+#[future_local(REQUEST_ID = id)]
+async fn describe<T: std::fmt::Display>(id: u64, value: T) -> String {
+    if id == 0 {
+        return "empty".to_owned();
+    }
+    format!("{value}#{}", REQUEST_ID.get())
+}
+
+struct Handler {
+    request_id: u64,
+    name: String,
+}
+
+impl Handler {
+    #[future_local(REQUEST_ID = self.request_id)]
+    async fn handle(&self, suffix: &str) -> String {
+        tokio::task::yield_now().await;
+        format!("{}-{}{suffix}", self.name, REQUEST_ID.get())
+    }
+}
+
+#[tokio::test]
+async fn test_future_local_attribute_free_fn() {
+    assert_eq!(handle_request(1).await.unwrap(), "1:2");
+    assert_eq!(describe(7, "value").await, "value#7");
+    assert_eq!(describe(0, "value").await, "empty");
+}
+
+#[tokio::test]
+async fn test_future_local_attribute_method() {
+    let handler = Handler {
+        request_id: 42,
+        name: "handler".to_owned(),
+    };
+
+    assert_eq!(handler.handle("!").await, "handler-42!");
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "derive")]
+
+use future_local_storage::FutureLocal;
+use pretty_assertions::assert_eq;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Region {
+    Eu,
+    Us,
+}
+
+#[derive(Debug, FutureLocal)]
+struct RequestContext {
+    id: u64,
+    tenant: String,
+    #[future_local(copy)]
+    region: Region,
+}
+
+async fn handle_request() -> String {
+    tokio::task::yield_now().await;
+    let region = RequestContext::region();
+    RequestContext::tenant(|tenant| format!("{}/{tenant}/{region:?}", RequestContext::id()))
+}
+
+#[tokio::test]
+async fn test_derive_future_local() {
+    let (first, second) = futures_util::future::join(
+        RequestContext {
+            id: 1,
+            tenant: "acme".to_owned(),
+            region: Region::Eu,
+        }
+        .scope(handle_request()),
+        RequestContext {
+            id: 2,
+            tenant: "umbrella".to_owned(),
+            region: Region::Us,
+        }
+        .scope(handle_request()),
+    )
+    .await;
+
+    assert_eq!(first.1, "1/acme/Eu");
+    assert_eq!(second.1, "2/umbrella/Us");
+    assert_eq!(second.0.tenant, "umbrella");
+}
@@ -0,0 +1,18 @@
+//! Compile tests for the bounds of the guard-based and spawning APIs.
+
+#[test]
+fn test_borrow_guard_ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/borrow_dropped_before_await.rs");
+    cases.compile_fail("tests/ui/borrow_held_across_await.rs");
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_spawn_scoped_ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/spawn_scoped.rs");
+    cases.compile_fail("tests/ui/spawn_scoped_not_send.rs");
+    cases.compile_fail("tests/ui/spawn_scoped_not_static.rs");
+    cases.compile_fail("tests/ui/spawn_scoped_value_not_clone.rs");
+}
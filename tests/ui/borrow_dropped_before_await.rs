@@ -0,0 +1,13 @@
+use future_local_storage::FutureOnceCell;
+
+static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+#[tokio::main]
+async fn main() {
+    let handle = tokio::spawn(VALUE.scope(42, async {
+        let value = *VALUE.borrow();
+        tokio::task::yield_now().await;
+        value
+    }));
+    assert_eq!(handle.await.unwrap(), (42, 42));
+}
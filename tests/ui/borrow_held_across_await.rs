@@ -0,0 +1,12 @@
+use future_local_storage::FutureOnceCell;
+
+static VALUE: FutureOnceCell<u64> = FutureOnceCell::new();
+
+#[tokio::main]
+async fn main() {
+    tokio::spawn(VALUE.scope(42, async {
+        let value = VALUE.borrow();
+        tokio::task::yield_now().await;
+        *value
+    }));
+}
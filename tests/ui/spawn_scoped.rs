@@ -0,0 +1,18 @@
+use future_local_storage::FutureOnceCell;
+
+static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+#[tokio::main]
+async fn main() {
+    let (_, child) = REQUEST_ID
+        .scope(42, async {
+            let name = "child".to_owned();
+            let handle = REQUEST_ID.spawn_scoped(async move {
+                tokio::task::yield_now().await;
+                (name.len(), REQUEST_ID.get())
+            });
+            handle.await.unwrap()
+        })
+        .await;
+    assert_eq!(child, (5, 42));
+}
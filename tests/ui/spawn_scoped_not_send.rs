@@ -0,0 +1,17 @@
+use std::rc::Rc;
+
+use future_local_storage::FutureOnceCell;
+
+static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+async fn spawn_child() {
+    REQUEST_ID.spawn_scoped(async {
+        let rc = Rc::new(REQUEST_ID.get());
+        tokio::task::yield_now().await;
+        *rc
+    });
+}
+
+fn main() {
+    drop(spawn_child());
+}
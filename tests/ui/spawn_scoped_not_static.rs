@@ -0,0 +1,11 @@
+use future_local_storage::FutureOnceCell;
+
+static REQUEST_ID: FutureOnceCell<u64> = FutureOnceCell::new();
+
+fn spawn_child(name: &str) {
+    REQUEST_ID.spawn_scoped(async move { name.len() });
+}
+
+fn main() {
+    spawn_child("child");
+}
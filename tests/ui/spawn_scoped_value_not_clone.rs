@@ -0,0 +1,13 @@
+use future_local_storage::FutureOnceCell;
+
+struct RequestId(u64);
+
+static REQUEST_ID: FutureOnceCell<RequestId> = FutureOnceCell::new();
+
+fn spawn_child() {
+    REQUEST_ID.spawn_scoped(async { REQUEST_ID.with(|id| id.0) });
+}
+
+fn main() {
+    spawn_child();
+}